@@ -0,0 +1,407 @@
+use std::{
+    collections::VecDeque,
+    sync::{Mutex, PoisonError},
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use thiserror::Error;
+use tokio::sync::Mutex as AsyncMutex;
+use tracing::info;
+
+use super::{Backend, BackendError, Clicks, Link, Metadata, Revision};
+
+/// Milliseconds since epoch of 2024-01-01, the start of snowflake time.
+static SNOWFLAKE_EPOCH: i64 = 1_704_067_200_000;
+static NODE_BITS: u32 = 10;
+static SEQUENCE_BITS: u32 = 12;
+/// Longest wait for the clock to catch up with the last id, a clock further
+/// behind fails allocations.
+const MAX_CLOCK_LAG: Duration = Duration::from_secs(5);
+
+#[derive(Error, Debug)]
+pub enum AllocatorError {
+    #[error("Snowflake node must be below 1024, got {0}")]
+    InvalidNode(u16),
+    #[error("Id block size must be positive")]
+    InvalidBlockSize,
+}
+
+/// Source of ids for new links.
+#[async_trait]
+pub trait IdAllocator {
+    async fn allocate(&self, backend: &(dyn Backend + Send + Sync)) -> Result<u64, BackendError>;
+
+    async fn allocate_many(
+        &self,
+        backend: &(dyn Backend + Send + Sync),
+        count: u64,
+    ) -> Result<Vec<u64>, BackendError> {
+        let mut ids = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            ids.push(self.allocate(backend).await?);
+        }
+        Ok(ids)
+    }
+}
+
+/// Ids taken one by one from the storage counter.
+#[derive(Default)]
+pub struct Counter;
+
+#[async_trait]
+impl IdAllocator for Counter {
+    async fn allocate(&self, backend: &(dyn Backend + Send + Sync)) -> Result<u64, BackendError> {
+        let ids = backend.allocate_ids(1).await?;
+        ids.first()
+            .copied()
+            .ok_or_else(|| BackendError::Internal("Backend allocated no ids".into()))
+    }
+
+    async fn allocate_many(
+        &self,
+        backend: &(dyn Backend + Send + Sync),
+        count: u64,
+    ) -> Result<Vec<u64>, BackendError> {
+        backend.allocate_ids(count).await
+    }
+}
+
+/// Ids taken from the storage counter in blocks of `size`, so most links
+/// are stored without a round trip for the id.
+///
+/// Ids of a block left unused on shutdown are never handed out, and with
+/// several instances ids are no longer ordered by creation time. Links
+/// restored under ids of a taken block fail later stores of those ids with
+/// [`BackendError::AlreadyExists`].
+pub struct Block {
+    size: u64,
+    ids: AsyncMutex<VecDeque<u64>>,
+}
+
+impl Block {
+    pub fn new(size: u64) -> Result<Self, AllocatorError> {
+        if size == 0 {
+            return Err(AllocatorError::InvalidBlockSize);
+        }
+        Ok(Self {
+            size,
+            ids: AsyncMutex::new(VecDeque::new()),
+        })
+    }
+}
+
+#[async_trait]
+impl IdAllocator for Block {
+    async fn allocate(&self, backend: &(dyn Backend + Send + Sync)) -> Result<u64, BackendError> {
+        Ok(self.allocate_many(backend, 1).await?[0])
+    }
+
+    async fn allocate_many(
+        &self,
+        backend: &(dyn Backend + Send + Sync),
+        count: u64,
+    ) -> Result<Vec<u64>, BackendError> {
+        let mut ids = self.ids.lock().await;
+        while (ids.len() as u64) < count {
+            let missing = count - ids.len() as u64;
+            ids.extend(backend.allocate_ids(self.size.max(missing)).await?);
+        }
+        Ok(ids.drain(..count as usize).collect())
+    }
+}
+
+/// Time based ids which need no coordination between instances: 41 bits of
+/// milliseconds since 2024-01-01, 10 bits of node and 12 bits of sequence
+/// within the millisecond.
+///
+/// Every instance must be configured with a distinct node. The first
+/// allocation takes an id from the storage counter, which stores under
+/// snowflake ids raise past them, and continues after its millisecond, so
+/// ids of a previous run are not repeated when the clock went backwards in
+/// between. When a millisecond runs out of sequence ids, or the clock is
+/// behind the last id, allocations wait for the clock and fail when it is
+/// more than [`MAX_CLOCK_LAG`] behind.
+///
+/// Snowflake ids are around 2^60, and the counter stays above them, so
+/// switching back to [`Counter`] or [`Block`] keeps allocating from there.
+/// This can not be undone.
+pub struct Snowflake {
+    node: u64,
+    /// Millisecond and sequence of the last id, none until seeded from
+    /// the storage counter
+    state: Mutex<Option<(u64, u64)>>,
+}
+
+impl Snowflake {
+    pub fn new(node: u16) -> Result<Self, AllocatorError> {
+        if u64::from(node) >= 1 << NODE_BITS {
+            return Err(AllocatorError::InvalidNode(node));
+        }
+        Ok(Self {
+            node: node.into(),
+            state: Mutex::new(None),
+        })
+    }
+
+    /// Start after the millisecond of the next counter id, no sequence of
+    /// that millisecond is left.
+    async fn seed(&self, backend: &(dyn Backend + Send + Sync)) -> Result<(), BackendError> {
+        if self
+            .state
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .is_some()
+        {
+            return Ok(());
+        }
+        let id = Counter.allocate(backend).await?;
+        let millis = id >> (NODE_BITS + SEQUENCE_BITS);
+        self.state
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get_or_insert((millis, (1 << SEQUENCE_BITS) - 1));
+        Ok(())
+    }
+
+    /// Next id, or how long to wait for the clock to reach a millisecond
+    /// with sequence ids left.
+    fn next(&self) -> Result<u64, Duration> {
+        let now = (Utc::now().timestamp_millis() - SNOWFLAKE_EPOCH).max(0) as u64;
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        let (millis, sequence) = state.unwrap_or_default();
+        let (millis, sequence) = if now > millis {
+            (now, 0)
+        } else if now == millis && sequence + 1 < 1 << SEQUENCE_BITS {
+            (millis, sequence + 1)
+        } else {
+            return Err(Duration::from_millis(millis + 1 - now));
+        };
+        *state = Some((millis, sequence));
+        Ok(millis << (NODE_BITS + SEQUENCE_BITS) | self.node << SEQUENCE_BITS | sequence)
+    }
+}
+
+#[async_trait]
+impl IdAllocator for Snowflake {
+    async fn allocate(&self, backend: &(dyn Backend + Send + Sync)) -> Result<u64, BackendError> {
+        self.seed(backend).await?;
+        loop {
+            match self.next() {
+                Ok(id) => return Ok(id),
+                Err(lag) if lag <= MAX_CLOCK_LAG => tokio::time::sleep(lag).await,
+                Err(lag) => {
+                    return Err(BackendError::Internal(
+                        format!(
+                            "Clock is {} ms behind the last snowflake id",
+                            lag.as_millis()
+                        )
+                        .into(),
+                    ))
+                }
+            }
+        }
+    }
+}
+
+/// Decorator storing new links under ids of `allocator` instead of the
+/// storage counter of the wrapped backend.
+pub struct AllocatedBackend<B> {
+    backend: B,
+    allocator: Box<dyn IdAllocator + Send + Sync>,
+}
+
+impl<B> AllocatedBackend<B>
+where
+    B: Backend + Send + Sync,
+{
+    pub fn new<A>(backend: B, allocator: A) -> Self
+    where
+        A: IdAllocator + Send + Sync + 'static,
+    {
+        info!("Initialize id allocator");
+        Self {
+            backend,
+            allocator: Box::new(allocator),
+        }
+    }
+}
+
+#[async_trait]
+impl<B> Backend for AllocatedBackend<B>
+where
+    B: Backend + Send + Sync,
+{
+    async fn store<'a>(&self, url: &'a str) -> Result<u64, BackendError> {
+        let id = self.allocator.allocate(&self.backend).await?;
+        self.backend.store_with_id(id, url).await?;
+        Ok(id)
+    }
+
+    async fn allocate_ids(&self, count: u64) -> Result<Vec<u64>, BackendError> {
+        self.allocator.allocate_many(&self.backend, count).await
+    }
+
+    async fn reserve_until(&self, id: u64) -> Result<(), BackendError> {
+        self.backend.reserve_until(id).await
+    }
+
+    async fn store_with_id<'a>(&self, id: u64, url: &'a str) -> Result<(), BackendError> {
+        self.backend.store_with_id(id, url).await
+    }
+
+    async fn retrive_versioned(&self, id: u64) -> Result<(String, u64), BackendError> {
+        self.backend.retrive_versioned(id).await
+    }
+
+    async fn stat(&self, id: u64, since: Option<DateTime<Utc>>) -> Result<u64, BackendError> {
+        self.backend.stat(id, since).await
+    }
+
+    async fn update_versioned<'a>(
+        &self,
+        id: u64,
+        url: &'a str,
+        expected: Option<u64>,
+    ) -> Result<u64, BackendError> {
+        self.backend.update_versioned(id, url, expected).await
+    }
+
+    async fn delete(&self, id: u64) -> Result<(), BackendError> {
+        self.backend.delete(id).await
+    }
+
+    async fn click(&self, id: u64) -> Result<(), BackendError> {
+        self.backend.click(id).await
+    }
+
+    async fn undelete(&self, id: u64) -> Result<(), BackendError> {
+        self.backend.undelete(id).await
+    }
+
+    async fn purge(&self, before: DateTime<Utc>) -> Result<u64, BackendError> {
+        self.backend.purge(before).await
+    }
+
+    async fn list(
+        &self,
+        after: Option<u64>,
+        limit: usize,
+    ) -> Result<Vec<(u64, String)>, BackendError> {
+        self.backend.list(after, limit).await
+    }
+
+    async fn list_with_tombstones(
+        &self,
+        after: Option<u64>,
+        limit: usize,
+    ) -> Result<Vec<(u64, String)>, BackendError> {
+        self.backend.list_with_tombstones(after, limit).await
+    }
+
+    async fn find<'a>(&self, url: &'a str) -> Result<Vec<u64>, BackendError> {
+        self.backend.find(url).await
+    }
+
+    async fn clicks(&self, id: u64) -> Result<Clicks, BackendError> {
+        self.backend.clicks(id).await
+    }
+
+    async fn restore<'a>(
+        &self,
+        id: u64,
+        link: &'a Link,
+        history: &'a [Revision],
+        clicks: &'a [(DateTime<Utc>, u64)],
+    ) -> Result<(), BackendError> {
+        self.backend.restore(id, link, history, clicks).await
+    }
+
+    async fn info(&self, id: u64) -> Result<Link, BackendError> {
+        self.backend.info(id).await
+    }
+
+    async fn set_metadata<'a>(&self, id: u64, metadata: &'a Metadata) -> Result<(), BackendError> {
+        self.backend.set_metadata(id, metadata).await
+    }
+
+    async fn history(&self, id: u64) -> Result<Vec<Revision>, BackendError> {
+        self.backend.history(id).await
+    }
+
+    async fn store_alias<'a>(&self, alias: &'a str, id: u64) -> Result<(), BackendError> {
+        self.backend.store_alias(alias, id).await
+    }
+
+    async fn store_with_alias<'a>(
+        &self,
+        id: u64,
+        url: &'a str,
+        alias: &'a str,
+    ) -> Result<(), BackendError> {
+        self.backend.store_with_alias(id, url, alias).await
+    }
+
+    async fn resolve_alias<'a>(&self, alias: &'a str) -> Result<u64, BackendError> {
+        self.backend.resolve_alias(alias).await
+    }
+
+    async fn aliases(&self, id: u64) -> Result<Vec<String>, BackendError> {
+        self.backend.aliases(id).await
+    }
+
+    async fn store_code<'a>(&self, id: u64, code: &'a str) -> Result<String, BackendError> {
+        self.backend.store_code(id, code).await
+    }
+
+    async fn resolve_code<'a>(&self, code: &'a str) -> Result<u64, BackendError> {
+        self.backend.resolve_code(code).await
+    }
+
+    async fn code(&self, id: u64) -> Result<String, BackendError> {
+        self.backend.code(id).await
+    }
+
+    async fn store_many<'a>(
+        &self,
+        urls: &'a [String],
+    ) -> Result<Vec<Result<u64, BackendError>>, BackendError> {
+        let ids = self
+            .allocator
+            .allocate_many(&self.backend, urls.len() as u64)
+            .await?;
+        let links = ids
+            .iter()
+            .copied()
+            .zip(urls.iter().map(String::as_str))
+            .collect::<Vec<_>>();
+        let results = self.backend.store_many_with_ids(&links).await?;
+        Ok(ids
+            .into_iter()
+            .zip(results)
+            .map(|(id, result)| result.map(|_| id))
+            .collect())
+    }
+
+    async fn retrive_many<'a>(
+        &self,
+        ids: &'a [u64],
+    ) -> Result<Vec<Result<String, BackendError>>, BackendError> {
+        self.backend.retrive_many(ids).await
+    }
+
+    async fn delete_many<'a>(
+        &self,
+        ids: &'a [u64],
+    ) -> Result<Vec<Result<(), BackendError>>, BackendError> {
+        self.backend.delete_many(ids).await
+    }
+
+    async fn store_many_with_ids<'a>(
+        &self,
+        links: &'a [(u64, &'a str)],
+    ) -> Result<Vec<Result<(), BackendError>>, BackendError> {
+        self.backend.store_many_with_ids(links).await
+    }
+}
@@ -0,0 +1,274 @@
+use std::{
+    num::NonZeroUsize,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use lru::LruCache;
+use tokio::sync::{Mutex, Semaphore};
+use tracing::{info, warn};
+
+use super::{Backend, BackendError, Clicks, Link, Metadata, Revision};
+
+/// Clicks of cache hits recorded in background at the same time. Hits past
+/// the limit record their click before answering.
+const MAX_PENDING_CLICKS: usize = 1024;
+
+/// Read-through cache of destinations in front of any backend.
+///
+/// Cache hits skip the lookup in the wrapped backend, the click is recorded
+/// in background with [`Backend::click`], which every bundled backend
+/// implements without reading the destination. Entries are dropped on `update` and
+/// `delete` and expire after `ttl`.
+///
+/// Every invalidation bumps an epoch under the cache lock. A miss only fills
+/// the cache when the epoch did not move while the wrapped backend was read,
+/// so a lookup racing an update or delete never caches the old destination.
+pub struct CachedBackend<B> {
+    backend: Arc<B>,
+    cache: Mutex<LruCache<u64, (String, u64, Instant)>>,
+    epoch: AtomicU64,
+    pending_clicks: Arc<Semaphore>,
+    ttl: Duration,
+}
+
+impl<B> CachedBackend<B>
+where
+    B: Backend + Send + Sync + 'static,
+{
+    pub fn new(backend: B, capacity: NonZeroUsize, ttl: Duration) -> Self {
+        info!("Initialize backend cache");
+        Self {
+            backend: Arc::new(backend),
+            cache: Mutex::new(LruCache::new(capacity)),
+            epoch: AtomicU64::new(0),
+            pending_clicks: Arc::new(Semaphore::new(MAX_PENDING_CLICKS)),
+            ttl,
+        }
+    }
+
+    async fn cached(&self, id: u64) -> Option<(String, u64)> {
+        let mut cache = self.cache.lock().await;
+        match cache.get(&id) {
+            Some((url, version, expiration)) if *expiration > Instant::now() => {
+                Some((url.clone(), *version))
+            }
+            Some(_) => {
+                cache.pop(&id);
+                None
+            }
+            None => None,
+        }
+    }
+
+    async fn invalidate(&self, id: u64) {
+        let mut cache = self.cache.lock().await;
+        cache.pop(&id);
+        self.epoch.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+#[async_trait]
+impl<B> Backend for CachedBackend<B>
+where
+    B: Backend + Send + Sync + 'static,
+{
+    async fn store<'a>(&self, url: &'a str) -> Result<u64, BackendError> {
+        self.backend.store(url).await
+    }
+
+    async fn allocate_ids(&self, count: u64) -> Result<Vec<u64>, BackendError> {
+        self.backend.allocate_ids(count).await
+    }
+
+    async fn reserve_until(&self, id: u64) -> Result<(), BackendError> {
+        self.backend.reserve_until(id).await
+    }
+
+    async fn store_with_id<'a>(&self, id: u64, url: &'a str) -> Result<(), BackendError> {
+        self.backend.store_with_id(id, url).await
+    }
+
+    async fn store_many_with_ids<'a>(
+        &self,
+        links: &'a [(u64, &'a str)],
+    ) -> Result<Vec<Result<(), BackendError>>, BackendError> {
+        self.backend.store_many_with_ids(links).await
+    }
+
+    async fn retrive_versioned(&self, id: u64) -> Result<(String, u64), BackendError> {
+        if let Some(link) = self.cached(id).await {
+            match self.pending_clicks.clone().try_acquire_owned() {
+                Ok(permit) => {
+                    let backend = self.backend.clone();
+                    tokio::spawn(async move {
+                        if let Err(error) = backend.click(id).await {
+                            warn!("Unable to record click for {}: {}", id, error);
+                        }
+                        drop(permit);
+                    });
+                }
+                Err(_) => {
+                    if let Err(error) = self.backend.click(id).await {
+                        warn!("Unable to record click for {}: {}", id, error);
+                    }
+                }
+            }
+            return Ok(link);
+        }
+        let epoch = self.epoch.load(Ordering::SeqCst);
+        let (url, version) = self.backend.retrive_versioned(id).await?;
+        let mut cache = self.cache.lock().await;
+        if self.epoch.load(Ordering::SeqCst) == epoch {
+            cache.put(id, (url.clone(), version, Instant::now() + self.ttl));
+        }
+        Ok((url, version))
+    }
+
+    async fn stat(&self, id: u64, since: Option<DateTime<Utc>>) -> Result<u64, BackendError> {
+        self.backend.stat(id, since).await
+    }
+
+    async fn update_versioned<'a>(
+        &self,
+        id: u64,
+        url: &'a str,
+        expected: Option<u64>,
+    ) -> Result<u64, BackendError> {
+        let result = self.backend.update_versioned(id, url, expected).await;
+        self.invalidate(id).await;
+        result
+    }
+
+    async fn delete(&self, id: u64) -> Result<(), BackendError> {
+        let result = self.backend.delete(id).await;
+        self.invalidate(id).await;
+        result
+    }
+
+    async fn click(&self, id: u64) -> Result<(), BackendError> {
+        self.backend.click(id).await
+    }
+
+    async fn list(
+        &self,
+        after: Option<u64>,
+        limit: usize,
+    ) -> Result<Vec<(u64, String)>, BackendError> {
+        self.backend.list(after, limit).await
+    }
+
+    async fn list_with_tombstones(
+        &self,
+        after: Option<u64>,
+        limit: usize,
+    ) -> Result<Vec<(u64, String)>, BackendError> {
+        self.backend.list_with_tombstones(after, limit).await
+    }
+
+    async fn clicks(&self, id: u64) -> Result<Clicks, BackendError> {
+        self.backend.clicks(id).await
+    }
+
+    async fn restore<'a>(
+        &self,
+        id: u64,
+        link: &'a Link,
+        history: &'a [Revision],
+        clicks: &'a [(DateTime<Utc>, u64)],
+    ) -> Result<(), BackendError> {
+        let result = self.backend.restore(id, link, history, clicks).await;
+        self.invalidate(id).await;
+        result
+    }
+
+    async fn store_many<'a>(
+        &self,
+        urls: &'a [String],
+    ) -> Result<Vec<Result<u64, BackendError>>, BackendError> {
+        self.backend.store_many(urls).await
+    }
+
+    /// Batches bypass the cache.
+    async fn retrive_many<'a>(
+        &self,
+        ids: &'a [u64],
+    ) -> Result<Vec<Result<String, BackendError>>, BackendError> {
+        self.backend.retrive_many(ids).await
+    }
+
+    async fn delete_many<'a>(
+        &self,
+        ids: &'a [u64],
+    ) -> Result<Vec<Result<(), BackendError>>, BackendError> {
+        let results = self.backend.delete_many(ids).await;
+        let mut cache = self.cache.lock().await;
+        for id in ids {
+            cache.pop(id);
+        }
+        self.epoch.fetch_add(1, Ordering::SeqCst);
+        results
+    }
+
+    async fn undelete(&self, id: u64) -> Result<(), BackendError> {
+        self.backend.undelete(id).await
+    }
+
+    async fn purge(&self, before: DateTime<Utc>) -> Result<u64, BackendError> {
+        self.backend.purge(before).await
+    }
+
+    async fn find<'a>(&self, url: &'a str) -> Result<Vec<u64>, BackendError> {
+        self.backend.find(url).await
+    }
+
+    async fn history(&self, id: u64) -> Result<Vec<Revision>, BackendError> {
+        self.backend.history(id).await
+    }
+
+    async fn store_alias<'a>(&self, alias: &'a str, id: u64) -> Result<(), BackendError> {
+        self.backend.store_alias(alias, id).await
+    }
+
+    async fn store_with_alias<'a>(
+        &self,
+        id: u64,
+        url: &'a str,
+        alias: &'a str,
+    ) -> Result<(), BackendError> {
+        self.backend.store_with_alias(id, url, alias).await
+    }
+
+    async fn resolve_alias<'a>(&self, alias: &'a str) -> Result<u64, BackendError> {
+        self.backend.resolve_alias(alias).await
+    }
+
+    async fn aliases(&self, id: u64) -> Result<Vec<String>, BackendError> {
+        self.backend.aliases(id).await
+    }
+
+    async fn store_code<'a>(&self, id: u64, code: &'a str) -> Result<String, BackendError> {
+        self.backend.store_code(id, code).await
+    }
+
+    async fn resolve_code<'a>(&self, code: &'a str) -> Result<u64, BackendError> {
+        self.backend.resolve_code(code).await
+    }
+
+    async fn code(&self, id: u64) -> Result<String, BackendError> {
+        self.backend.code(id).await
+    }
+
+    async fn info(&self, id: u64) -> Result<Link, BackendError> {
+        self.backend.info(id).await
+    }
+
+    async fn set_metadata<'a>(&self, id: u64, metadata: &'a Metadata) -> Result<(), BackendError> {
+        self.backend.set_metadata(id, metadata).await
+    }
+}
@@ -0,0 +1,301 @@
+use std::{collections::HashMap, time::Duration};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use rand::random;
+use thiserror::Error;
+use tokio::time::sleep;
+use tracing::{info, warn};
+
+use super::{Backend, BackendError, Clicks, Link, Metadata, Revision};
+
+#[derive(Error, Debug)]
+pub enum ChaosError {
+    #[error("Injected {0:?} failure")]
+    Failure(Operation),
+    #[error("Injected {0:?} timeout")]
+    Timeout(Operation),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Operation {
+    Store,
+    Retrive,
+    Stat,
+    Update,
+    Delete,
+    Click,
+    List,
+    Clicks,
+    Restore,
+    Info,
+    SetMetadata,
+    Undelete,
+    Purge,
+    History,
+    Find,
+    Allocate,
+    Alias,
+    Code,
+}
+
+/// Faults injected into a single operation. Rates are probabilities in
+/// `0.0..=1.0`, rolled independently on every call.
+#[derive(Debug, Clone, Default)]
+pub struct Fault {
+    /// Fail with [`ChaosError::Failure`]
+    pub failure_rate: f64,
+    /// Hang for the backend timeout and fail with [`ChaosError::Timeout`]
+    pub timeout_rate: f64,
+    /// Delay the call by `latency`
+    pub latency_rate: f64,
+    pub latency: Duration,
+}
+
+/// Decorator making the wrapped backend misbehave, for resilience testing.
+pub struct ChaosBackend<B> {
+    backend: B,
+    faults: HashMap<Operation, Fault>,
+    timeout: Duration,
+}
+
+impl<B> ChaosBackend<B>
+where
+    B: Backend + Send + Sync,
+{
+    pub fn new(backend: B) -> Self {
+        warn!("Initialize chaos backend, storage faults will be injected");
+        Self {
+            backend,
+            faults: HashMap::new(),
+            timeout: Duration::from_secs(30),
+        }
+    }
+
+    pub fn with_fault(mut self, operation: Operation, fault: Fault) -> Self {
+        self.faults.insert(operation, fault);
+        self
+    }
+
+    /// How long an injected timeout hangs before failing.
+    pub fn with_timeout(self, timeout: Duration) -> Self {
+        Self { timeout, ..self }
+    }
+
+    async fn inject(&self, operation: Operation) -> Result<(), BackendError> {
+        let Some(fault) = self.faults.get(&operation) else {
+            return Ok(());
+        };
+        if random::<f64>() < fault.latency_rate {
+            info!("Inject {:?} latency {:?}", operation, fault.latency);
+            sleep(fault.latency).await;
+        }
+        if random::<f64>() < fault.timeout_rate {
+            info!("Inject {:?} timeout", operation);
+            sleep(self.timeout).await;
+            return Err(BackendError::Internal(Box::new(ChaosError::Timeout(
+                operation,
+            ))));
+        }
+        if random::<f64>() < fault.failure_rate {
+            info!("Inject {:?} failure", operation);
+            return Err(BackendError::Internal(Box::new(ChaosError::Failure(
+                operation,
+            ))));
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl<B> Backend for ChaosBackend<B>
+where
+    B: Backend + Send + Sync,
+{
+    async fn store<'a>(&self, url: &'a str) -> Result<u64, BackendError> {
+        self.inject(Operation::Store).await?;
+        self.backend.store(url).await
+    }
+
+    async fn allocate_ids(&self, count: u64) -> Result<Vec<u64>, BackendError> {
+        self.inject(Operation::Allocate).await?;
+        self.backend.allocate_ids(count).await
+    }
+
+    async fn reserve_until(&self, id: u64) -> Result<(), BackendError> {
+        self.inject(Operation::Allocate).await?;
+        self.backend.reserve_until(id).await
+    }
+
+    async fn store_with_id<'a>(&self, id: u64, url: &'a str) -> Result<(), BackendError> {
+        self.inject(Operation::Store).await?;
+        self.backend.store_with_id(id, url).await
+    }
+
+    async fn store_many_with_ids<'a>(
+        &self,
+        links: &'a [(u64, &'a str)],
+    ) -> Result<Vec<Result<(), BackendError>>, BackendError> {
+        self.inject(Operation::Store).await?;
+        self.backend.store_many_with_ids(links).await
+    }
+
+    async fn retrive_versioned(&self, id: u64) -> Result<(String, u64), BackendError> {
+        self.inject(Operation::Retrive).await?;
+        self.backend.retrive_versioned(id).await
+    }
+
+    async fn stat(&self, id: u64, since: Option<DateTime<Utc>>) -> Result<u64, BackendError> {
+        self.inject(Operation::Stat).await?;
+        self.backend.stat(id, since).await
+    }
+
+    async fn update_versioned<'a>(
+        &self,
+        id: u64,
+        url: &'a str,
+        expected: Option<u64>,
+    ) -> Result<u64, BackendError> {
+        self.inject(Operation::Update).await?;
+        self.backend.update_versioned(id, url, expected).await
+    }
+
+    async fn delete(&self, id: u64) -> Result<(), BackendError> {
+        self.inject(Operation::Delete).await?;
+        self.backend.delete(id).await
+    }
+
+    async fn click(&self, id: u64) -> Result<(), BackendError> {
+        self.inject(Operation::Click).await?;
+        self.backend.click(id).await
+    }
+
+    async fn list(
+        &self,
+        after: Option<u64>,
+        limit: usize,
+    ) -> Result<Vec<(u64, String)>, BackendError> {
+        self.inject(Operation::List).await?;
+        self.backend.list(after, limit).await
+    }
+
+    async fn list_with_tombstones(
+        &self,
+        after: Option<u64>,
+        limit: usize,
+    ) -> Result<Vec<(u64, String)>, BackendError> {
+        self.inject(Operation::List).await?;
+        self.backend.list_with_tombstones(after, limit).await
+    }
+
+    async fn clicks(&self, id: u64) -> Result<Clicks, BackendError> {
+        self.inject(Operation::Clicks).await?;
+        self.backend.clicks(id).await
+    }
+
+    async fn restore<'a>(
+        &self,
+        id: u64,
+        link: &'a Link,
+        history: &'a [Revision],
+        clicks: &'a [(DateTime<Utc>, u64)],
+    ) -> Result<(), BackendError> {
+        self.inject(Operation::Restore).await?;
+        self.backend.restore(id, link, history, clicks).await
+    }
+
+    async fn store_many<'a>(
+        &self,
+        urls: &'a [String],
+    ) -> Result<Vec<Result<u64, BackendError>>, BackendError> {
+        self.inject(Operation::Store).await?;
+        self.backend.store_many(urls).await
+    }
+
+    async fn retrive_many<'a>(
+        &self,
+        ids: &'a [u64],
+    ) -> Result<Vec<Result<String, BackendError>>, BackendError> {
+        self.inject(Operation::Retrive).await?;
+        self.backend.retrive_many(ids).await
+    }
+
+    async fn delete_many<'a>(
+        &self,
+        ids: &'a [u64],
+    ) -> Result<Vec<Result<(), BackendError>>, BackendError> {
+        self.inject(Operation::Delete).await?;
+        self.backend.delete_many(ids).await
+    }
+
+    async fn undelete(&self, id: u64) -> Result<(), BackendError> {
+        self.inject(Operation::Undelete).await?;
+        self.backend.undelete(id).await
+    }
+
+    async fn purge(&self, before: DateTime<Utc>) -> Result<u64, BackendError> {
+        self.inject(Operation::Purge).await?;
+        self.backend.purge(before).await
+    }
+
+    async fn find<'a>(&self, url: &'a str) -> Result<Vec<u64>, BackendError> {
+        self.inject(Operation::Find).await?;
+        self.backend.find(url).await
+    }
+
+    async fn history(&self, id: u64) -> Result<Vec<Revision>, BackendError> {
+        self.inject(Operation::History).await?;
+        self.backend.history(id).await
+    }
+
+    async fn store_alias<'a>(&self, alias: &'a str, id: u64) -> Result<(), BackendError> {
+        self.inject(Operation::Alias).await?;
+        self.backend.store_alias(alias, id).await
+    }
+
+    async fn store_with_alias<'a>(
+        &self,
+        id: u64,
+        url: &'a str,
+        alias: &'a str,
+    ) -> Result<(), BackendError> {
+        self.inject(Operation::Store).await?;
+        self.inject(Operation::Alias).await?;
+        self.backend.store_with_alias(id, url, alias).await
+    }
+
+    async fn resolve_alias<'a>(&self, alias: &'a str) -> Result<u64, BackendError> {
+        self.inject(Operation::Alias).await?;
+        self.backend.resolve_alias(alias).await
+    }
+
+    async fn aliases(&self, id: u64) -> Result<Vec<String>, BackendError> {
+        self.inject(Operation::Alias).await?;
+        self.backend.aliases(id).await
+    }
+
+    async fn store_code<'a>(&self, id: u64, code: &'a str) -> Result<String, BackendError> {
+        self.inject(Operation::Code).await?;
+        self.backend.store_code(id, code).await
+    }
+
+    async fn resolve_code<'a>(&self, code: &'a str) -> Result<u64, BackendError> {
+        self.inject(Operation::Code).await?;
+        self.backend.resolve_code(code).await
+    }
+
+    async fn code(&self, id: u64) -> Result<String, BackendError> {
+        self.inject(Operation::Code).await?;
+        self.backend.code(id).await
+    }
+
+    async fn info(&self, id: u64) -> Result<Link, BackendError> {
+        self.inject(Operation::Info).await?;
+        self.backend.info(id).await
+    }
+
+    async fn set_metadata<'a>(&self, id: u64, metadata: &'a Metadata) -> Result<(), BackendError> {
+        self.inject(Operation::SetMetadata).await?;
+        self.backend.set_metadata(id, metadata).await
+    }
+}
@@ -0,0 +1,856 @@
+//! Behaviour every [`Backend`] implementation must share.
+//!
+//! Each check takes a freshly created backend and panics on violation.
+//! Use [`backend_conformance!`](crate::backend_conformance) to generate one
+//! test per check:
+//!
+//! ```ignore
+//! mod memory {
+//!     shortland::backend_conformance!(async { InMemoryBackend::new() });
+//! }
+//! ```
+//!
+//! Backends may share storage between tests, so checks only rely on links
+//! they created themselves.
+
+use chrono::{DateTime, Duration, Utc};
+use uuid::Uuid;
+
+use super::{link::now, Backend, BackendError, Link, Metadata, Revision};
+
+/// Id which is never allocated during tests, yet fits every storage.
+pub const MISSING_ID: u64 = i64::MAX as u64;
+
+/// Generate a `#[tokio::test]` per conformance check.
+///
+/// `$factory` is an expression evaluated in every test, returning a future
+/// of the backend. An attribute before it (e.g. `#[ignore]`) is applied to
+/// every generated test.
+#[macro_export]
+macro_rules! backend_conformance {
+    (@test [$($attr:tt)*] $factory:expr, $check:ident) => {
+        #[tokio::test]
+        $($attr)*
+        async fn $check() {
+            let backend = $factory.await;
+            $crate::backend::conformance::$check(&backend).await;
+        }
+    };
+    (@tests $attrs:tt $factory:expr) => {
+        $crate::backend_conformance!(
+            @tests $attrs $factory;
+            store_and_retrive,
+            store_allocates_unique_ids,
+            retrive_missing,
+            update,
+            update_missing,
+            update_versioned,
+            update_version_mismatch,
+            delete,
+            delete_missing,
+            undelete,
+            undelete_missing,
+            purge,
+            stat_counts_clicks,
+            stat_without_clicks,
+            stat_window,
+            stat_missing,
+            click,
+            click_missing,
+            list,
+            list_skips_tombstones,
+            list_with_tombstones,
+            clicks,
+            clicks_missing,
+            restore,
+            restore_overwrites,
+            store_many,
+            retrive_many,
+            delete_many,
+            info,
+            info_missing,
+            set_metadata,
+            set_metadata_missing,
+            history,
+            history_missing,
+            history_after_restore,
+            restore_history,
+            rollback,
+            rollback_unknown_version,
+            find,
+            find_after_update,
+            find_after_restore,
+            allocate_ids,
+            reserve_until,
+            store_with_id,
+            store_with_taken_id,
+            store_many_with_ids,
+            alias,
+            alias_taken,
+            alias_missing_link,
+            resolve_missing_alias,
+            alias_purged,
+            store_with_alias,
+            store_with_alias_taken,
+            code,
+            code_taken,
+            code_missing,
+            code_purged
+        );
+    };
+    (@tests $attrs:tt $factory:expr; $($check:ident),*) => {
+        $($crate::backend_conformance!(@test $attrs $factory, $check);)*
+    };
+    (#[$meta:meta] $factory:expr) => {
+        $crate::backend_conformance!(@tests [#[$meta]] $factory);
+    };
+    ($factory:expr) => {
+        $crate::backend_conformance!(@tests [] $factory);
+    };
+}
+
+fn assert_not_found<T: std::fmt::Debug>(result: Result<T, BackendError>) {
+    match result {
+        Err(BackendError::NotFound) => {}
+        other => panic!("Expected BackendError::NotFound, got {:?}", other),
+    }
+}
+
+fn assert_already_exists<T: std::fmt::Debug>(result: Result<T, BackendError>) {
+    match result {
+        Err(BackendError::AlreadyExists) => {}
+        other => panic!("Expected BackendError::AlreadyExists, got {:?}", other),
+    }
+}
+
+fn assert_deleted<T: std::fmt::Debug>(result: Result<T, BackendError>) {
+    match result {
+        Err(BackendError::Deleted) => {}
+        other => panic!("Expected BackendError::Deleted, got {:?}", other),
+    }
+}
+
+pub async fn store_and_retrive<B: Backend + Sync>(backend: &B) {
+    let id = backend.store("http://example.com/").await.unwrap();
+    assert_eq!(backend.retrive(id).await.unwrap(), "http://example.com/");
+}
+
+pub async fn store_allocates_unique_ids<B: Backend + Sync>(backend: &B) {
+    let first = backend.store("http://example.com/").await.unwrap();
+    let second = backend.store("http://example.com/").await.unwrap();
+    assert_ne!(first, second);
+    assert_eq!(backend.retrive(first).await.unwrap(), "http://example.com/");
+    assert_eq!(
+        backend.retrive(second).await.unwrap(),
+        "http://example.com/"
+    );
+}
+
+pub async fn retrive_missing<B: Backend + Sync>(backend: &B) {
+    assert_not_found(backend.retrive(MISSING_ID).await);
+}
+
+pub async fn update<B: Backend + Sync>(backend: &B) {
+    let id = backend.store("http://example.com/").await.unwrap();
+    backend.update(id, "http://example.org/").await.unwrap();
+    assert_eq!(backend.retrive(id).await.unwrap(), "http://example.org/");
+}
+
+pub async fn update_missing<B: Backend + Sync>(backend: &B) {
+    assert_not_found(backend.update(MISSING_ID, "http://example.org/").await);
+}
+
+pub async fn update_versioned<B: Backend + Sync>(backend: &B) {
+    let id = backend.store("http://example.com/").await.unwrap();
+    assert_eq!(backend.retrive_versioned(id).await.unwrap().1, 1);
+    let version = backend
+        .update_versioned(id, "http://example.org/", Some(1))
+        .await
+        .unwrap();
+    assert_eq!(version, 2);
+    assert_eq!(
+        backend
+            .update_versioned(id, "http://example.net/", None)
+            .await
+            .unwrap(),
+        3
+    );
+    assert_eq!(
+        backend.retrive_versioned(id).await.unwrap(),
+        ("http://example.net/".to_owned(), 3)
+    );
+}
+
+pub async fn update_version_mismatch<B: Backend + Sync>(backend: &B) {
+    let id = backend.store("http://example.com/").await.unwrap();
+    backend.update(id, "http://example.org/").await.unwrap();
+    match backend
+        .update_versioned(id, "http://example.net/", Some(1))
+        .await
+    {
+        Err(BackendError::VersionMismatch) => {}
+        other => panic!("Expected BackendError::VersionMismatch, got {:?}", other),
+    }
+    assert_eq!(backend.retrive(id).await.unwrap(), "http://example.org/");
+    assert_not_found(
+        backend
+            .update_versioned(MISSING_ID, "http://example.net/", Some(1))
+            .await,
+    );
+}
+
+pub async fn delete<B: Backend + Sync>(backend: &B) {
+    let id = backend.store("http://example.com/").await.unwrap();
+    backend.retrive(id).await.unwrap();
+    backend.delete(id).await.unwrap();
+    assert_deleted(backend.retrive(id).await);
+    assert_deleted(backend.click(id).await);
+    assert_deleted(backend.update(id, "http://example.org/").await);
+    assert_deleted(backend.set_metadata(id, &Metadata::default()).await);
+    assert_deleted(backend.delete(id).await);
+    assert_eq!(backend.stat(id, None).await.unwrap(), 1);
+    let link = backend.info(id).await.unwrap();
+    assert_eq!(link.url, "http://example.com/");
+    assert!(link.deleted_at.is_some());
+}
+
+pub async fn delete_missing<B: Backend + Sync>(backend: &B) {
+    assert_not_found(backend.delete(MISSING_ID).await);
+}
+
+pub async fn undelete<B: Backend + Sync>(backend: &B) {
+    let id = backend.store("http://example.com/").await.unwrap();
+    backend.undelete(id).await.unwrap();
+    backend.delete(id).await.unwrap();
+    backend.undelete(id).await.unwrap();
+    assert_eq!(backend.retrive(id).await.unwrap(), "http://example.com/");
+    assert_eq!(backend.info(id).await.unwrap().deleted_at, None);
+}
+
+pub async fn undelete_missing<B: Backend + Sync>(backend: &B) {
+    assert_not_found(backend.undelete(MISSING_ID).await);
+}
+
+/// Tombstones of other checks are recent, so purging long deleted links
+/// leaves them alone.
+pub async fn purge<B: Backend + Sync>(backend: &B) {
+    let live = backend.store("http://example.com/").await.unwrap();
+    let id = backend.store("http://example.org/").await.unwrap();
+    let deleted_at = now() - Duration::days(400);
+    let link = Link {
+        deleted_at: Some(deleted_at),
+        ..Link::new("http://example.org/")
+    };
+    backend.restore(id, &link, &[], &[]).await.unwrap();
+    assert_deleted(backend.retrive(id).await);
+
+    backend
+        .purge(deleted_at - Duration::seconds(1))
+        .await
+        .unwrap();
+    assert_eq!(backend.info(id).await.unwrap().deleted_at, Some(deleted_at));
+    assert!(backend.purge(deleted_at).await.unwrap() >= 1);
+    assert_not_found(backend.info(id).await);
+    assert_not_found(backend.clicks(id).await);
+    assert_eq!(backend.retrive(live).await.unwrap(), "http://example.com/");
+}
+
+pub async fn stat_counts_clicks<B: Backend + Sync>(backend: &B) {
+    let id = backend.store("http://example.com/").await.unwrap();
+    for _ in 0..3 {
+        backend.retrive(id).await.unwrap();
+    }
+    assert_eq!(backend.stat(id, None).await.unwrap(), 3);
+}
+
+pub async fn stat_without_clicks<B: Backend + Sync>(backend: &B) {
+    let id = backend.store("http://example.com/").await.unwrap();
+    assert_eq!(backend.stat(id, None).await.unwrap(), 0);
+}
+
+pub async fn stat_window<B: Backend + Sync>(backend: &B) {
+    let id = backend.store("http://example.com/").await.unwrap();
+    backend.retrive(id).await.unwrap();
+    backend.retrive(id).await.unwrap();
+    let now = Utc::now();
+    let hour_ago = now - Duration::hours(1);
+    let in_hour = now + Duration::hours(1);
+    assert_eq!(backend.stat(id, Some(hour_ago)).await.unwrap(), 2);
+    assert_eq!(backend.stat(id, Some(in_hour)).await.unwrap(), 0);
+}
+
+pub async fn stat_missing<B: Backend + Sync>(backend: &B) {
+    assert_not_found(backend.stat(MISSING_ID, None).await);
+}
+
+pub async fn click<B: Backend + Sync>(backend: &B) {
+    let id = backend.store("http://example.com/").await.unwrap();
+    backend.click(id).await.unwrap();
+    backend.click(id).await.unwrap();
+    assert_eq!(backend.stat(id, None).await.unwrap(), 2);
+}
+
+pub async fn click_missing<B: Backend + Sync>(backend: &B) {
+    assert_not_found(backend.click(MISSING_ID).await);
+}
+
+pub async fn list<B: Backend + Sync>(backend: &B) {
+    let first = backend.store("http://example.com/1").await.unwrap();
+    let second = backend.store("http://example.com/2").await.unwrap();
+    let third = backend.store("http://example.com/3").await.unwrap();
+    backend.delete(second).await.unwrap();
+    let links = backend.list(Some(first - 1), 1000).await.unwrap();
+    assert!(links.windows(2).all(|pair| pair[0].0 < pair[1].0));
+    let own = links
+        .into_iter()
+        .filter(|(id, _)| [first, second, third].contains(id))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        own,
+        vec![
+            (first, "http://example.com/1".to_owned()),
+            (third, "http://example.com/3".to_owned())
+        ]
+    );
+    let next = backend.list(Some(first), 1).await.unwrap();
+    assert_eq!(next.len(), 1);
+    assert!(next[0].0 > first);
+}
+
+pub async fn list_skips_tombstones<B: Backend + Sync>(backend: &B) {
+    let deleted = backend.store("http://example.com/").await.unwrap();
+    let live = backend.store("http://example.org/").await.unwrap();
+    backend.delete(deleted).await.unwrap();
+    let links = backend.list(Some(deleted - 1), 10).await.unwrap();
+    assert!(links.iter().all(|(id, _)| *id != deleted));
+    assert!(links.contains(&(live, "http://example.org/".to_owned())));
+}
+
+pub async fn list_with_tombstones<B: Backend + Sync>(backend: &B) {
+    let deleted = backend.store("http://example.com/").await.unwrap();
+    let live = backend.store("http://example.org/").await.unwrap();
+    backend.delete(deleted).await.unwrap();
+    let links = backend
+        .list_with_tombstones(Some(deleted - 1), 10)
+        .await
+        .unwrap();
+    assert!(links.contains(&(deleted, "http://example.com/".to_owned())));
+    assert!(links.contains(&(live, "http://example.org/".to_owned())));
+    assert!(links.windows(2).all(|pair| pair[0].0 < pair[1].0));
+}
+
+pub async fn clicks<B: Backend + Sync>(backend: &B) {
+    let id = backend.store("http://example.com/").await.unwrap();
+    assert!(backend.clicks(id).await.unwrap().is_empty());
+    backend.retrive(id).await.unwrap();
+    backend.retrive(id).await.unwrap();
+    let clicks = backend.clicks(id).await.unwrap();
+    assert_eq!(clicks.iter().map(|(_, counter)| counter).sum::<u64>(), 2);
+}
+
+pub async fn clicks_missing<B: Backend + Sync>(backend: &B) {
+    assert_not_found(backend.clicks(MISSING_ID).await);
+}
+
+pub async fn restore<B: Backend + Sync>(backend: &B) {
+    let id = backend.store("http://example.com/").await.unwrap() + 1000;
+    // Whole minutes, as coarsest backends keep clicks in minute buckets
+    let ts = Utc::now().timestamp();
+    let now = DateTime::from_timestamp(ts - ts % 60, 0).unwrap();
+    let clicks = vec![(now - Duration::minutes(30), 2), (now, 3)];
+    let link = Link {
+        url: "http://example.org/".to_owned(),
+        version: 3,
+        created_at: Some(now - Duration::days(7)),
+        updated_at: Some(now),
+        deleted_at: None,
+        metadata: Metadata {
+            title: Some("Example".to_owned()),
+            flags: ["pinned".to_owned()].into(),
+            ..Default::default()
+        },
+    };
+    backend.restore(id, &link, &[], &clicks).await.unwrap();
+    assert_eq!(backend.clicks(id).await.unwrap(), clicks);
+    assert_eq!(backend.stat(id, None).await.unwrap(), 5);
+    assert_eq!(backend.info(id).await.unwrap(), link);
+    assert_eq!(backend.retrive(id).await.unwrap(), "http://example.org/");
+    assert!(backend.store("http://example.com/").await.unwrap() > id);
+}
+
+pub async fn restore_overwrites<B: Backend + Sync>(backend: &B) {
+    let id = backend.store("http://example.com/").await.unwrap();
+    backend.retrive(id).await.unwrap();
+    backend
+        .restore(id, &Link::new("http://example.org/"), &[], &[])
+        .await
+        .unwrap();
+    assert!(backend.clicks(id).await.unwrap().is_empty());
+    assert_eq!(backend.retrive(id).await.unwrap(), "http://example.org/");
+}
+
+pub async fn store_many<B: Backend + Sync>(backend: &B) {
+    let urls = vec![
+        "http://example.com/1".to_owned(),
+        "http://example.com/2".to_owned(),
+    ];
+    let ids = backend
+        .store_many(&urls)
+        .await
+        .unwrap()
+        .into_iter()
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    assert_eq!(ids.len(), 2);
+    assert_ne!(ids[0], ids[1]);
+    assert_eq!(backend.retrive(ids[0]).await.unwrap(), urls[0]);
+    assert_eq!(backend.retrive(ids[1]).await.unwrap(), urls[1]);
+    assert!(backend.store_many(&[]).await.unwrap().is_empty());
+}
+
+pub async fn retrive_many<B: Backend + Sync>(backend: &B) {
+    let id = backend.store("http://example.com/").await.unwrap();
+    let results = backend.retrive_many(&[id, MISSING_ID]).await.unwrap();
+    assert_eq!(results.len(), 2);
+    assert_eq!(results[0].as_ref().unwrap(), "http://example.com/");
+    assert!(matches!(results[1], Err(BackendError::NotFound)));
+    assert_eq!(backend.stat(id, None).await.unwrap(), 1);
+}
+
+pub async fn delete_many<B: Backend + Sync>(backend: &B) {
+    let id = backend.store("http://example.com/").await.unwrap();
+    let results = backend.delete_many(&[id, MISSING_ID]).await.unwrap();
+    assert!(results[0].is_ok());
+    assert!(matches!(results[1], Err(BackendError::NotFound)));
+    assert_deleted(backend.retrive(id).await);
+}
+
+pub async fn info<B: Backend + Sync>(backend: &B) {
+    let before = now();
+    let id = backend.store("http://example.com/").await.unwrap();
+    let link = backend.info(id).await.unwrap();
+    assert_eq!(link.url, "http://example.com/");
+    assert_eq!(link.version, 1);
+    assert_eq!(link.metadata, Metadata::default());
+    let created_at = link.created_at.unwrap();
+    assert!(created_at >= before);
+    assert_eq!(link.updated_at, Some(created_at));
+
+    backend.update(id, "http://example.org/").await.unwrap();
+    let link = backend.info(id).await.unwrap();
+    assert_eq!(link.url, "http://example.org/");
+    assert_eq!(link.version, 2);
+    assert_eq!(link.created_at, Some(created_at));
+    assert!(link.updated_at.unwrap() >= created_at);
+}
+
+pub async fn info_missing<B: Backend + Sync>(backend: &B) {
+    assert_not_found(backend.info(MISSING_ID).await);
+}
+
+pub async fn set_metadata<B: Backend + Sync>(backend: &B) {
+    let id = backend.store("http://example.com/").await.unwrap();
+    let metadata = Metadata {
+        title: Some("Example".to_owned()),
+        notes: Some("Landing page".to_owned()),
+        owner: Some("marketing".to_owned()),
+        flags: ["campaign".to_owned(), "pinned".to_owned()].into(),
+    };
+    backend.set_metadata(id, &metadata).await.unwrap();
+    let link = backend.info(id).await.unwrap();
+    assert_eq!(link.metadata, metadata);
+    assert_eq!(link.url, "http://example.com/");
+    assert_eq!(link.version, 1);
+
+    backend
+        .set_metadata(id, &Metadata::default())
+        .await
+        .unwrap();
+    assert_eq!(
+        backend.info(id).await.unwrap().metadata,
+        Metadata::default()
+    );
+}
+
+pub async fn set_metadata_missing<B: Backend + Sync>(backend: &B) {
+    assert_not_found(backend.set_metadata(MISSING_ID, &Metadata::default()).await);
+}
+
+pub async fn history<B: Backend + Sync>(backend: &B) {
+    let id = backend.store("http://example.com/").await.unwrap();
+    backend.update(id, "http://example.org/").await.unwrap();
+    backend
+        .set_metadata(id, &Metadata::default())
+        .await
+        .unwrap();
+    backend.update(id, "http://example.net/").await.unwrap();
+    let history = backend.history(id).await.unwrap();
+    let destinations = history
+        .iter()
+        .map(|revision| (revision.version, revision.url.as_str()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        destinations,
+        [
+            (1, "http://example.com/"),
+            (2, "http://example.org/"),
+            (3, "http://example.net/"),
+        ]
+    );
+    let link = backend.info(id).await.unwrap();
+    assert_eq!(history[0].created_at, link.created_at);
+    assert!(history.iter().all(|revision| revision.created_at.is_some()));
+    assert!(history[1].created_at <= history[2].created_at);
+
+    backend.delete(id).await.unwrap();
+    assert_eq!(backend.history(id).await.unwrap(), history);
+}
+
+pub async fn history_missing<B: Backend + Sync>(backend: &B) {
+    assert_not_found(backend.history(MISSING_ID).await);
+}
+
+pub async fn history_after_restore<B: Backend + Sync>(backend: &B) {
+    let id = backend.store("http://example.com/").await.unwrap();
+    backend.update(id, "http://example.org/").await.unwrap();
+    let mut link = Link::new("http://example.net/");
+    link.version = 5;
+    backend.restore(id, &link, &[], &[]).await.unwrap();
+    assert_eq!(
+        backend.history(id).await.unwrap(),
+        [Revision {
+            version: 5,
+            url: "http://example.net/".to_owned(),
+            created_at: None,
+        }]
+    );
+    backend.update(id, "http://example.com/").await.unwrap();
+    let versions = backend
+        .history(id)
+        .await
+        .unwrap()
+        .iter()
+        .map(|revision| revision.version)
+        .collect::<Vec<_>>();
+    assert_eq!(versions, [5, 6]);
+}
+
+pub async fn restore_history<B: Backend + Sync>(backend: &B) {
+    let id = backend.store("http://example.com/").await.unwrap();
+    let created_at = now() - Duration::days(7);
+    let history = [
+        Revision {
+            version: 1,
+            url: "http://example.com/".to_owned(),
+            created_at: Some(created_at),
+        },
+        Revision {
+            version: 2,
+            url: "http://example.org/".to_owned(),
+            created_at: Some(created_at + Duration::days(1)),
+        },
+    ];
+    let link = Link {
+        version: 2,
+        ..Link::new("http://example.org/")
+    };
+    backend.restore(id, &link, &history, &[]).await.unwrap();
+    assert_eq!(backend.history(id).await.unwrap(), history);
+    assert_eq!(backend.rollback(id, 1, Some(2)).await.unwrap(), 3);
+    assert_eq!(backend.retrive(id).await.unwrap(), "http://example.com/");
+}
+
+pub async fn rollback<B: Backend + Sync>(backend: &B) {
+    let id = backend.store("http://example.com/").await.unwrap();
+    backend.update(id, "http://example.org/").await.unwrap();
+    assert_eq!(backend.rollback(id, 1, Some(2)).await.unwrap(), 3);
+    assert_eq!(
+        backend.retrive_versioned(id).await.unwrap(),
+        ("http://example.com/".to_owned(), 3)
+    );
+    let history = backend.history(id).await.unwrap();
+    assert_eq!(history.len(), 3);
+    assert_eq!(history[2].url, "http://example.com/");
+
+    match backend.rollback(id, 2, Some(2)).await {
+        Err(BackendError::VersionMismatch) => {}
+        other => panic!("Expected BackendError::VersionMismatch, got {:?}", other),
+    }
+    backend.delete(id).await.unwrap();
+    assert_deleted(backend.rollback(id, 2, None).await);
+}
+
+pub async fn rollback_unknown_version<B: Backend + Sync>(backend: &B) {
+    let id = backend.store("http://example.com/").await.unwrap();
+    assert_not_found(backend.rollback(id, 7, None).await);
+    assert_not_found(backend.rollback(MISSING_ID, 1, None).await);
+    assert_eq!(backend.retrive(id).await.unwrap(), "http://example.com/");
+}
+
+/// Destination no other check points to.
+fn unique_url() -> String {
+    format!("http://example.com/{}", Uuid::new_v4())
+}
+
+pub async fn find<B: Backend + Sync>(backend: &B) {
+    let url = unique_url();
+    assert!(backend.find(&url).await.unwrap().is_empty());
+    let first = backend.store(&url).await.unwrap();
+    backend.store("http://example.org/").await.unwrap();
+    let second = backend.store(&url).await.unwrap();
+    assert_eq!(backend.find(&url).await.unwrap(), [first, second]);
+
+    backend.delete(first).await.unwrap();
+    assert_eq!(backend.find(&url).await.unwrap(), [second]);
+    backend.undelete(first).await.unwrap();
+    assert_eq!(backend.find(&url).await.unwrap(), [first, second]);
+}
+
+pub async fn find_after_update<B: Backend + Sync>(backend: &B) {
+    let (url, other) = (unique_url(), unique_url());
+    let id = backend.store(&url).await.unwrap();
+    backend.update(id, &other).await.unwrap();
+    assert!(backend.find(&url).await.unwrap().is_empty());
+    assert_eq!(backend.find(&other).await.unwrap(), [id]);
+    backend.rollback(id, 1, None).await.unwrap();
+    assert_eq!(backend.find(&url).await.unwrap(), [id]);
+    assert!(backend.find(&other).await.unwrap().is_empty());
+}
+
+pub async fn find_after_restore<B: Backend + Sync>(backend: &B) {
+    let (url, other) = (unique_url(), unique_url());
+    let id = backend.store(&url).await.unwrap();
+    backend
+        .restore(id, &Link::new(&other), &[], &[])
+        .await
+        .unwrap();
+    assert!(backend.find(&url).await.unwrap().is_empty());
+    assert_eq!(backend.find(&other).await.unwrap(), [id]);
+
+    let deleted_at = now() - Duration::days(400);
+    let link = Link {
+        deleted_at: Some(deleted_at),
+        ..Link::new(&url)
+    };
+    backend.restore(id, &link, &[], &[]).await.unwrap();
+    assert!(backend.find(&url).await.unwrap().is_empty());
+    assert!(backend.find(&other).await.unwrap().is_empty());
+    assert!(backend.purge(deleted_at).await.unwrap() >= 1);
+    let stored = backend.store(&url).await.unwrap();
+    assert_eq!(backend.find(&url).await.unwrap(), [stored]);
+}
+
+pub async fn allocate_ids<B: Backend + Sync>(backend: &B) {
+    let ids = backend.allocate_ids(3).await.unwrap();
+    assert_eq!(ids.len(), 3);
+    assert!(ids.windows(2).all(|pair| pair[0] < pair[1]));
+    assert_not_found(backend.retrive(ids[0]).await);
+    assert!(backend.store("http://example.com/").await.unwrap() > ids[2]);
+    assert!(backend.allocate_ids(0).await.unwrap().is_empty());
+}
+
+pub async fn reserve_until<B: Backend + Sync>(backend: &B) {
+    let id = backend.allocate_ids(1).await.unwrap()[0];
+    backend.reserve_until(id + 1000).await.unwrap();
+    assert!(backend.store("http://example.com/").await.unwrap() > id + 1000);
+    // A counter already past the id stays where it is
+    let last = backend.allocate_ids(1).await.unwrap()[0];
+    backend.reserve_until(id).await.unwrap();
+    assert!(backend.allocate_ids(1).await.unwrap()[0] > last);
+}
+
+/// Ids past allocated ones are not taken by other checks, which only
+/// offset ids they allocated themselves.
+pub async fn store_with_id<B: Backend + Sync>(backend: &B) {
+    let url = unique_url();
+    let id = backend.allocate_ids(1).await.unwrap()[0] + 1000;
+    backend.store_with_id(id, &url).await.unwrap();
+    assert_eq!(backend.retrive(id).await.unwrap(), url);
+    assert_eq!(backend.info(id).await.unwrap().version, 1);
+    assert_eq!(backend.history(id).await.unwrap().len(), 1);
+    assert_eq!(backend.find(&url).await.unwrap(), [id]);
+    assert_eq!(
+        backend.list(Some(id - 1), 1).await.unwrap(),
+        [(id, url.clone())]
+    );
+    assert!(backend.store("http://example.com/").await.unwrap() > id);
+    assert!(backend.allocate_ids(1).await.unwrap()[0] > id);
+}
+
+pub async fn store_with_taken_id<B: Backend + Sync>(backend: &B) {
+    let id = backend.store("http://example.com/").await.unwrap();
+    assert_already_exists(backend.store_with_id(id, "http://example.org/").await);
+    assert_eq!(backend.retrive(id).await.unwrap(), "http://example.com/");
+    backend.delete(id).await.unwrap();
+    assert_already_exists(backend.store_with_id(id, "http://example.org/").await);
+    assert_deleted(backend.retrive(id).await);
+}
+
+pub async fn store_many_with_ids<B: Backend + Sync>(backend: &B) {
+    let ids = backend
+        .allocate_ids(2)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|id| id + 1000)
+        .collect::<Vec<_>>();
+    let links = [
+        (ids[0], "http://example.com/1"),
+        (ids[1], "http://example.com/2"),
+        (ids[0], "http://example.com/3"),
+    ];
+    let results = backend.store_many_with_ids(&links).await.unwrap();
+    assert_eq!(results.len(), 3);
+    assert!(results[0].is_ok());
+    assert!(results[1].is_ok());
+    assert!(matches!(results[2], Err(BackendError::AlreadyExists)));
+    assert_eq!(
+        backend.retrive(ids[0]).await.unwrap(),
+        "http://example.com/1"
+    );
+    assert_eq!(
+        backend.retrive(ids[1]).await.unwrap(),
+        "http://example.com/2"
+    );
+    assert!(backend.store("http://example.com/").await.unwrap() > ids[1]);
+    assert!(backend.store_many_with_ids(&[]).await.unwrap().is_empty());
+}
+
+fn unique_alias() -> String {
+    format!("alias-{}", Uuid::new_v4().simple())
+}
+
+pub async fn alias<B: Backend + Sync>(backend: &B) {
+    let mut aliases = [unique_alias(), unique_alias()];
+    aliases.sort();
+    let [first, second] = aliases;
+    let id = backend.store("http://example.com/").await.unwrap();
+    assert!(backend.aliases(id).await.unwrap().is_empty());
+    backend.store_alias(&second, id).await.unwrap();
+    backend.store_alias(&first, id).await.unwrap();
+    backend.store_alias(&first, id).await.unwrap();
+    assert_eq!(backend.resolve_alias(&first).await.unwrap(), id);
+    assert_eq!(backend.resolve_alias(&second).await.unwrap(), id);
+    assert_eq!(backend.aliases(id).await.unwrap(), [first, second]);
+}
+
+pub async fn alias_taken<B: Backend + Sync>(backend: &B) {
+    let alias = unique_alias();
+    let id = backend.store("http://example.com/").await.unwrap();
+    let other = backend.store("http://example.org/").await.unwrap();
+    backend.store_alias(&alias, id).await.unwrap();
+    assert_already_exists(backend.store_alias(&alias, other).await);
+    assert_eq!(backend.resolve_alias(&alias).await.unwrap(), id);
+    assert!(backend.aliases(other).await.unwrap().is_empty());
+
+    // Tombstones keep their aliases
+    backend.delete(id).await.unwrap();
+    assert_already_exists(backend.store_alias(&alias, other).await);
+    assert_eq!(backend.aliases(id).await.unwrap(), [alias]);
+}
+
+pub async fn store_with_alias<B: Backend + Sync>(backend: &B) {
+    let alias = unique_alias();
+    let id = backend.allocate_ids(1).await.unwrap()[0];
+    backend
+        .store_with_alias(id, "http://example.com/", &alias)
+        .await
+        .unwrap();
+    assert_eq!(backend.resolve_alias(&alias).await.unwrap(), id);
+    assert_eq!(backend.aliases(id).await.unwrap(), [alias]);
+    assert_eq!(backend.retrive(id).await.unwrap(), "http://example.com/");
+}
+
+/// Nothing is stored when the alias is taken.
+pub async fn store_with_alias_taken<B: Backend + Sync>(backend: &B) {
+    let alias = unique_alias();
+    let owner = backend.store("http://example.com/").await.unwrap();
+    backend.store_alias(&alias, owner).await.unwrap();
+    let id = backend.allocate_ids(1).await.unwrap()[0];
+    assert_already_exists(
+        backend
+            .store_with_alias(id, "http://example.org/", &alias)
+            .await,
+    );
+    assert_not_found(backend.info(id).await);
+    assert_eq!(backend.resolve_alias(&alias).await.unwrap(), owner);
+    assert_already_exists(
+        backend
+            .store_with_alias(owner, "http://example.org/", &unique_alias())
+            .await,
+    );
+}
+
+pub async fn alias_missing_link<B: Backend + Sync>(backend: &B) {
+    let alias = unique_alias();
+    assert_not_found(backend.store_alias(&alias, MISSING_ID).await);
+    assert_not_found(backend.resolve_alias(&alias).await);
+    assert!(backend.aliases(MISSING_ID).await.unwrap().is_empty());
+}
+
+pub async fn resolve_missing_alias<B: Backend + Sync>(backend: &B) {
+    assert_not_found(backend.resolve_alias(&unique_alias()).await);
+}
+
+pub async fn alias_purged<B: Backend + Sync>(backend: &B) {
+    let alias = unique_alias();
+    let id = backend.store("http://example.com/").await.unwrap();
+    backend.store_alias(&alias, id).await.unwrap();
+    let deleted_at = now() - Duration::days(400);
+    let link = Link {
+        deleted_at: Some(deleted_at),
+        ..Link::new("http://example.com/")
+    };
+    backend.restore(id, &link, &[], &[]).await.unwrap();
+    assert!(backend.purge(deleted_at).await.unwrap() >= 1);
+    assert_not_found(backend.resolve_alias(&alias).await);
+    assert!(backend.aliases(id).await.unwrap().is_empty());
+    let other = backend.store("http://example.org/").await.unwrap();
+    backend.store_alias(&alias, other).await.unwrap();
+}
+
+fn unique_code() -> String {
+    Uuid::new_v4().simple().to_string()
+}
+
+pub async fn code<B: Backend + Sync>(backend: &B) {
+    let (code, other) = (unique_code(), unique_code());
+    let id = backend.store("http://example.com/").await.unwrap();
+    assert_eq!(backend.store_code(id, &code).await.unwrap(), code);
+    assert_eq!(backend.store_code(id, &code).await.unwrap(), code);
+    assert_eq!(backend.resolve_code(&code).await.unwrap(), id);
+    assert_eq!(backend.code(id).await.unwrap(), code);
+
+    // The first code stays, the other one is left free
+    assert_eq!(backend.store_code(id, &other).await.unwrap(), code);
+    assert_not_found(backend.resolve_code(&other).await);
+}
+
+pub async fn code_taken<B: Backend + Sync>(backend: &B) {
+    let code = unique_code();
+    let id = backend.store("http://example.com/").await.unwrap();
+    let other = backend.store("http://example.org/").await.unwrap();
+    backend.store_code(id, &code).await.unwrap();
+    assert_already_exists(backend.store_code(other, &code).await);
+    assert_eq!(backend.resolve_code(&code).await.unwrap(), id);
+    assert_not_found(backend.code(other).await);
+}
+
+pub async fn code_missing<B: Backend + Sync>(backend: &B) {
+    assert_not_found(backend.resolve_code(&unique_code()).await);
+    assert_not_found(backend.code(MISSING_ID).await);
+}
+
+pub async fn code_purged<B: Backend + Sync>(backend: &B) {
+    let code = unique_code();
+    let id = backend.store("http://example.com/").await.unwrap();
+    backend.store_code(id, &code).await.unwrap();
+    backend.delete(id).await.unwrap();
+    backend.purge(Utc::now()).await.unwrap();
+
+    // The code is never handed out again
+    assert_eq!(backend.resolve_code(&code).await.unwrap(), id);
+    let other = backend.store("http://example.org/").await.unwrap();
+    assert_already_exists(backend.store_code(other, &code).await);
+    assert_not_found(backend.info(id).await);
+}
@@ -0,0 +1,86 @@
+use std::collections::BTreeSet;
+
+use chrono::{DateTime, SubsecRound, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Descriptive attributes of a link, set by its owner.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(default)]
+pub struct Metadata {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub notes: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub owner: Option<String>,
+    #[serde(skip_serializing_if = "BTreeSet::is_empty")]
+    pub flags: BTreeSet<String>,
+}
+
+/// Everything kept about a link except its click statistics.
+///
+/// Timestamps have microsecond precision, the finest every storage keeps.
+/// They are absent for links stored before records were introduced.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Link {
+    pub url: String,
+    pub version: u64,
+    pub created_at: Option<DateTime<Utc>>,
+    pub updated_at: Option<DateTime<Utc>>,
+    /// Set while the link is a tombstone
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deleted_at: Option<DateTime<Utc>>,
+    #[serde(flatten)]
+    pub metadata: Metadata,
+}
+
+impl Link {
+    /// Record of a link stored just now.
+    pub fn new<U: Into<String>>(url: U) -> Self {
+        let now = now();
+        Self {
+            url: url.into(),
+            version: 1,
+            created_at: Some(now),
+            updated_at: Some(now),
+            deleted_at: None,
+            metadata: Metadata::default(),
+        }
+    }
+}
+
+/// Destination a link had at some version.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Revision {
+    pub version: u64,
+    pub url: String,
+    /// When the link got this destination, absent for links stored before
+    /// history was kept
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+impl Revision {
+    /// Only revision known of a link stored before history was kept.
+    pub(crate) fn current(link: &Link) -> Self {
+        Self {
+            version: link.version,
+            url: link.url.clone(),
+            created_at: None,
+        }
+    }
+
+    /// History written by a restore, the current revision alone when none is
+    /// given.
+    pub(crate) fn restored(link: &Link, history: &[Revision]) -> Vec<Self> {
+        if history.is_empty() {
+            vec![Self::current(link)]
+        } else {
+            history.to_vec()
+        }
+    }
+}
+
+/// Current time truncated to the precision kept by backends.
+pub(crate) fn now() -> DateTime<Utc> {
+    Utc::now().trunc_subsecs(6)
+}
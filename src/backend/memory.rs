@@ -1,17 +1,41 @@
-use std::collections::{BTreeMap, HashMap};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    path::PathBuf,
+    sync::{Arc, Weak},
+    time::Duration,
+};
 
 use async_trait::async_trait;
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
 use thiserror::Error;
 use tokio::sync::RwLock;
-use tracing::info;
+use tracing::{info, warn};
 
-use super::{Backend, BackendError};
+use self::persistence::{JournalEntry, Persistence, Snapshot};
+
+use super::{link::now, Backend, BackendError, Clicks, Link, Metadata, Revision};
+
+mod persistence;
+
+static DEFAULT_STAT_PERIOD_IN_HOURS: i64 = 24;
+/// Shortest period of the snapshot task, zero intervals snapshot on every
+/// write instead.
+const MIN_SNAPSHOT_PERIOD: Duration = Duration::from_secs(1);
 
 #[derive(Default)]
 pub struct InMemoryBackend {
-    storage: RwLock<(u64, HashMap<u64, String>)>,
+    /// Last allocated id and links ordered by id, so pages are ranges
+    storage: RwLock<(u64, BTreeMap<u64, Link>)>,
     stat: RwLock<HashMap<u64, BTreeMap<i64, u64>>>,
+    history: RwLock<HashMap<u64, Vec<Revision>>>,
+    /// Ids of links by destination, tombstones included. Rebuilt from links
+    /// on startup.
+    destinations: RwLock<HashMap<String, BTreeSet<u64>>>,
+    aliases: RwLock<BTreeMap<String, u64>>,
+    codes: RwLock<HashMap<String, u64>>,
+    /// Code of every link with one. Rebuilt from codes on startup.
+    link_codes: RwLock<HashMap<u64, String>>,
+    persistence: Option<Persistence>,
 }
 
 impl InMemoryBackend {
@@ -19,6 +43,201 @@ impl InMemoryBackend {
         info!("Initialize InMemory backend");
         Self::default()
     }
+
+    pub async fn with_persistence<J: Into<PathBuf>, S: Into<PathBuf>>(
+        journal: J,
+        snapshot: S,
+        snapshot_interval: Duration,
+    ) -> Result<Self, BackendError> {
+        info!("Initialize InMemory backend with persistence");
+        let (persistence, snapshot) =
+            Persistence::open(journal.into(), snapshot.into(), snapshot_interval).await?;
+        let mut destinations: HashMap<String, BTreeSet<u64>> = HashMap::new();
+        for (id, link) in &snapshot.links {
+            destinations
+                .entry(link.url.clone())
+                .or_default()
+                .insert(*id);
+        }
+        let link_codes = snapshot
+            .codes
+            .iter()
+            .map(|(code, id)| (*id, code.clone()))
+            .collect();
+        Ok(Self {
+            destinations: RwLock::new(destinations),
+            storage: RwLock::new((snapshot.last_id, snapshot.links)),
+            stat: RwLock::new(snapshot.stat),
+            history: RwLock::new(snapshot.history),
+            aliases: RwLock::new(snapshot.aliases),
+            codes: RwLock::new(snapshot.codes),
+            link_codes: RwLock::new(link_codes),
+            persistence: Some(persistence),
+        })
+    }
+
+    /// Write a snapshot of the whole state and truncate the journal.
+    ///
+    /// Every operation journals and applies its entry while holding the
+    /// storage lock, read locks included. The write lock is held until the
+    /// journal is truncated, so no entry is appended after the state was
+    /// copied and dropped with the journal.
+    pub async fn snapshot(&self) -> Result<(), BackendError> {
+        if let Some(persistence) = &self.persistence {
+            let storage = self.storage.write().await;
+            let snapshot = Snapshot {
+                last_id: storage.0,
+                links: storage.1.clone(),
+                stat: self.stat.read().await.clone(),
+                history: self.history.read().await.clone(),
+                aliases: self.aliases.read().await.clone(),
+                codes: self.codes.read().await.clone(),
+            };
+            persistence.write_snapshot(&snapshot).await?;
+            drop(storage);
+        }
+        Ok(())
+    }
+
+    /// Snapshot in background once the interval has elapsed, so the journal
+    /// is compacted when no writes come in. The task stops with the backend.
+    pub fn spawn_snapshots(self: &Arc<Self>) {
+        let Some(persistence) = &self.persistence else {
+            return;
+        };
+        let period = persistence.snapshot_interval().max(MIN_SNAPSHOT_PERIOD);
+        tokio::spawn(Self::snapshot_periodically(Arc::downgrade(self), period));
+    }
+
+    async fn snapshot_periodically(backend: Weak<Self>, period: Duration) {
+        let mut interval = tokio::time::interval(period);
+        loop {
+            interval.tick().await;
+            let Some(backend) = backend.upgrade() else {
+                break;
+            };
+            if let Err(error) = backend.snapshot_if_due().await {
+                warn!("Unable to write snapshot: {}", error);
+            }
+        }
+    }
+
+    async fn journal(&self, entry: JournalEntry) -> Result<(), BackendError> {
+        if let Some(persistence) = &self.persistence {
+            persistence.append(&entry).await?;
+        }
+        Ok(())
+    }
+
+    async fn index(&self, id: u64, url: &str) {
+        self.destinations
+            .write()
+            .await
+            .entry(url.to_owned())
+            .or_default()
+            .insert(id);
+    }
+
+    async fn unindex(&self, id: u64, url: &str) {
+        let mut destinations = self.destinations.write().await;
+        if let Some(ids) = destinations.get_mut(url) {
+            ids.remove(&id);
+            if ids.is_empty() {
+                destinations.remove(url);
+            }
+        }
+    }
+
+    /// Store a link under `id`, the counter is moved past it.
+    /// Journal and store a new link. `alias` is only journaled with it, the
+    /// caller holding the aliases lock records it.
+    async fn insert(
+        &self,
+        storage: &mut (u64, BTreeMap<u64, Link>),
+        id: u64,
+        url: &str,
+        alias: Option<&str>,
+    ) -> Result<(), BackendError> {
+        let link = Link::new(url);
+        self.journal(JournalEntry::Store {
+            id,
+            url: url.to_owned(),
+            created_at: link.created_at,
+            alias: alias.map(str::to_owned),
+        })
+        .await?;
+        self.history.write().await.insert(
+            id,
+            vec![Revision {
+                version: 1,
+                url: url.to_owned(),
+                created_at: link.created_at,
+            }],
+        );
+        storage.0 = storage.0.max(id);
+        storage.1.insert(id, link);
+        self.index(id, url).await;
+        Ok(())
+    }
+
+    async fn record_click(&self, id: u64) -> Result<(), BackendError> {
+        let ts = Utc::now().timestamp();
+        self.journal(JournalEntry::Click { id, ts }).await?;
+        *self
+            .stat
+            .write()
+            .await
+            .entry(id)
+            .or_default()
+            .entry(ts)
+            .or_default() += 1;
+        Ok(())
+    }
+
+    /// Links following `after` ordered by id, tombstones only with
+    /// `tombstones` set.
+    async fn links(
+        &self,
+        after: Option<u64>,
+        limit: usize,
+        tombstones: bool,
+    ) -> Vec<(u64, String)> {
+        let Some(start) = after.map_or(Some(0), |after| after.checked_add(1)) else {
+            return Vec::new();
+        };
+        let storage = self.storage.read().await;
+        storage
+            .1
+            .range(start..)
+            .filter(|(_, link)| tombstones || link.deleted_at.is_none())
+            .take(limit)
+            .map(|(id, link)| (*id, link.url.clone()))
+            .collect()
+    }
+
+    async fn snapshot_if_due(&self) -> Result<(), BackendError> {
+        match &self.persistence {
+            Some(persistence) if persistence.snapshot_due().await => self.snapshot().await,
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Link which is not a tombstone.
+fn live(link: Option<&Link>) -> Result<&Link, BackendError> {
+    match link {
+        Some(link) if link.deleted_at.is_some() => Err(BackendError::Deleted),
+        Some(link) => Ok(link),
+        None => Err(BackendError::NotFound),
+    }
+}
+
+fn live_mut(link: Option<&mut Link>) -> Result<&mut Link, BackendError> {
+    match link {
+        Some(link) if link.deleted_at.is_some() => Err(BackendError::Deleted),
+        Some(link) => Ok(link),
+        None => Err(BackendError::NotFound),
+    }
 }
 
 #[derive(Error, Debug)]
@@ -31,57 +250,391 @@ pub enum MemoryBackendError {
 impl Backend for InMemoryBackend {
     async fn store<'a>(&self, url: &'a str) -> Result<u64, BackendError> {
         let mut storage = self.storage.write().await;
-        storage.0 += 1;
-        let id = storage.0;
-        storage.1.insert(id, url.to_owned());
-        Ok(storage.0)
+        let id = storage.0 + 1;
+        self.insert(&mut storage, id, url, None).await?;
+        drop(storage);
+        self.snapshot_if_due().await?;
+        Ok(id)
+    }
+
+    async fn allocate_ids(&self, count: u64) -> Result<Vec<u64>, BackendError> {
+        let mut storage = self.storage.write().await;
+        let first = storage.0 + 1;
+        let last_id = storage.0 + count;
+        self.journal(JournalEntry::Reserve { last_id }).await?;
+        storage.0 = last_id;
+        drop(storage);
+        self.snapshot_if_due().await?;
+        Ok((first..=last_id).collect())
+    }
+
+    async fn reserve_until(&self, id: u64) -> Result<(), BackendError> {
+        let mut storage = self.storage.write().await;
+        if storage.0 >= id {
+            return Ok(());
+        }
+        self.journal(JournalEntry::Reserve { last_id: id }).await?;
+        storage.0 = id;
+        drop(storage);
+        self.snapshot_if_due().await
+    }
+
+    async fn store_with_id<'a>(&self, id: u64, url: &'a str) -> Result<(), BackendError> {
+        let mut storage = self.storage.write().await;
+        if storage.1.contains_key(&id) {
+            return Err(BackendError::AlreadyExists);
+        }
+        self.insert(&mut storage, id, url, None).await?;
+        drop(storage);
+        self.snapshot_if_due().await
     }
 
-    async fn retrive(&self, id: u64) -> Result<String, BackendError> {
+    async fn retrive_versioned(&self, id: u64) -> Result<(String, u64), BackendError> {
         let storage = self.storage.read().await;
-        dbg!(&storage);
-        let ts = Utc::now().timestamp();
-        self.stat
-            .write()
+        let link = live(storage.1.get(&id))?;
+        let result = (link.url.clone(), link.version);
+        self.record_click(id).await?;
+        Ok(result)
+    }
+
+    async fn click(&self, id: u64) -> Result<(), BackendError> {
+        let storage = self.storage.read().await;
+        live(storage.1.get(&id))?;
+        self.record_click(id).await
+    }
+
+    async fn stat(&self, id: u64, since: Option<DateTime<Utc>>) -> Result<u64, BackendError> {
+        let storage = self.storage.read().await;
+        if !storage.1.contains_key(&id) {
+            return Err(BackendError::NotFound);
+        }
+        let now = Utc::now();
+        let since = since
+            .or_else(|| now.checked_sub_signed(ChronoDuration::hours(DEFAULT_STAT_PERIOD_IN_HOURS)))
+            .ok_or(BackendError::DateTimeOverflow)?;
+        if since > now {
+            return Ok(0);
+        }
+        let count = self
+            .stat
+            .read()
             .await
-            .entry(id)
-            .and_modify(|stat| {
-                stat.entry(ts)
-                    .and_modify(|counter| *counter += 1)
-                    .or_insert(1);
+            .get(&id)
+            .map(|stat| {
+                stat.range(since.timestamp()..=now.timestamp())
+                    .map(|(_, counter)| counter)
+                    .sum()
             })
-            .or_insert_with(|| {
-                let mut map = BTreeMap::new();
-                map.insert(ts, 1);
-                map
-            });
-        storage.1.get(&id).cloned().ok_or(BackendError::NotFound)
+            .unwrap_or(0);
+        Ok(count)
+    }
+
+    async fn update_versioned<'a>(
+        &self,
+        id: u64,
+        url: &'a str,
+        expected: Option<u64>,
+    ) -> Result<u64, BackendError> {
+        let mut storage = self.storage.write().await;
+        let link = live_mut(storage.1.get_mut(&id))?;
+        if expected.is_some_and(|expected| expected != link.version) {
+            return Err(BackendError::VersionMismatch);
+        }
+        let updated_at = Some(now());
+        self.journal(JournalEntry::Update {
+            id,
+            url: url.to_owned(),
+            updated_at,
+        })
+        .await?;
+        let previous = link.url.clone();
+        persistence::record_revision(&mut *self.history.write().await, id, link, url, updated_at);
+        let version = link.version;
+        self.unindex(id, &previous).await;
+        self.index(id, url).await;
+        drop(storage);
+        self.snapshot_if_due().await?;
+        Ok(version)
     }
 
-    async fn stat(&self, id: u64, _since: Option<DateTime<Utc>>) -> Result<u64, BackendError> {
-        self.stat
+    async fn delete(&self, id: u64) -> Result<(), BackendError> {
+        let mut storage = self.storage.write().await;
+        let link = live_mut(storage.1.get_mut(&id))?;
+        let deleted_at = now();
+        self.journal(JournalEntry::Tombstone { id, deleted_at })
+            .await?;
+        link.deleted_at = Some(deleted_at);
+        drop(storage);
+        self.snapshot_if_due().await
+    }
+
+    async fn undelete(&self, id: u64) -> Result<(), BackendError> {
+        let mut storage = self.storage.write().await;
+        let link = storage.1.get_mut(&id).ok_or(BackendError::NotFound)?;
+        if link.deleted_at.is_none() {
+            return Ok(());
+        }
+        self.journal(JournalEntry::Undelete { id }).await?;
+        link.deleted_at = None;
+        drop(storage);
+        self.snapshot_if_due().await
+    }
+
+    async fn purge(&self, before: DateTime<Utc>) -> Result<u64, BackendError> {
+        let mut storage = self.storage.write().await;
+        let ids = storage
+            .1
+            .iter()
+            .filter(|(_, link)| {
+                link.deleted_at
+                    .is_some_and(|deleted_at| deleted_at <= before)
+            })
+            .map(|(id, _)| *id)
+            .collect::<Vec<_>>();
+        let mut stat = self.stat.write().await;
+        let mut history = self.history.write().await;
+        let mut aliases = self.aliases.write().await;
+        for id in &ids {
+            self.journal(JournalEntry::Delete { id: *id }).await?;
+            if let Some(link) = storage.1.remove(id) {
+                self.unindex(*id, &link.url).await;
+            }
+            stat.remove(id);
+            history.remove(id);
+            aliases.retain(|_, link| link != id);
+        }
+        drop(aliases);
+        drop(history);
+        drop(stat);
+        drop(storage);
+        self.snapshot_if_due().await?;
+        Ok(ids.len() as u64)
+    }
+
+    async fn list(
+        &self,
+        after: Option<u64>,
+        limit: usize,
+    ) -> Result<Vec<(u64, String)>, BackendError> {
+        Ok(self.links(after, limit, false).await)
+    }
+
+    async fn list_with_tombstones(
+        &self,
+        after: Option<u64>,
+        limit: usize,
+    ) -> Result<Vec<(u64, String)>, BackendError> {
+        Ok(self.links(after, limit, true).await)
+    }
+
+    async fn find<'a>(&self, url: &'a str) -> Result<Vec<u64>, BackendError> {
+        let storage = self.storage.read().await;
+        let ids = self
+            .destinations
+            .read()
+            .await
+            .get(url)
+            .map(|ids| {
+                ids.iter()
+                    .filter(|id| live(storage.1.get(id)).is_ok())
+                    .copied()
+                    .collect()
+            })
+            .unwrap_or_default();
+        Ok(ids)
+    }
+
+    async fn clicks(&self, id: u64) -> Result<Clicks, BackendError> {
+        let storage = self.storage.read().await;
+        if !storage.1.contains_key(&id) {
+            return Err(BackendError::NotFound);
+        }
+        let clicks = self
+            .stat
             .read()
             .await
             .get(&id)
-            .map(BTreeMap::len)
-            .map(u64::try_from)
-            .map(Result::unwrap)
-            .ok_or(BackendError::NotFound)
+            .map(|stat| {
+                stat.iter()
+                    .filter_map(|(ts, counter)| {
+                        DateTime::from_timestamp(*ts, 0).map(|ts| (ts, *counter))
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        Ok(clicks)
+    }
+
+    async fn restore<'a>(
+        &self,
+        id: u64,
+        link: &'a Link,
+        history: &'a [Revision],
+        clicks: &'a [(DateTime<Utc>, u64)],
+    ) -> Result<(), BackendError> {
+        let mut stat = BTreeMap::new();
+        for (ts, counter) in clicks {
+            *stat.entry(ts.timestamp()).or_default() += counter;
+        }
+        let history = Revision::restored(link, history);
+        let mut storage = self.storage.write().await;
+        self.journal(JournalEntry::Restore {
+            id,
+            link: link.clone(),
+            history: history.clone(),
+            stat: stat.clone(),
+        })
+        .await?;
+        storage.0 = storage.0.max(id);
+        if let Some(previous) = storage.1.insert(id, link.clone()) {
+            self.unindex(id, &previous.url).await;
+        }
+        self.index(id, &link.url).await;
+        self.stat.write().await.insert(id, stat);
+        self.history.write().await.insert(id, history);
+        drop(storage);
+        self.snapshot_if_due().await
     }
 
-    async fn update<'a>(&self, id: u64, url: &'a str) -> Result<(), BackendError> {
+    async fn info(&self, id: u64) -> Result<Link, BackendError> {
         self.storage
-            .write()
+            .read()
             .await
             .1
-            .get_mut(&id)
-            .map(|old| *old = url.to_owned())
+            .get(&id)
+            .cloned()
             .ok_or(BackendError::NotFound)
     }
 
-    async fn delete(&self, id: u64) -> Result<(), BackendError> {
-        self.storage.write().await.1.remove(&id);
-        self.stat.write().await.remove(&id);
-        Ok(())
+    async fn history(&self, id: u64) -> Result<Vec<Revision>, BackendError> {
+        let storage = self.storage.read().await;
+        let link = storage.1.get(&id).ok_or(BackendError::NotFound)?;
+        let history = self
+            .history
+            .read()
+            .await
+            .get(&id)
+            .cloned()
+            .unwrap_or_else(|| vec![Revision::current(link)]);
+        Ok(history)
+    }
+
+    async fn store_alias<'a>(&self, alias: &'a str, id: u64) -> Result<(), BackendError> {
+        let storage = self.storage.read().await;
+        if !storage.1.contains_key(&id) {
+            return Err(BackendError::NotFound);
+        }
+        let mut aliases = self.aliases.write().await;
+        match aliases.get(alias) {
+            Some(existing) if *existing == id => return Ok(()),
+            Some(_) => return Err(BackendError::AlreadyExists),
+            None => {}
+        }
+        self.journal(JournalEntry::Alias {
+            alias: alias.to_owned(),
+            id,
+        })
+        .await?;
+        aliases.insert(alias.to_owned(), id);
+        drop(aliases);
+        drop(storage);
+        self.snapshot_if_due().await
+    }
+
+    async fn store_with_alias<'a>(
+        &self,
+        id: u64,
+        url: &'a str,
+        alias: &'a str,
+    ) -> Result<(), BackendError> {
+        let mut storage = self.storage.write().await;
+        let mut aliases = self.aliases.write().await;
+        if storage.1.contains_key(&id) || aliases.contains_key(alias) {
+            return Err(BackendError::AlreadyExists);
+        }
+        self.insert(&mut storage, id, url, Some(alias)).await?;
+        aliases.insert(alias.to_owned(), id);
+        drop(aliases);
+        drop(storage);
+        self.snapshot_if_due().await
+    }
+
+    async fn resolve_alias<'a>(&self, alias: &'a str) -> Result<u64, BackendError> {
+        self.aliases
+            .read()
+            .await
+            .get(alias)
+            .copied()
+            .ok_or(BackendError::NotFound)
+    }
+
+    async fn aliases(&self, id: u64) -> Result<Vec<String>, BackendError> {
+        Ok(self
+            .aliases
+            .read()
+            .await
+            .iter()
+            .filter(|(_, link)| **link == id)
+            .map(|(alias, _)| alias.clone())
+            .collect())
+    }
+
+    async fn store_code<'a>(&self, id: u64, code: &'a str) -> Result<String, BackendError> {
+        // Journaled under the storage lock, see [`InMemoryBackend::snapshot`]
+        let storage = self.storage.read().await;
+        let mut codes = self.codes.write().await;
+        let mut link_codes = self.link_codes.write().await;
+        if let Some(existing) = link_codes.get(&id) {
+            return Ok(existing.clone());
+        }
+        if codes.contains_key(code) {
+            return Err(BackendError::AlreadyExists);
+        }
+        self.journal(JournalEntry::Code {
+            code: code.to_owned(),
+            id,
+        })
+        .await?;
+        codes.insert(code.to_owned(), id);
+        link_codes.insert(id, code.to_owned());
+        drop(link_codes);
+        drop(codes);
+        drop(storage);
+        self.snapshot_if_due().await?;
+        Ok(code.to_owned())
+    }
+
+    async fn resolve_code<'a>(&self, code: &'a str) -> Result<u64, BackendError> {
+        self.codes
+            .read()
+            .await
+            .get(code)
+            .copied()
+            .ok_or(BackendError::NotFound)
+    }
+
+    async fn code(&self, id: u64) -> Result<String, BackendError> {
+        self.link_codes
+            .read()
+            .await
+            .get(&id)
+            .cloned()
+            .ok_or(BackendError::NotFound)
+    }
+
+    async fn set_metadata<'a>(&self, id: u64, metadata: &'a Metadata) -> Result<(), BackendError> {
+        let mut storage = self.storage.write().await;
+        let link = live_mut(storage.1.get_mut(&id))?;
+        let updated_at = Some(now());
+        self.journal(JournalEntry::SetMetadata {
+            id,
+            metadata: metadata.clone(),
+            updated_at,
+        })
+        .await?;
+        link.metadata = metadata.clone();
+        link.updated_at = updated_at;
+        drop(storage);
+        self.snapshot_if_due().await
     }
 }
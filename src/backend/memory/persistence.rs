@@ -0,0 +1,408 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Deserializer, Serialize};
+use tokio::{
+    fs::{self, File, OpenOptions},
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    sync::Mutex,
+};
+use tracing::{info, warn};
+
+use crate::backend::{BackendError, Link, Metadata, Revision};
+
+impl From<std::io::Error> for BackendError {
+    fn from(error: std::io::Error) -> Self {
+        BackendError::Internal(Box::new(error))
+    }
+}
+
+impl From<serde_json::Error> for BackendError {
+    fn from(error: serde_json::Error) -> Self {
+        BackendError::Internal(Box::new(error))
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "op")]
+pub(super) enum JournalEntry {
+    Store {
+        id: u64,
+        url: String,
+        #[serde(default)]
+        created_at: Option<DateTime<Utc>>,
+        /// Alias created with the link, in the same entry so neither is
+        /// replayed without the other
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        alias: Option<String>,
+    },
+    Update {
+        id: u64,
+        url: String,
+        #[serde(default)]
+        updated_at: Option<DateTime<Utc>>,
+    },
+    /// Permanent removal, written by purges
+    Delete {
+        id: u64,
+    },
+    Tombstone {
+        id: u64,
+        deleted_at: DateTime<Utc>,
+    },
+    Undelete {
+        id: u64,
+    },
+    Click {
+        id: u64,
+        ts: i64,
+    },
+    Restore {
+        id: u64,
+        link: Link,
+        #[serde(default)]
+        history: Vec<Revision>,
+        stat: BTreeMap<i64, u64>,
+    },
+    SetMetadata {
+        id: u64,
+        metadata: Metadata,
+        updated_at: Option<DateTime<Utc>>,
+    },
+    /// Ids allocated without storing links
+    Reserve {
+        last_id: u64,
+    },
+    Alias {
+        alias: String,
+        id: u64,
+    },
+    Code {
+        code: String,
+        id: u64,
+    },
+}
+
+/// Snapshots written before link records kept bare destinations.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StoredLink {
+    Record(Link),
+    Legacy(String),
+}
+
+fn links<'de, D: Deserializer<'de>>(deserializer: D) -> Result<BTreeMap<u64, Link>, D::Error> {
+    let links = BTreeMap::<u64, StoredLink>::deserialize(deserializer)?;
+    Ok(links
+        .into_iter()
+        .map(|(id, link)| match link {
+            StoredLink::Record(link) => (id, link),
+            StoredLink::Legacy(url) => (
+                id,
+                Link {
+                    url,
+                    version: 1,
+                    created_at: None,
+                    updated_at: None,
+                    deleted_at: None,
+                    metadata: Metadata::default(),
+                },
+            ),
+        })
+        .collect())
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub(super) struct Snapshot {
+    pub last_id: u64,
+    #[serde(deserialize_with = "links")]
+    pub links: BTreeMap<u64, Link>,
+    pub stat: HashMap<u64, BTreeMap<i64, u64>>,
+    #[serde(default)]
+    pub history: HashMap<u64, Vec<Revision>>,
+    #[serde(default)]
+    pub aliases: BTreeMap<String, u64>,
+    #[serde(default)]
+    pub codes: HashMap<String, u64>,
+}
+
+/// Point the link to `url` as a new version and append it to the history.
+/// Links stored before history was kept start it with their current
+/// destination.
+pub(super) fn record_revision(
+    history: &mut HashMap<u64, Vec<Revision>>,
+    id: u64,
+    link: &mut Link,
+    url: &str,
+    updated_at: Option<DateTime<Utc>>,
+) {
+    let revisions = history
+        .entry(id)
+        .or_insert_with(|| vec![Revision::current(link)]);
+    link.url = url.to_owned();
+    link.version += 1;
+    link.updated_at = updated_at;
+    revisions.push(Revision {
+        version: link.version,
+        url: url.to_owned(),
+        created_at: updated_at,
+    });
+}
+
+impl Snapshot {
+    fn apply(&mut self, entry: JournalEntry) {
+        match entry {
+            JournalEntry::Store {
+                id,
+                url,
+                created_at,
+                alias,
+            } => {
+                self.last_id = self.last_id.max(id);
+                if let Some(alias) = alias {
+                    self.aliases.insert(alias, id);
+                }
+                self.history.insert(
+                    id,
+                    vec![Revision {
+                        version: 1,
+                        url: url.clone(),
+                        created_at,
+                    }],
+                );
+                self.links.insert(
+                    id,
+                    Link {
+                        url,
+                        version: 1,
+                        created_at,
+                        updated_at: created_at,
+                        deleted_at: None,
+                        metadata: Metadata::default(),
+                    },
+                );
+            }
+            JournalEntry::Update {
+                id,
+                url,
+                updated_at,
+            } => {
+                if let Some(link) = self.links.get_mut(&id) {
+                    record_revision(&mut self.history, id, link, &url, updated_at);
+                }
+            }
+            JournalEntry::Delete { id } => {
+                self.links.remove(&id);
+                self.stat.remove(&id);
+                self.history.remove(&id);
+                self.aliases.retain(|_, link| *link != id);
+            }
+            JournalEntry::Tombstone { id, deleted_at } => {
+                if let Some(link) = self.links.get_mut(&id) {
+                    link.deleted_at = Some(deleted_at);
+                }
+            }
+            JournalEntry::Undelete { id } => {
+                if let Some(link) = self.links.get_mut(&id) {
+                    link.deleted_at = None;
+                }
+            }
+            JournalEntry::Click { id, ts } => {
+                if self.links.contains_key(&id) {
+                    *self.stat.entry(id).or_default().entry(ts).or_default() += 1;
+                }
+            }
+            JournalEntry::Restore {
+                id,
+                link,
+                history,
+                stat,
+            } => {
+                self.last_id = self.last_id.max(id);
+                self.history.insert(id, Revision::restored(&link, &history));
+                self.links.insert(id, link);
+                self.stat.insert(id, stat);
+            }
+            JournalEntry::SetMetadata {
+                id,
+                metadata,
+                updated_at,
+            } => {
+                if let Some(link) = self.links.get_mut(&id) {
+                    link.metadata = metadata;
+                    link.updated_at = updated_at;
+                }
+            }
+            JournalEntry::Reserve { last_id } => {
+                self.last_id = self.last_id.max(last_id);
+            }
+            JournalEntry::Alias { alias, id } => {
+                self.aliases.insert(alias, id);
+            }
+            JournalEntry::Code { code, id } => {
+                self.codes.insert(code, id);
+            }
+        }
+    }
+}
+
+impl JournalEntry {
+    /// Whether the entry has to be on disk before the operation returns.
+    /// Clicks are synced with the next entry which is.
+    fn is_durable(&self) -> bool {
+        !matches!(self, JournalEntry::Click { .. })
+    }
+}
+
+struct Journal {
+    file: File,
+    last_snapshot: Instant,
+    /// Number of entries appended since opening
+    written: u64,
+}
+
+/// Handle of the journal file used for syncs, so appends continue while
+/// the disk is busy.
+struct JournalSync {
+    file: File,
+    /// Number of entries known to be on disk
+    synced: u64,
+}
+
+/// Append-only journal of write operations compacted into periodic snapshots.
+///
+/// Every write operation is appended to the journal before it is
+/// applied. Once `snapshot_interval` has elapsed the whole state is written to
+/// the snapshot file and the journal is truncated, either on the next write or
+/// by the periodic task. On startup the snapshot is
+/// loaded and the journal replayed on top of it.
+///
+/// Appends are committed in groups: a sync covers every entry written
+/// before it started, so concurrent writers share one `fsync` instead of
+/// queueing for their own. Clicks do not wait for the disk at all, a crash
+/// may lose those appended after the last sync.
+pub(super) struct Persistence {
+    snapshot_path: PathBuf,
+    snapshot_interval: Duration,
+    journal: Mutex<Journal>,
+    sync: Mutex<JournalSync>,
+}
+
+impl Persistence {
+    pub async fn open(
+        journal_path: PathBuf,
+        snapshot_path: PathBuf,
+        snapshot_interval: Duration,
+    ) -> Result<(Self, Snapshot), BackendError> {
+        let mut snapshot = match fs::read(&snapshot_path).await {
+            Ok(content) => serde_json::from_slice(&content)?,
+            Err(error) if error.kind() == ErrorKind::NotFound => Snapshot::default(),
+            Err(error) => return Err(error.into()),
+        };
+        let replayed = Self::replay(&journal_path, &mut snapshot).await?;
+        info!(
+            "Restored {} links from snapshot and {} journal entries",
+            snapshot.links.len(),
+            replayed
+        );
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&journal_path)
+            .await?;
+        let persistence = Self {
+            snapshot_path,
+            snapshot_interval,
+            sync: Mutex::new(JournalSync {
+                file: file.try_clone().await?,
+                synced: 0,
+            }),
+            journal: Mutex::new(Journal {
+                file,
+                last_snapshot: Instant::now(),
+                written: 0,
+            }),
+        };
+        persistence.write_snapshot(&snapshot).await?;
+        Ok((persistence, snapshot))
+    }
+
+    async fn replay(journal_path: &Path, snapshot: &mut Snapshot) -> Result<usize, BackendError> {
+        let file = match File::open(journal_path).await {
+            Ok(file) => file,
+            Err(error) if error.kind() == ErrorKind::NotFound => return Ok(0),
+            Err(error) => return Err(error.into()),
+        };
+        let mut lines = BufReader::new(file).lines();
+        let mut replayed = 0;
+        while let Some(line) = lines.next_line().await? {
+            match serde_json::from_str(&line) {
+                Ok(entry) => {
+                    snapshot.apply(entry);
+                    replayed += 1;
+                }
+                Err(error) => {
+                    warn!("Skip malformed journal entry: {}", error);
+                }
+            }
+        }
+        Ok(replayed)
+    }
+
+    pub async fn append(&self, entry: &JournalEntry) -> Result<(), BackendError> {
+        let mut line = serde_json::to_vec(entry)?;
+        line.push(b'\n');
+        let position = {
+            let mut journal = self.journal.lock().await;
+            journal.file.write_all(&line).await?;
+            // Hand the write to the OS, the sync goes through another handle
+            journal.file.flush().await?;
+            journal.written += 1;
+            journal.written
+        };
+        if !entry.is_durable() {
+            return Ok(());
+        }
+        let mut sync = self.sync.lock().await;
+        if sync.synced >= position {
+            // Synced by a concurrent append
+            return Ok(());
+        }
+        let written = self.journal.lock().await.written;
+        sync.file.sync_data().await?;
+        sync.synced = written;
+        Ok(())
+    }
+
+    pub fn snapshot_interval(&self) -> Duration {
+        self.snapshot_interval
+    }
+
+    pub async fn snapshot_due(&self) -> bool {
+        self.journal.lock().await.last_snapshot.elapsed() >= self.snapshot_interval
+    }
+
+    pub async fn write_snapshot(&self, snapshot: &Snapshot) -> Result<(), BackendError> {
+        let mut journal = self.journal.lock().await;
+        let temporary = self.snapshot_path.with_extension("tmp");
+        let mut file = File::create(&temporary).await?;
+        file.write_all(&serde_json::to_vec(snapshot)?).await?;
+        file.sync_all().await?;
+        fs::rename(&temporary, &self.snapshot_path).await?;
+        // The rename has to be durable before the journal is dropped
+        let directory = match self.snapshot_path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        File::open(directory).await?.sync_all().await?;
+        journal.file.set_len(0).await?;
+        journal.file.sync_all().await?;
+        journal.last_snapshot = Instant::now();
+        Ok(())
+    }
+}
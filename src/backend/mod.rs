@@ -1,29 +1,608 @@
-use std::{error::Error, fmt::Debug};
+use std::{error::Error, fmt::Debug, sync::Arc};
 
 use async_trait::async_trait;
 use chrono::{DateTime, Utc};
 use thiserror::Error;
 
+pub use self::link::{Link, Metadata, Revision};
+
+pub mod allocator;
+pub mod cached;
+pub mod chaos;
+pub mod conformance;
+mod link;
 pub mod memory;
+pub mod postgres;
+pub mod redb;
 pub mod redis;
+pub mod sharded;
+pub mod sqlite;
 
 #[derive(Error, Debug)]
 pub enum BackendError {
     #[error("Shorten not found")]
     NotFound,
+    #[error("Shorten already exists")]
+    AlreadyExists,
     #[error(transparent)]
     Internal(Box<dyn Error + Send + Sync>),
     #[error("Datetime overflow")]
     DateTimeOverflow,
     #[error("Unsupported backend version")]
     UnsupportedVersion,
+    #[error("Shorten version mismatch")]
+    VersionMismatch,
+    #[error("Shorten deleted")]
+    Deleted,
 }
 
+/// Click counters grouped by click time, as exported by [`Backend::clicks`].
+pub type Clicks = Vec<(DateTime<Utc>, u64)>;
+
 #[async_trait]
 pub trait Backend {
+    /// Store a link under the next id of the storage counter.
     async fn store<'a>(&self, url: &'a str) -> Result<u64, BackendError>;
-    async fn retrive(&self, id: u64) -> Result<String, BackendError>;
+
+    /// Take `count` ids from the storage counter without storing links,
+    /// in ascending order.
+    async fn allocate_ids(&self, count: u64) -> Result<Vec<u64>, BackendError>;
+
+    /// Make sure the storage counter never allocates `id` or below. A
+    /// counter already past `id` is left alone.
+    async fn reserve_until(&self, id: u64) -> Result<(), BackendError>;
+
+    /// Store a link under an id allocated elsewhere. Fails with
+    /// [`BackendError::AlreadyExists`] when the id is taken, tombstones
+    /// included. Ids of the storage counter never collide with it afterwards.
+    async fn store_with_id<'a>(&self, id: u64, url: &'a str) -> Result<(), BackendError>;
+
+    /// Destination with its version, recording a click. Stored links start
+    /// at version 1, every update increments it.
+    async fn retrive_versioned(&self, id: u64) -> Result<(String, u64), BackendError>;
+
     async fn stat(&self, id: u64, since: Option<DateTime<Utc>>) -> Result<u64, BackendError>;
-    async fn update<'a>(&self, id: u64, url: &'a str) -> Result<(), BackendError>;
+
+    /// Replace the destination and return the new version. With `expected`
+    /// set the update fails with [`BackendError::VersionMismatch`] unless the
+    /// link is at that version.
+    async fn update_versioned<'a>(
+        &self,
+        id: u64,
+        url: &'a str,
+        expected: Option<u64>,
+    ) -> Result<u64, BackendError>;
+
+    /// Turn the link into a tombstone. Lookups and changes fail with
+    /// [`BackendError::Deleted`] until it is undeleted or purged, click
+    /// statistics and the record stay available.
     async fn delete(&self, id: u64) -> Result<(), BackendError>;
+
+    /// Bring a tombstoned link back. Live links are left as they are.
+    async fn undelete(&self, id: u64) -> Result<(), BackendError>;
+
+    /// Permanently remove links tombstoned at or before `before` with their
+    /// statistics, history and aliases. Returns number of removed links.
+    ///
+    /// Short codes are kept: they keep resolving to the purged id, which is
+    /// never allocated again, so a printed code is never drawn for another
+    /// link, see [`Backend::store_code`].
+    async fn purge(&self, before: DateTime<Utc>) -> Result<u64, BackendError>;
+
+    /// Whole record of the link, without recording a click.
+    async fn info(&self, id: u64) -> Result<Link, BackendError>;
+
+    /// Replace metadata of the link. The destination version is kept.
+    async fn set_metadata<'a>(&self, id: u64, metadata: &'a Metadata) -> Result<(), BackendError>;
+
+    /// Every destination the link had, ordered by version. The last one is
+    /// the current destination.
+    async fn history(&self, id: u64) -> Result<Vec<Revision>, BackendError>;
+
+    /// Make `alias` another name of the link, tombstones included. Fails
+    /// with [`BackendError::AlreadyExists`] when the alias names another
+    /// link. Aliases are removed when their link is purged.
+    async fn store_alias<'a>(&self, alias: &'a str, id: u64) -> Result<(), BackendError>;
+
+    /// Store a link under `id` as [`Backend::store_with_id`] does, named
+    /// `alias` from the start. Fails with [`BackendError::AlreadyExists`]
+    /// storing nothing when the id or the alias is taken.
+    async fn store_with_alias<'a>(
+        &self,
+        id: u64,
+        url: &'a str,
+        alias: &'a str,
+    ) -> Result<(), BackendError>;
+
+    /// Id of the link named `alias`.
+    async fn resolve_alias<'a>(&self, alias: &'a str) -> Result<u64, BackendError>;
+
+    /// Aliases of the link in lexicographic order, empty for unknown links.
+    async fn aliases(&self, id: u64) -> Result<Vec<String>, BackendError>;
+
+    /// Make `code` the short code of the link unless it already has one,
+    /// the code of the link is returned. Fails with
+    /// [`BackendError::AlreadyExists`] when the code belongs to another link.
+    /// The link is not checked, codes are never released so a printed code
+    /// never leads to another link.
+    async fn store_code<'a>(&self, id: u64, code: &'a str) -> Result<String, BackendError>;
+
+    /// Id of the link with short code `code`.
+    async fn resolve_code<'a>(&self, code: &'a str) -> Result<u64, BackendError>;
+
+    /// Short code of the link, if one is stored.
+    async fn code(&self, id: u64) -> Result<String, BackendError>;
+
+    /// Make the destination of `version` current again as a new version,
+    /// which is returned. Fails with [`BackendError::NotFound`] for versions
+    /// absent in the history; `expected` is checked as in
+    /// [`Backend::update_versioned`].
+    async fn rollback(
+        &self,
+        id: u64,
+        version: u64,
+        expected: Option<u64>,
+    ) -> Result<u64, BackendError> {
+        let revision = self
+            .history(id)
+            .await?
+            .into_iter()
+            .find(|revision| revision.version == version)
+            .ok_or(BackendError::NotFound)?;
+        self.update_versioned(id, &revision.url, expected).await
+    }
+
+    async fn retrive(&self, id: u64) -> Result<String, BackendError> {
+        self.retrive_versioned(id).await.map(|(url, _)| url)
+    }
+
+    async fn update<'a>(&self, id: u64, url: &'a str) -> Result<(), BackendError> {
+        self.update_versioned(id, url, None).await.map(|_| ())
+    }
+
+    /// Live links pointing to `url`, ordered by id.
+    async fn find<'a>(&self, url: &'a str) -> Result<Vec<u64>, BackendError>;
+
+    /// Live links with id greater than `after` ordered by id, at most
+    /// `limit` of them.
+    async fn list(
+        &self,
+        after: Option<u64>,
+        limit: usize,
+    ) -> Result<Vec<(u64, String)>, BackendError>;
+
+    /// Links with id greater than `after` ordered by id as [`Backend::list`]
+    /// does, tombstones included.
+    async fn list_with_tombstones(
+        &self,
+        after: Option<u64>,
+        limit: usize,
+    ) -> Result<Vec<(u64, String)>, BackendError>;
+
+    /// All click statistics kept for the link.
+    async fn clicks(&self, id: u64) -> Result<Clicks, BackendError>;
+
+    /// Write the link record under the given id with its history and click
+    /// statistics, replacing existing ones. An empty `history` keeps only the
+    /// current revision. Ids allocated afterwards never collide with it.
+    async fn restore<'a>(
+        &self,
+        id: u64,
+        link: &'a Link,
+        history: &'a [Revision],
+        clicks: &'a [(DateTime<Utc>, u64)],
+    ) -> Result<(), BackendError>;
+
+    /// Record a click without returning the destination. The default looks
+    /// the destination up, backends override it with a plain write.
+    async fn click(&self, id: u64) -> Result<(), BackendError> {
+        self.retrive(id).await.map(|_| ())
+    }
+
+    /// Store every url, results are in the order of `urls`. The outer error
+    /// means the whole batch failed.
+    async fn store_many<'a>(
+        &self,
+        urls: &'a [String],
+    ) -> Result<Vec<Result<u64, BackendError>>, BackendError> {
+        let mut results = Vec::with_capacity(urls.len());
+        for url in urls {
+            results.push(self.store(url).await);
+        }
+        Ok(results)
+    }
+
+    /// Destinations of `ids` recording a click for each found link.
+    async fn retrive_many<'a>(
+        &self,
+        ids: &'a [u64],
+    ) -> Result<Vec<Result<String, BackendError>>, BackendError> {
+        let mut results = Vec::with_capacity(ids.len());
+        for id in ids {
+            results.push(self.retrive(*id).await);
+        }
+        Ok(results)
+    }
+
+    async fn delete_many<'a>(
+        &self,
+        ids: &'a [u64],
+    ) -> Result<Vec<Result<(), BackendError>>, BackendError> {
+        let mut results = Vec::with_capacity(ids.len());
+        for id in ids {
+            results.push(self.delete(*id).await);
+        }
+        Ok(results)
+    }
+
+    /// [`Backend::store_with_id`] for every link, results are in the order
+    /// of `links`.
+    async fn store_many_with_ids<'a>(
+        &self,
+        links: &'a [(u64, &'a str)],
+    ) -> Result<Vec<Result<(), BackendError>>, BackendError> {
+        let mut results = Vec::with_capacity(links.len());
+        for (id, url) in links {
+            results.push(self.store_with_id(*id, url).await);
+        }
+        Ok(results)
+    }
+}
+
+#[async_trait]
+impl<B> Backend for Box<B>
+where
+    B: Backend + Send + Sync + ?Sized,
+{
+    async fn store<'a>(&self, url: &'a str) -> Result<u64, BackendError> {
+        (**self).store(url).await
+    }
+
+    async fn allocate_ids(&self, count: u64) -> Result<Vec<u64>, BackendError> {
+        (**self).allocate_ids(count).await
+    }
+
+    async fn reserve_until(&self, id: u64) -> Result<(), BackendError> {
+        (**self).reserve_until(id).await
+    }
+
+    async fn store_with_id<'a>(&self, id: u64, url: &'a str) -> Result<(), BackendError> {
+        (**self).store_with_id(id, url).await
+    }
+
+    async fn retrive_versioned(&self, id: u64) -> Result<(String, u64), BackendError> {
+        (**self).retrive_versioned(id).await
+    }
+
+    async fn stat(&self, id: u64, since: Option<DateTime<Utc>>) -> Result<u64, BackendError> {
+        (**self).stat(id, since).await
+    }
+
+    async fn update_versioned<'a>(
+        &self,
+        id: u64,
+        url: &'a str,
+        expected: Option<u64>,
+    ) -> Result<u64, BackendError> {
+        (**self).update_versioned(id, url, expected).await
+    }
+
+    async fn retrive(&self, id: u64) -> Result<String, BackendError> {
+        (**self).retrive(id).await
+    }
+
+    async fn update<'a>(&self, id: u64, url: &'a str) -> Result<(), BackendError> {
+        (**self).update(id, url).await
+    }
+
+    async fn delete(&self, id: u64) -> Result<(), BackendError> {
+        (**self).delete(id).await
+    }
+
+    async fn click(&self, id: u64) -> Result<(), BackendError> {
+        (**self).click(id).await
+    }
+
+    async fn undelete(&self, id: u64) -> Result<(), BackendError> {
+        (**self).undelete(id).await
+    }
+
+    async fn purge(&self, before: DateTime<Utc>) -> Result<u64, BackendError> {
+        (**self).purge(before).await
+    }
+
+    async fn list(
+        &self,
+        after: Option<u64>,
+        limit: usize,
+    ) -> Result<Vec<(u64, String)>, BackendError> {
+        (**self).list(after, limit).await
+    }
+
+    async fn list_with_tombstones(
+        &self,
+        after: Option<u64>,
+        limit: usize,
+    ) -> Result<Vec<(u64, String)>, BackendError> {
+        (**self).list_with_tombstones(after, limit).await
+    }
+
+    async fn find<'a>(&self, url: &'a str) -> Result<Vec<u64>, BackendError> {
+        (**self).find(url).await
+    }
+
+    async fn clicks(&self, id: u64) -> Result<Clicks, BackendError> {
+        (**self).clicks(id).await
+    }
+
+    async fn restore<'a>(
+        &self,
+        id: u64,
+        link: &'a Link,
+        history: &'a [Revision],
+        clicks: &'a [(DateTime<Utc>, u64)],
+    ) -> Result<(), BackendError> {
+        (**self).restore(id, link, history, clicks).await
+    }
+
+    async fn info(&self, id: u64) -> Result<Link, BackendError> {
+        (**self).info(id).await
+    }
+
+    async fn set_metadata<'a>(&self, id: u64, metadata: &'a Metadata) -> Result<(), BackendError> {
+        (**self).set_metadata(id, metadata).await
+    }
+
+    async fn history(&self, id: u64) -> Result<Vec<Revision>, BackendError> {
+        (**self).history(id).await
+    }
+
+    async fn store_alias<'a>(&self, alias: &'a str, id: u64) -> Result<(), BackendError> {
+        (**self).store_alias(alias, id).await
+    }
+
+    async fn store_with_alias<'a>(
+        &self,
+        id: u64,
+        url: &'a str,
+        alias: &'a str,
+    ) -> Result<(), BackendError> {
+        (**self).store_with_alias(id, url, alias).await
+    }
+
+    async fn resolve_alias<'a>(&self, alias: &'a str) -> Result<u64, BackendError> {
+        (**self).resolve_alias(alias).await
+    }
+
+    async fn aliases(&self, id: u64) -> Result<Vec<String>, BackendError> {
+        (**self).aliases(id).await
+    }
+
+    async fn store_code<'a>(&self, id: u64, code: &'a str) -> Result<String, BackendError> {
+        (**self).store_code(id, code).await
+    }
+
+    async fn resolve_code<'a>(&self, code: &'a str) -> Result<u64, BackendError> {
+        (**self).resolve_code(code).await
+    }
+
+    async fn code(&self, id: u64) -> Result<String, BackendError> {
+        (**self).code(id).await
+    }
+
+    async fn rollback(
+        &self,
+        id: u64,
+        version: u64,
+        expected: Option<u64>,
+    ) -> Result<u64, BackendError> {
+        (**self).rollback(id, version, expected).await
+    }
+
+    async fn store_many<'a>(
+        &self,
+        urls: &'a [String],
+    ) -> Result<Vec<Result<u64, BackendError>>, BackendError> {
+        (**self).store_many(urls).await
+    }
+
+    async fn retrive_many<'a>(
+        &self,
+        ids: &'a [u64],
+    ) -> Result<Vec<Result<String, BackendError>>, BackendError> {
+        (**self).retrive_many(ids).await
+    }
+
+    async fn delete_many<'a>(
+        &self,
+        ids: &'a [u64],
+    ) -> Result<Vec<Result<(), BackendError>>, BackendError> {
+        (**self).delete_many(ids).await
+    }
+
+    async fn store_many_with_ids<'a>(
+        &self,
+        links: &'a [(u64, &'a str)],
+    ) -> Result<Vec<Result<(), BackendError>>, BackendError> {
+        (**self).store_many_with_ids(links).await
+    }
+}
+
+#[async_trait]
+impl<B> Backend for Arc<B>
+where
+    B: Backend + Send + Sync + ?Sized,
+{
+    async fn store<'a>(&self, url: &'a str) -> Result<u64, BackendError> {
+        (**self).store(url).await
+    }
+
+    async fn allocate_ids(&self, count: u64) -> Result<Vec<u64>, BackendError> {
+        (**self).allocate_ids(count).await
+    }
+
+    async fn reserve_until(&self, id: u64) -> Result<(), BackendError> {
+        (**self).reserve_until(id).await
+    }
+
+    async fn store_with_id<'a>(&self, id: u64, url: &'a str) -> Result<(), BackendError> {
+        (**self).store_with_id(id, url).await
+    }
+
+    async fn retrive_versioned(&self, id: u64) -> Result<(String, u64), BackendError> {
+        (**self).retrive_versioned(id).await
+    }
+
+    async fn stat(&self, id: u64, since: Option<DateTime<Utc>>) -> Result<u64, BackendError> {
+        (**self).stat(id, since).await
+    }
+
+    async fn update_versioned<'a>(
+        &self,
+        id: u64,
+        url: &'a str,
+        expected: Option<u64>,
+    ) -> Result<u64, BackendError> {
+        (**self).update_versioned(id, url, expected).await
+    }
+
+    async fn retrive(&self, id: u64) -> Result<String, BackendError> {
+        (**self).retrive(id).await
+    }
+
+    async fn update<'a>(&self, id: u64, url: &'a str) -> Result<(), BackendError> {
+        (**self).update(id, url).await
+    }
+
+    async fn delete(&self, id: u64) -> Result<(), BackendError> {
+        (**self).delete(id).await
+    }
+
+    async fn click(&self, id: u64) -> Result<(), BackendError> {
+        (**self).click(id).await
+    }
+
+    async fn undelete(&self, id: u64) -> Result<(), BackendError> {
+        (**self).undelete(id).await
+    }
+
+    async fn purge(&self, before: DateTime<Utc>) -> Result<u64, BackendError> {
+        (**self).purge(before).await
+    }
+
+    async fn list(
+        &self,
+        after: Option<u64>,
+        limit: usize,
+    ) -> Result<Vec<(u64, String)>, BackendError> {
+        (**self).list(after, limit).await
+    }
+
+    async fn list_with_tombstones(
+        &self,
+        after: Option<u64>,
+        limit: usize,
+    ) -> Result<Vec<(u64, String)>, BackendError> {
+        (**self).list_with_tombstones(after, limit).await
+    }
+
+    async fn find<'a>(&self, url: &'a str) -> Result<Vec<u64>, BackendError> {
+        (**self).find(url).await
+    }
+
+    async fn clicks(&self, id: u64) -> Result<Clicks, BackendError> {
+        (**self).clicks(id).await
+    }
+
+    async fn restore<'a>(
+        &self,
+        id: u64,
+        link: &'a Link,
+        history: &'a [Revision],
+        clicks: &'a [(DateTime<Utc>, u64)],
+    ) -> Result<(), BackendError> {
+        (**self).restore(id, link, history, clicks).await
+    }
+
+    async fn info(&self, id: u64) -> Result<Link, BackendError> {
+        (**self).info(id).await
+    }
+
+    async fn set_metadata<'a>(&self, id: u64, metadata: &'a Metadata) -> Result<(), BackendError> {
+        (**self).set_metadata(id, metadata).await
+    }
+
+    async fn history(&self, id: u64) -> Result<Vec<Revision>, BackendError> {
+        (**self).history(id).await
+    }
+
+    async fn store_alias<'a>(&self, alias: &'a str, id: u64) -> Result<(), BackendError> {
+        (**self).store_alias(alias, id).await
+    }
+
+    async fn store_with_alias<'a>(
+        &self,
+        id: u64,
+        url: &'a str,
+        alias: &'a str,
+    ) -> Result<(), BackendError> {
+        (**self).store_with_alias(id, url, alias).await
+    }
+
+    async fn resolve_alias<'a>(&self, alias: &'a str) -> Result<u64, BackendError> {
+        (**self).resolve_alias(alias).await
+    }
+
+    async fn aliases(&self, id: u64) -> Result<Vec<String>, BackendError> {
+        (**self).aliases(id).await
+    }
+
+    async fn store_code<'a>(&self, id: u64, code: &'a str) -> Result<String, BackendError> {
+        (**self).store_code(id, code).await
+    }
+
+    async fn resolve_code<'a>(&self, code: &'a str) -> Result<u64, BackendError> {
+        (**self).resolve_code(code).await
+    }
+
+    async fn code(&self, id: u64) -> Result<String, BackendError> {
+        (**self).code(id).await
+    }
+
+    async fn rollback(
+        &self,
+        id: u64,
+        version: u64,
+        expected: Option<u64>,
+    ) -> Result<u64, BackendError> {
+        (**self).rollback(id, version, expected).await
+    }
+
+    async fn store_many<'a>(
+        &self,
+        urls: &'a [String],
+    ) -> Result<Vec<Result<u64, BackendError>>, BackendError> {
+        (**self).store_many(urls).await
+    }
+
+    async fn retrive_many<'a>(
+        &self,
+        ids: &'a [u64],
+    ) -> Result<Vec<Result<String, BackendError>>, BackendError> {
+        (**self).retrive_many(ids).await
+    }
+
+    async fn delete_many<'a>(
+        &self,
+        ids: &'a [u64],
+    ) -> Result<Vec<Result<(), BackendError>>, BackendError> {
+        (**self).delete_many(ids).await
+    }
+
+    async fn store_many_with_ids<'a>(
+        &self,
+        links: &'a [(u64, &'a str)],
+    ) -> Result<Vec<Result<(), BackendError>>, BackendError> {
+        (**self).store_many_with_ids(links).await
+    }
 }
@@ -0,0 +1,782 @@
+use std::str::FromStr;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use deadpool_postgres::{Client, Manager, Pool, PoolError, Transaction};
+use tokio_postgres::NoTls;
+use tracing::info;
+
+use super::{link::now, Backend, BackendError, Clicks, Link, Metadata, Revision};
+
+/// Ordered schema migrations. Applied versions are tracked in the
+/// `schema_migrations` table, so new migrations must only be appended.
+static MIGRATIONS: &[(i32, &str)] = &[
+    (
+        1,
+        r"
+CREATE TABLE links (
+    id BIGSERIAL PRIMARY KEY,
+    url TEXT NOT NULL
+);
+CREATE TABLE clicks (
+    link_id BIGINT NOT NULL REFERENCES links (id) ON DELETE CASCADE,
+    clicked_at TIMESTAMPTZ NOT NULL
+);
+CREATE INDEX clicks_link_id_clicked_at ON clicks (link_id, clicked_at);
+",
+    ),
+    (
+        2,
+        r"
+ALTER TABLE links ADD COLUMN version BIGINT NOT NULL DEFAULT 1;
+",
+    ),
+    (
+        3,
+        r"
+ALTER TABLE links
+    ADD COLUMN created_at TIMESTAMPTZ,
+    ADD COLUMN updated_at TIMESTAMPTZ,
+    ADD COLUMN title TEXT,
+    ADD COLUMN notes TEXT,
+    ADD COLUMN owner TEXT,
+    ADD COLUMN flags TEXT[] NOT NULL DEFAULT '{}';
+",
+    ),
+    (
+        4,
+        r"
+ALTER TABLE links ADD COLUMN deleted_at TIMESTAMPTZ;
+CREATE INDEX links_deleted_at ON links (deleted_at) WHERE deleted_at IS NOT NULL;
+",
+    ),
+    (
+        5,
+        r"
+CREATE TABLE history (
+    link_id BIGINT NOT NULL REFERENCES links (id) ON DELETE CASCADE,
+    version BIGINT NOT NULL,
+    url TEXT NOT NULL,
+    created_at TIMESTAMPTZ,
+    PRIMARY KEY (link_id, version)
+);
+INSERT INTO history (link_id, version, url) SELECT id, version, url FROM links;
+",
+    ),
+    (
+        6,
+        r"
+CREATE INDEX links_url ON links USING HASH (url) WHERE deleted_at IS NULL;
+",
+    ),
+    (
+        7,
+        r"
+CREATE TABLE aliases (
+    alias TEXT PRIMARY KEY,
+    link_id BIGINT NOT NULL REFERENCES links (id) ON DELETE CASCADE
+);
+CREATE INDEX aliases_link_id ON aliases (link_id);
+",
+    ),
+    (
+        8,
+        r"
+CREATE TABLE codes (
+    code TEXT PRIMARY KEY,
+    link_id BIGINT NOT NULL UNIQUE
+);
+",
+    ),
+];
+
+/// Arbitrary key of the advisory lock held while migrations are applied,
+/// so several instances may start simultaneously.
+static MIGRATION_LOCK: i64 = 0x73686f72746c616e;
+
+static DEFAULT_STAT_PERIOD_IN_HOURS: i64 = 24;
+
+impl From<tokio_postgres::Error> for BackendError {
+    fn from(error: tokio_postgres::Error) -> Self {
+        BackendError::Internal(Box::new(error))
+    }
+}
+
+impl From<PoolError> for BackendError {
+    fn from(error: PoolError) -> Self {
+        BackendError::Internal(Box::new(error))
+    }
+}
+
+fn to_key(id: u64) -> Result<i64, BackendError> {
+    i64::try_from(id).map_err(|_| BackendError::NotFound)
+}
+
+/// Error for a link an update matched no row of: it is either missing or a
+/// tombstone.
+async fn unchanged(client: &Client, key: i64) -> Result<BackendError, BackendError> {
+    let deleted = client
+        .query_opt(
+            "SELECT deleted_at IS NOT NULL FROM links WHERE id = $1",
+            &[&key],
+        )
+        .await?
+        .map(|row| row.get::<_, bool>(0));
+    Ok(match deleted {
+        Some(true) => BackendError::Deleted,
+        Some(false) => BackendError::VersionMismatch,
+        None => BackendError::NotFound,
+    })
+}
+
+/// Insert a new link under `key` with its first revision and move the
+/// sequence past it.
+async fn insert_link(
+    transaction: &Transaction<'_>,
+    key: i64,
+    url: &str,
+) -> Result<(), BackendError> {
+    transaction
+        .query_opt(
+            "WITH link AS (
+                INSERT INTO links (id, url, created_at, updated_at) VALUES ($1, $2, $3, $3)
+                ON CONFLICT (id) DO NOTHING
+                RETURNING id
+            ),
+            revision AS (
+                INSERT INTO history (link_id, version, url, created_at)
+                SELECT id, 1, $2, $3 FROM link
+            )
+            SELECT id FROM link",
+            &[&key, &url, &now()],
+        )
+        .await?
+        .ok_or(BackendError::AlreadyExists)?;
+    transaction
+        .execute(
+            "SELECT setval(pg_get_serial_sequence('links', 'id'), GREATEST(
+                (SELECT last_value FROM links_id_seq), $1
+            ))",
+            &[&key],
+        )
+        .await?;
+    Ok(())
+}
+
+pub struct PostgresBackend {
+    pool: Pool,
+}
+
+impl PostgresBackend {
+    pub async fn new(connection: &str, pool_size: usize) -> Result<Self, BackendError> {
+        info!("Initialize Postgres backend");
+        let config = tokio_postgres::Config::from_str(connection)?;
+        let manager = Manager::new(config, NoTls);
+        let pool = Pool::builder(manager)
+            .max_size(pool_size)
+            .build()
+            .map_err(|error| BackendError::Internal(Box::new(error)))?;
+        let backend = Self { pool };
+        backend.migrate().await?;
+        Ok(backend)
+    }
+
+    async fn migrate(&self) -> Result<(), BackendError> {
+        let mut client = self.pool.get().await?;
+        let transaction = client.transaction().await?;
+        transaction
+            .execute("SELECT pg_advisory_xact_lock($1)", &[&MIGRATION_LOCK])
+            .await?;
+        transaction
+            .batch_execute(
+                "CREATE TABLE IF NOT EXISTS schema_migrations (
+                    version INTEGER PRIMARY KEY,
+                    applied_at TIMESTAMPTZ NOT NULL DEFAULT now()
+                )",
+            )
+            .await?;
+        let current: i32 = transaction
+            .query_one(
+                "SELECT COALESCE(MAX(version), 0) FROM schema_migrations",
+                &[],
+            )
+            .await?
+            .get(0);
+        for (version, migration) in MIGRATIONS.iter().filter(|(version, _)| *version > current) {
+            info!("Apply postgres migration {}", version);
+            transaction.batch_execute(migration).await?;
+            transaction
+                .execute(
+                    "INSERT INTO schema_migrations (version) VALUES ($1)",
+                    &[version],
+                )
+                .await?;
+        }
+        transaction.commit().await?;
+        Ok(())
+    }
+
+    /// Links following `after` ordered by id, tombstones only with
+    /// `tombstones` set.
+    async fn links(
+        &self,
+        after: Option<u64>,
+        limit: usize,
+        tombstones: bool,
+    ) -> Result<Vec<(u64, String)>, BackendError> {
+        let after = after.map(to_key).transpose()?.unwrap_or(0);
+        let limit = i64::try_from(limit).unwrap_or(i64::MAX);
+        let client = self.pool.get().await?;
+        let links = client
+            .query(
+                "SELECT id, url FROM links WHERE id > $1 AND ($3 OR deleted_at IS NULL)
+                ORDER BY id LIMIT $2",
+                &[&after, &limit, &tombstones],
+            )
+            .await?
+            .into_iter()
+            .map(|row| (row.get::<_, i64>(0) as u64, row.get(1)))
+            .collect();
+        Ok(links)
+    }
+}
+
+#[async_trait]
+impl Backend for PostgresBackend {
+    async fn store<'a>(&self, url: &'a str) -> Result<u64, BackendError> {
+        let client = self.pool.get().await?;
+        let id: i64 = client
+            .query_one(
+                "WITH link AS (
+                    INSERT INTO links (url, created_at, updated_at) VALUES ($1, $2, $2)
+                    RETURNING id
+                ),
+                revision AS (
+                    INSERT INTO history (link_id, version, url, created_at)
+                    SELECT id, 1, $1, $2 FROM link
+                )
+                SELECT id FROM link",
+                &[&url, &now()],
+            )
+            .await?
+            .get(0);
+        Ok(id as u64)
+    }
+
+    async fn allocate_ids(&self, count: u64) -> Result<Vec<u64>, BackendError> {
+        let client = self.pool.get().await?;
+        let count = count as i64;
+        let rows = client
+            .query(
+                "SELECT nextval(pg_get_serial_sequence('links', 'id'))
+                FROM generate_series(1, $1::BIGINT)",
+                &[&count],
+            )
+            .await?;
+        let mut ids = rows
+            .iter()
+            .map(|row| row.get::<_, i64>(0) as u64)
+            .collect::<Vec<_>>();
+        ids.sort_unstable();
+        Ok(ids)
+    }
+
+    async fn reserve_until(&self, id: u64) -> Result<(), BackendError> {
+        if id == 0 {
+            return Ok(());
+        }
+        let key = to_key(id)?;
+        let client = self.pool.get().await?;
+        client
+            .execute(
+                "SELECT setval(pg_get_serial_sequence('links', 'id'), $1)
+                FROM links_id_seq WHERE NOT is_called OR last_value < $1",
+                &[&key],
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn store_with_id<'a>(&self, id: u64, url: &'a str) -> Result<(), BackendError> {
+        let key = to_key(id)?;
+        let mut client = self.pool.get().await?;
+        let transaction = client.transaction().await?;
+        insert_link(&transaction, key, url).await?;
+        transaction.commit().await?;
+        Ok(())
+    }
+
+    async fn retrive_versioned(&self, id: u64) -> Result<(String, u64), BackendError> {
+        let key = to_key(id)?;
+        let client = self.pool.get().await?;
+        let row = client
+            .query_opt(
+                "WITH link AS (SELECT id, url, version, deleted_at FROM links WHERE id = $1),
+                click AS (
+                    INSERT INTO clicks (link_id, clicked_at)
+                    SELECT id, $2 FROM link WHERE deleted_at IS NULL
+                )
+                SELECT url, version, deleted_at IS NOT NULL FROM link",
+                &[&key, &Utc::now()],
+            )
+            .await?
+            .ok_or(BackendError::NotFound)?;
+        if row.get::<_, bool>(2) {
+            return Err(BackendError::Deleted);
+        }
+        Ok((row.get(0), row.get::<_, i64>(1) as u64))
+    }
+
+    async fn click(&self, id: u64) -> Result<(), BackendError> {
+        let key = to_key(id)?;
+        let client = self.pool.get().await?;
+        let row = client
+            .query_opt(
+                "WITH link AS (SELECT id, deleted_at FROM links WHERE id = $1),
+                click AS (
+                    INSERT INTO clicks (link_id, clicked_at)
+                    SELECT id, $2 FROM link WHERE deleted_at IS NULL
+                )
+                SELECT deleted_at IS NOT NULL FROM link",
+                &[&key, &Utc::now()],
+            )
+            .await?
+            .ok_or(BackendError::NotFound)?;
+        if row.get::<_, bool>(0) {
+            return Err(BackendError::Deleted);
+        }
+        Ok(())
+    }
+
+    async fn stat(&self, id: u64, since: Option<DateTime<Utc>>) -> Result<u64, BackendError> {
+        let key = to_key(id)?;
+        let now = Utc::now();
+        let since = since
+            .or_else(|| now.checked_sub_signed(Duration::hours(DEFAULT_STAT_PERIOD_IN_HOURS)))
+            .ok_or(BackendError::DateTimeOverflow)?;
+        let client = self.pool.get().await?;
+        let count: i64 = client
+            .query_opt(
+                "SELECT (
+                    SELECT COUNT(*) FROM clicks
+                    WHERE link_id = links.id AND clicked_at BETWEEN $2 AND $3
+                ) FROM links WHERE id = $1",
+                &[&key, &since, &now],
+            )
+            .await?
+            .ok_or(BackendError::NotFound)?
+            .get(0);
+        Ok(count as u64)
+    }
+
+    async fn update_versioned<'a>(
+        &self,
+        id: u64,
+        url: &'a str,
+        expected: Option<u64>,
+    ) -> Result<u64, BackendError> {
+        let key = to_key(id)?;
+        let expected = expected.map(|version| version as i64);
+        let client = self.pool.get().await?;
+        // The live link is updated only when the version matches, the
+        // subquery tells a missing link or a tombstone from a version conflict.
+        let row = client
+            .query_one(
+                "WITH updated AS (
+                    UPDATE links SET url = $2, version = version + 1, updated_at = $4
+                    WHERE id = $1 AND deleted_at IS NULL
+                        AND ($3::BIGINT IS NULL OR version = $3)
+                    RETURNING version
+                ),
+                revision AS (
+                    INSERT INTO history (link_id, version, url, created_at)
+                    SELECT $1, version, $2, $4 FROM updated
+                    ON CONFLICT (link_id, version) DO UPDATE SET
+                        url = EXCLUDED.url,
+                        created_at = EXCLUDED.created_at
+                )
+                SELECT
+                    (SELECT version FROM updated),
+                    (SELECT deleted_at IS NOT NULL FROM links WHERE id = $1)",
+                &[&key, &url, &expected, &now()],
+            )
+            .await?;
+        match (row.get::<_, Option<i64>>(0), row.get::<_, Option<bool>>(1)) {
+            (Some(version), _) => Ok(version as u64),
+            (None, Some(true)) => Err(BackendError::Deleted),
+            (None, Some(false)) => Err(BackendError::VersionMismatch),
+            (None, None) => Err(BackendError::NotFound),
+        }
+    }
+
+    async fn delete(&self, id: u64) -> Result<(), BackendError> {
+        let key = to_key(id)?;
+        let client = self.pool.get().await?;
+        let deleted = client
+            .execute(
+                "UPDATE links SET deleted_at = $2 WHERE id = $1 AND deleted_at IS NULL",
+                &[&key, &now()],
+            )
+            .await?;
+        if deleted == 0 {
+            Err(unchanged(&client, key).await?)
+        } else {
+            Ok(())
+        }
+    }
+
+    async fn undelete(&self, id: u64) -> Result<(), BackendError> {
+        let key = to_key(id)?;
+        let client = self.pool.get().await?;
+        let updated = client
+            .execute("UPDATE links SET deleted_at = NULL WHERE id = $1", &[&key])
+            .await?;
+        if updated == 0 {
+            Err(BackendError::NotFound)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Clicks and history of purged links are removed by the foreign key
+    /// cascade.
+    async fn purge(&self, before: DateTime<Utc>) -> Result<u64, BackendError> {
+        let client = self.pool.get().await?;
+        // Dependent rows cascade, codes have no foreign key and stay taken,
+        // see `Backend::purge`
+        let purged = client
+            .execute("DELETE FROM links WHERE deleted_at <= $1", &[&before])
+            .await?;
+        Ok(purged)
+    }
+
+    async fn list(
+        &self,
+        after: Option<u64>,
+        limit: usize,
+    ) -> Result<Vec<(u64, String)>, BackendError> {
+        self.links(after, limit, false).await
+    }
+
+    async fn list_with_tombstones(
+        &self,
+        after: Option<u64>,
+        limit: usize,
+    ) -> Result<Vec<(u64, String)>, BackendError> {
+        self.links(after, limit, true).await
+    }
+
+    async fn find<'a>(&self, url: &'a str) -> Result<Vec<u64>, BackendError> {
+        let client = self.pool.get().await?;
+        let ids = client
+            .query(
+                "SELECT id FROM links WHERE url = $1 AND deleted_at IS NULL ORDER BY id",
+                &[&url],
+            )
+            .await?
+            .into_iter()
+            .map(|row| row.get::<_, i64>(0) as u64)
+            .collect();
+        Ok(ids)
+    }
+
+    async fn clicks(&self, id: u64) -> Result<Clicks, BackendError> {
+        let key = to_key(id)?;
+        let client = self.pool.get().await?;
+        client
+            .query_opt("SELECT 1 FROM links WHERE id = $1", &[&key])
+            .await?
+            .ok_or(BackendError::NotFound)?;
+        let clicks = client
+            .query(
+                "SELECT clicked_at, COUNT(*) FROM clicks WHERE link_id = $1
+                GROUP BY clicked_at ORDER BY clicked_at",
+                &[&key],
+            )
+            .await?
+            .into_iter()
+            .map(|row| (row.get(0), row.get::<_, i64>(1) as u64))
+            .collect();
+        Ok(clicks)
+    }
+
+    async fn restore<'a>(
+        &self,
+        id: u64,
+        link: &'a Link,
+        history: &'a [Revision],
+        clicks: &'a [(DateTime<Utc>, u64)],
+    ) -> Result<(), BackendError> {
+        let key = to_key(id)?;
+        let version = link.version as i64;
+        let flags = link.metadata.flags.iter().collect::<Vec<_>>();
+        let mut client = self.pool.get().await?;
+        let transaction = client.transaction().await?;
+        transaction
+            .execute(
+                "INSERT INTO links (
+                    id, url, version, created_at, updated_at, deleted_at,
+                    title, notes, owner, flags
+                )
+                VALUES ($1, $2, $3, $4, $5, $10, $6, $7, $8, $9)
+                ON CONFLICT (id) DO UPDATE SET
+                    url = EXCLUDED.url,
+                    version = EXCLUDED.version,
+                    created_at = EXCLUDED.created_at,
+                    updated_at = EXCLUDED.updated_at,
+                    deleted_at = EXCLUDED.deleted_at,
+                    title = EXCLUDED.title,
+                    notes = EXCLUDED.notes,
+                    owner = EXCLUDED.owner,
+                    flags = EXCLUDED.flags",
+                &[
+                    &key,
+                    &link.url,
+                    &version,
+                    &link.created_at,
+                    &link.updated_at,
+                    &link.metadata.title,
+                    &link.metadata.notes,
+                    &link.metadata.owner,
+                    &flags,
+                    &link.deleted_at,
+                ],
+            )
+            .await?;
+        transaction
+            .execute("DELETE FROM history WHERE link_id = $1", &[&key])
+            .await?;
+        for revision in Revision::restored(link, history) {
+            transaction
+                .execute(
+                    "INSERT INTO history (link_id, version, url, created_at)
+                    VALUES ($1, $2, $3, $4)",
+                    &[
+                        &key,
+                        &(revision.version as i64),
+                        &revision.url,
+                        &revision.created_at,
+                    ],
+                )
+                .await?;
+        }
+        transaction
+            .execute("DELETE FROM clicks WHERE link_id = $1", &[&key])
+            .await?;
+        for (clicked_at, counter) in clicks {
+            let counter = *counter as i64;
+            transaction
+                .execute(
+                    "INSERT INTO clicks (link_id, clicked_at)
+                    SELECT $1, $2 FROM generate_series(1, $3::BIGINT)",
+                    &[&key, clicked_at, &counter],
+                )
+                .await?;
+        }
+        transaction
+            .execute(
+                "SELECT setval(pg_get_serial_sequence('links', 'id'), GREATEST(
+                    (SELECT last_value FROM links_id_seq), $1
+                ))",
+                &[&key],
+            )
+            .await?;
+        transaction.commit().await?;
+        Ok(())
+    }
+
+    async fn info(&self, id: u64) -> Result<Link, BackendError> {
+        let key = to_key(id)?;
+        let client = self.pool.get().await?;
+        let row = client
+            .query_opt(
+                "SELECT url, version, created_at, updated_at, title, notes, owner, flags,
+                    deleted_at
+                FROM links WHERE id = $1",
+                &[&key],
+            )
+            .await?
+            .ok_or(BackendError::NotFound)?;
+        Ok(Link {
+            url: row.get(0),
+            version: row.get::<_, i64>(1) as u64,
+            created_at: row.get(2),
+            updated_at: row.get(3),
+            deleted_at: row.get(8),
+            metadata: Metadata {
+                title: row.get(4),
+                notes: row.get(5),
+                owner: row.get(6),
+                flags: row.get::<_, Vec<String>>(7).into_iter().collect(),
+            },
+        })
+    }
+
+    async fn history(&self, id: u64) -> Result<Vec<Revision>, BackendError> {
+        let key = to_key(id)?;
+        let client = self.pool.get().await?;
+        client
+            .query_opt("SELECT 1 FROM links WHERE id = $1", &[&key])
+            .await?
+            .ok_or(BackendError::NotFound)?;
+        let history = client
+            .query(
+                "SELECT version, url, created_at FROM history WHERE link_id = $1
+                ORDER BY version",
+                &[&key],
+            )
+            .await?
+            .into_iter()
+            .map(|row| Revision {
+                version: row.get::<_, i64>(0) as u64,
+                url: row.get(1),
+                created_at: row.get(2),
+            })
+            .collect();
+        Ok(history)
+    }
+
+    /// Either the inserted alias or the one existing before is returned,
+    /// nothing means the link is missing.
+    async fn store_alias<'a>(&self, alias: &'a str, id: u64) -> Result<(), BackendError> {
+        let key = to_key(id)?;
+        let client = self.pool.get().await?;
+        let owner: i64 = client
+            .query_opt(
+                "WITH inserted AS (
+                    INSERT INTO aliases (alias, link_id) SELECT $1, id FROM links WHERE id = $2
+                    ON CONFLICT (alias) DO NOTHING
+                    RETURNING link_id
+                )
+                SELECT link_id FROM inserted
+                UNION ALL SELECT link_id FROM aliases WHERE alias = $1
+                LIMIT 1",
+                &[&alias, &key],
+            )
+            .await?
+            .ok_or(BackendError::NotFound)?
+            .get(0);
+        if owner != key {
+            return Err(BackendError::AlreadyExists);
+        }
+        Ok(())
+    }
+
+    async fn store_with_alias<'a>(
+        &self,
+        id: u64,
+        url: &'a str,
+        alias: &'a str,
+    ) -> Result<(), BackendError> {
+        let key = to_key(id)?;
+        let mut client = self.pool.get().await?;
+        let transaction = client.transaction().await?;
+        insert_link(&transaction, key, url).await?;
+        let inserted = transaction
+            .execute(
+                "INSERT INTO aliases (alias, link_id) VALUES ($1, $2)
+                ON CONFLICT (alias) DO NOTHING",
+                &[&alias, &key],
+            )
+            .await?;
+        if inserted == 0 {
+            return Err(BackendError::AlreadyExists);
+        }
+        transaction.commit().await?;
+        Ok(())
+    }
+
+    async fn resolve_alias<'a>(&self, alias: &'a str) -> Result<u64, BackendError> {
+        let client = self.pool.get().await?;
+        let id: i64 = client
+            .query_opt("SELECT link_id FROM aliases WHERE alias = $1", &[&alias])
+            .await?
+            .ok_or(BackendError::NotFound)?
+            .get(0);
+        Ok(id as u64)
+    }
+
+    async fn aliases(&self, id: u64) -> Result<Vec<String>, BackendError> {
+        let key = to_key(id)?;
+        let client = self.pool.get().await?;
+        let aliases = client
+            .query(
+                "SELECT alias FROM aliases WHERE link_id = $1 ORDER BY alias",
+                &[&key],
+            )
+            .await?
+            .into_iter()
+            .map(|row| row.get(0))
+            .collect();
+        Ok(aliases)
+    }
+
+    /// Either the inserted code or the one the link had before is returned,
+    /// nothing means the code belongs to another link.
+    async fn store_code<'a>(&self, id: u64, code: &'a str) -> Result<String, BackendError> {
+        let key = to_key(id)?;
+        let client = self.pool.get().await?;
+        let code = client
+            .query_opt(
+                "WITH inserted AS (
+                    INSERT INTO codes (code, link_id) VALUES ($1, $2)
+                    ON CONFLICT DO NOTHING
+                    RETURNING code
+                )
+                SELECT code FROM inserted
+                UNION ALL SELECT code FROM codes WHERE link_id = $2
+                LIMIT 1",
+                &[&code, &key],
+            )
+            .await?
+            .ok_or(BackendError::AlreadyExists)?
+            .get(0);
+        Ok(code)
+    }
+
+    async fn resolve_code<'a>(&self, code: &'a str) -> Result<u64, BackendError> {
+        let client = self.pool.get().await?;
+        let id: i64 = client
+            .query_opt("SELECT link_id FROM codes WHERE code = $1", &[&code])
+            .await?
+            .ok_or(BackendError::NotFound)?
+            .get(0);
+        Ok(id as u64)
+    }
+
+    async fn code(&self, id: u64) -> Result<String, BackendError> {
+        let key = to_key(id)?;
+        let client = self.pool.get().await?;
+        let code = client
+            .query_opt("SELECT code FROM codes WHERE link_id = $1", &[&key])
+            .await?
+            .ok_or(BackendError::NotFound)?
+            .get(0);
+        Ok(code)
+    }
+
+    async fn set_metadata<'a>(&self, id: u64, metadata: &'a Metadata) -> Result<(), BackendError> {
+        let key = to_key(id)?;
+        let flags = metadata.flags.iter().collect::<Vec<_>>();
+        let client = self.pool.get().await?;
+        let updated = client
+            .execute(
+                "UPDATE links SET title = $2, notes = $3, owner = $4, flags = $5, updated_at = $6
+                WHERE id = $1 AND deleted_at IS NULL",
+                &[
+                    &key,
+                    &metadata.title,
+                    &metadata.notes,
+                    &metadata.owner,
+                    &flags,
+                    &now(),
+                ],
+            )
+            .await?;
+        if updated == 0 {
+            Err(unchanged(&client, key).await?)
+        } else {
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,803 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use redb::{
+    Database, ReadableTable, ReadableTableMetadata, Table, TableDefinition, WriteTransaction,
+};
+use serde::{Deserialize, Serialize};
+use tokio::task::spawn_blocking;
+use tracing::info;
+
+use super::{link::now, Backend, BackendError, Clicks, Link, Metadata, Revision};
+
+static META: TableDefinition<&str, u64> = TableDefinition::new("meta");
+static LINKS: TableDefinition<u64, &str> = TableDefinition::new("links");
+/// Click counters keyed by link id and bucket start timestamp.
+static CLICKS: TableDefinition<(u64, i64), u64> = TableDefinition::new("clicks");
+/// Versions of updated links, absent ones are at version 1.
+static VERSIONS: TableDefinition<u64, u64> = TableDefinition::new("versions");
+/// JSON encoded [`Record`] of every link stored after records were introduced.
+static RECORDS: TableDefinition<u64, &str> = TableDefinition::new("records");
+/// Deletion time in microseconds of tombstoned links.
+static TOMBSTONES: TableDefinition<u64, i64> = TableDefinition::new("tombstones");
+/// JSON encoded [`Revision`] keyed by link id and version. Links stored
+/// before history was kept have none until they are updated.
+static HISTORY: TableDefinition<(u64, u64), &str> = TableDefinition::new("history");
+/// Ids of links keyed by their destination, tombstones included.
+static DESTINATIONS: TableDefinition<(&str, u64), ()> = TableDefinition::new("destinations");
+/// Link ids keyed by alias.
+static ALIASES: TableDefinition<&str, u64> = TableDefinition::new("aliases");
+/// Aliases keyed by link id, the reverse of [`ALIASES`].
+static LINK_ALIASES: TableDefinition<(u64, &str), ()> = TableDefinition::new("link_aliases");
+/// Link ids keyed by short code.
+static CODES: TableDefinition<&str, u64> = TableDefinition::new("codes");
+/// Short codes keyed by link id, the reverse of [`CODES`].
+static LINK_CODES: TableDefinition<u64, &str> = TableDefinition::new("link_codes");
+
+static LAST_ID_KEY: &str = "LID";
+
+/// Width of click bucket in seconds. `since` of a stat request is rounded
+/// down to the bucket start.
+static CLICK_BUCKET_IN_SECONDS: i64 = 60;
+
+static DEFAULT_STAT_PERIOD_IN_HOURS: i64 = 24;
+
+macro_rules! impl_from_redb_error {
+    ($($error:ty),*) => {
+        $(
+            impl From<$error> for BackendError {
+                fn from(error: $error) -> Self {
+                    BackendError::Internal(Box::new(error))
+                }
+            }
+        )*
+    };
+}
+
+impl_from_redb_error!(
+    redb::Error,
+    redb::DatabaseError,
+    redb::TransactionError,
+    redb::TableError,
+    redb::StorageError,
+    redb::CommitError
+);
+
+fn bucket(ts: i64) -> i64 {
+    ts - ts.rem_euclid(CLICK_BUCKET_IN_SECONDS)
+}
+
+/// Part of a [`Link`] kept apart from its destination, so redirects read
+/// nothing but the destination and version.
+#[derive(Serialize, Deserialize, Default)]
+struct Record {
+    created_at: Option<DateTime<Utc>>,
+    updated_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    metadata: Metadata,
+}
+
+fn read_record(table: &Table<u64, &str>, id: u64) -> Result<Record, BackendError> {
+    match table.get(id)? {
+        Some(record) => Ok(serde_json::from_str(record.value())?),
+        None => Ok(Record::default()),
+    }
+}
+
+fn write_record(
+    table: &mut Table<u64, &str>,
+    id: u64,
+    record: &Record,
+) -> Result<(), BackendError> {
+    table.insert(id, serde_json::to_string(record)?.as_str())?;
+    Ok(())
+}
+
+fn write_revision(
+    table: &mut Table<(u64, u64), &str>,
+    id: u64,
+    revision: &Revision,
+) -> Result<(), BackendError> {
+    table.insert(
+        (id, revision.version),
+        serde_json::to_string(revision)?.as_str(),
+    )?;
+    Ok(())
+}
+
+/// Fails unless the link exists and is not a tombstone.
+fn check_live(transaction: &WriteTransaction, id: u64) -> Result<(), BackendError> {
+    if transaction.open_table(LINKS)?.get(id)?.is_none() {
+        return Err(BackendError::NotFound);
+    }
+    if transaction.open_table(TOMBSTONES)?.get(id)?.is_some() {
+        return Err(BackendError::Deleted);
+    }
+    Ok(())
+}
+
+fn last_id(transaction: &WriteTransaction) -> Result<u64, BackendError> {
+    let meta = transaction.open_table(META)?;
+    let last_id = meta.get(LAST_ID_KEY)?.map(|id| id.value()).unwrap_or(0);
+    Ok(last_id)
+}
+
+/// Insert a new link with its first revision and move the counter past
+/// `id`.
+fn insert_link(transaction: &WriteTransaction, id: u64, link: Link) -> Result<(), BackendError> {
+    let mut links = transaction.open_table(LINKS)?;
+    if links.get(id)?.is_some() {
+        return Err(BackendError::AlreadyExists);
+    }
+    links.insert(id, link.url.as_str())?;
+    let last_id = last_id(transaction)?.max(id);
+    transaction.open_table(META)?.insert(LAST_ID_KEY, last_id)?;
+    let record = Record {
+        created_at: link.created_at,
+        updated_at: link.updated_at,
+        metadata: link.metadata,
+    };
+    write_record(&mut transaction.open_table(RECORDS)?, id, &record)?;
+    let revision = Revision {
+        version: 1,
+        url: link.url,
+        created_at: link.created_at,
+    };
+    write_revision(&mut transaction.open_table(HISTORY)?, id, &revision)?;
+    transaction
+        .open_table(DESTINATIONS)?
+        .insert((revision.url.as_str(), id), ())?;
+    Ok(())
+}
+
+pub struct RedbBackend {
+    database: Arc<Database>,
+}
+
+impl RedbBackend {
+    pub async fn new(path: &str) -> Result<Self, BackendError> {
+        info!("Initialize Redb backend");
+        let path = path.to_owned();
+        let database = spawn_blocking(move || -> Result<Database, BackendError> {
+            let database = Database::create(path)?;
+            let transaction = database.begin_write()?;
+            transaction.open_table(META)?;
+            transaction.open_table(LINKS)?;
+            transaction.open_table(CLICKS)?;
+            transaction.open_table(VERSIONS)?;
+            transaction.open_table(RECORDS)?;
+            transaction.open_table(TOMBSTONES)?;
+            transaction.open_table(HISTORY)?;
+            transaction.open_table(ALIASES)?;
+            transaction.open_table(LINK_ALIASES)?;
+            transaction.open_table(CODES)?;
+            transaction.open_table(LINK_CODES)?;
+            {
+                // Index links stored before destinations were indexed
+                let links = transaction.open_table(LINKS)?;
+                let mut destinations = transaction.open_table(DESTINATIONS)?;
+                if destinations.is_empty()? {
+                    for entry in links.iter()? {
+                        let (id, url) = entry?;
+                        destinations.insert((url.value(), id.value()), ())?;
+                    }
+                }
+            }
+            transaction.commit()?;
+            Ok(database)
+        })
+        .await??;
+        Ok(Self {
+            database: Arc::new(database),
+        })
+    }
+
+    async fn execute<T, F>(&self, operation: F) -> Result<T, BackendError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&Database) -> Result<T, BackendError> + Send + 'static,
+    {
+        let database = self.database.clone();
+        spawn_blocking(move || operation(&database)).await?
+    }
+
+    /// Links following `after` ordered by id, tombstones only with
+    /// `tombstones` set.
+    async fn links(
+        &self,
+        after: Option<u64>,
+        limit: usize,
+        tombstones: bool,
+    ) -> Result<Vec<(u64, String)>, BackendError> {
+        self.execute(move |database| {
+            let transaction = database.begin_read()?;
+            let links = transaction.open_table(LINKS)?;
+            let deleted = transaction.open_table(TOMBSTONES)?;
+            let start = match after {
+                Some(after) => match after.checked_add(1) {
+                    Some(start) => start,
+                    None => return Ok(Vec::new()),
+                },
+                None => 0,
+            };
+            let mut result = Vec::new();
+            for entry in links.range(start..)? {
+                if result.len() >= limit {
+                    break;
+                }
+                let (id, url) = entry?;
+                if tombstones || deleted.get(id.value())?.is_none() {
+                    result.push((id.value(), url.value().to_owned()));
+                }
+            }
+            Ok(result)
+        })
+        .await
+    }
+}
+
+#[async_trait]
+impl Backend for RedbBackend {
+    async fn store<'a>(&self, url: &'a str) -> Result<u64, BackendError> {
+        let link = Link::new(url);
+        self.execute(move |database| {
+            let transaction = database.begin_write()?;
+            let id = last_id(&transaction)? + 1;
+            insert_link(&transaction, id, link)?;
+            transaction.commit()?;
+            Ok(id)
+        })
+        .await
+    }
+
+    async fn allocate_ids(&self, count: u64) -> Result<Vec<u64>, BackendError> {
+        self.execute(move |database| {
+            let transaction = database.begin_write()?;
+            let first = last_id(&transaction)? + 1;
+            let last = first + count - 1;
+            transaction.open_table(META)?.insert(LAST_ID_KEY, last)?;
+            transaction.commit()?;
+            Ok((first..=last).collect())
+        })
+        .await
+    }
+
+    async fn reserve_until(&self, id: u64) -> Result<(), BackendError> {
+        self.execute(move |database| {
+            let transaction = database.begin_write()?;
+            let last = last_id(&transaction)?.max(id);
+            transaction.open_table(META)?.insert(LAST_ID_KEY, last)?;
+            transaction.commit()?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn store_with_id<'a>(&self, id: u64, url: &'a str) -> Result<(), BackendError> {
+        let link = Link::new(url);
+        self.execute(move |database| {
+            let transaction = database.begin_write()?;
+            insert_link(&transaction, id, link)?;
+            transaction.commit()?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn retrive_versioned(&self, id: u64) -> Result<(String, u64), BackendError> {
+        let ts = bucket(Utc::now().timestamp());
+        self.execute(move |database| {
+            let transaction = database.begin_write()?;
+            check_live(&transaction, id)?;
+            let url = transaction
+                .open_table(LINKS)?
+                .get(id)?
+                .map(|url| url.value().to_owned())
+                .ok_or(BackendError::NotFound)?;
+            let version = transaction
+                .open_table(VERSIONS)?
+                .get(id)?
+                .map(|version| version.value())
+                .unwrap_or(1);
+            {
+                let mut clicks = transaction.open_table(CLICKS)?;
+                let counter = clicks.get((id, ts))?.map(|counter| counter.value());
+                clicks.insert((id, ts), counter.unwrap_or(0) + 1)?;
+            }
+            transaction.commit()?;
+            Ok((url, version))
+        })
+        .await
+    }
+
+    async fn click(&self, id: u64) -> Result<(), BackendError> {
+        let ts = bucket(Utc::now().timestamp());
+        self.execute(move |database| {
+            let transaction = database.begin_write()?;
+            check_live(&transaction, id)?;
+            {
+                let mut clicks = transaction.open_table(CLICKS)?;
+                let counter = clicks.get((id, ts))?.map(|counter| counter.value());
+                clicks.insert((id, ts), counter.unwrap_or(0) + 1)?;
+            }
+            transaction.commit()?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn stat(&self, id: u64, since: Option<DateTime<Utc>>) -> Result<u64, BackendError> {
+        let now = Utc::now();
+        let since = since
+            .or_else(|| now.checked_sub_signed(Duration::hours(DEFAULT_STAT_PERIOD_IN_HOURS)))
+            .ok_or(BackendError::DateTimeOverflow)?;
+        self.execute(move |database| {
+            let transaction = database.begin_read()?;
+            if transaction.open_table(LINKS)?.get(id)?.is_none() {
+                return Err(BackendError::NotFound);
+            }
+            let clicks = transaction.open_table(CLICKS)?;
+            let mut count = 0;
+            for entry in clicks.range((id, bucket(since.timestamp()))..=(id, now.timestamp()))? {
+                count += entry?.1.value();
+            }
+            Ok(count)
+        })
+        .await
+    }
+
+    async fn update_versioned<'a>(
+        &self,
+        id: u64,
+        url: &'a str,
+        expected: Option<u64>,
+    ) -> Result<u64, BackendError> {
+        let url = url.to_owned();
+        self.execute(move |database| {
+            let transaction = database.begin_write()?;
+            check_live(&transaction, id)?;
+            let version = {
+                let mut links = transaction.open_table(LINKS)?;
+                let mut versions = transaction.open_table(VERSIONS)?;
+                let version = versions
+                    .get(id)?
+                    .map(|version| version.value())
+                    .unwrap_or(1);
+                if expected.is_some_and(|expected| expected != version) {
+                    return Err(BackendError::VersionMismatch);
+                }
+                let previous = links
+                    .get(id)?
+                    .map(|url| url.value().to_owned())
+                    .ok_or(BackendError::NotFound)?;
+                let mut destinations = transaction.open_table(DESTINATIONS)?;
+                destinations.remove((previous.as_str(), id))?;
+                destinations.insert((url.as_str(), id), ())?;
+                let mut history = transaction.open_table(HISTORY)?;
+                if history.get((id, version))?.is_none() {
+                    let revision = Revision {
+                        version,
+                        url: previous,
+                        created_at: None,
+                    };
+                    write_revision(&mut history, id, &revision)?;
+                }
+                links.insert(id, url.as_str())?;
+                versions.insert(id, version + 1)?;
+                let updated_at = Some(now());
+                let mut records = transaction.open_table(RECORDS)?;
+                let mut record = read_record(&records, id)?;
+                record.updated_at = updated_at;
+                write_record(&mut records, id, &record)?;
+                let revision = Revision {
+                    version: version + 1,
+                    url,
+                    created_at: updated_at,
+                };
+                write_revision(&mut history, id, &revision)?;
+                version + 1
+            };
+            transaction.commit()?;
+            Ok(version)
+        })
+        .await
+    }
+
+    async fn delete(&self, id: u64) -> Result<(), BackendError> {
+        let deleted_at = now().timestamp_micros();
+        self.execute(move |database| {
+            let transaction = database.begin_write()?;
+            check_live(&transaction, id)?;
+            transaction.open_table(TOMBSTONES)?.insert(id, deleted_at)?;
+            transaction.commit()?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn undelete(&self, id: u64) -> Result<(), BackendError> {
+        self.execute(move |database| {
+            let transaction = database.begin_write()?;
+            if transaction.open_table(LINKS)?.get(id)?.is_none() {
+                return Err(BackendError::NotFound);
+            }
+            transaction.open_table(TOMBSTONES)?.remove(id)?;
+            transaction.commit()?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn purge(&self, before: DateTime<Utc>) -> Result<u64, BackendError> {
+        let before = before.timestamp_micros();
+        self.execute(move |database| {
+            let transaction = database.begin_write()?;
+            let ids = {
+                let mut tombstones = transaction.open_table(TOMBSTONES)?;
+                let mut ids = Vec::new();
+                for entry in tombstones.iter()? {
+                    let (id, deleted_at) = entry?;
+                    if deleted_at.value() <= before {
+                        ids.push(id.value());
+                    }
+                }
+                for id in &ids {
+                    tombstones.remove(*id)?;
+                }
+                ids
+            };
+            {
+                let mut links = transaction.open_table(LINKS)?;
+                let mut versions = transaction.open_table(VERSIONS)?;
+                let mut records = transaction.open_table(RECORDS)?;
+                let mut clicks = transaction.open_table(CLICKS)?;
+                let mut history = transaction.open_table(HISTORY)?;
+                let mut destinations = transaction.open_table(DESTINATIONS)?;
+                let mut aliases = transaction.open_table(ALIASES)?;
+                let mut link_aliases = transaction.open_table(LINK_ALIASES)?;
+                for id in &ids {
+                    if let Some(url) = links.remove(*id)? {
+                        destinations.remove((url.value(), *id))?;
+                    }
+                    let mut named = Vec::new();
+                    for entry in link_aliases.range((*id, "")..(*id + 1, ""))? {
+                        named.push(entry?.0.value().1.to_owned());
+                    }
+                    for alias in named {
+                        aliases.remove(alias.as_str())?;
+                        link_aliases.remove((*id, alias.as_str()))?;
+                    }
+                    versions.remove(*id)?;
+                    records.remove(*id)?;
+                    clicks.retain_in((*id, i64::MIN)..=(*id, i64::MAX), |_, _| false)?;
+                    history.retain_in((*id, 0)..=(*id, u64::MAX), |_, _| false)?;
+                }
+            }
+            transaction.commit()?;
+            Ok(ids.len() as u64)
+        })
+        .await
+    }
+
+    async fn list(
+        &self,
+        after: Option<u64>,
+        limit: usize,
+    ) -> Result<Vec<(u64, String)>, BackendError> {
+        self.links(after, limit, false).await
+    }
+
+    async fn list_with_tombstones(
+        &self,
+        after: Option<u64>,
+        limit: usize,
+    ) -> Result<Vec<(u64, String)>, BackendError> {
+        self.links(after, limit, true).await
+    }
+
+    async fn find<'a>(&self, url: &'a str) -> Result<Vec<u64>, BackendError> {
+        let url = url.to_owned();
+        self.execute(move |database| {
+            let transaction = database.begin_read()?;
+            let tombstones = transaction.open_table(TOMBSTONES)?;
+            let mut ids = Vec::new();
+            for entry in transaction
+                .open_table(DESTINATIONS)?
+                .range((url.as_str(), 0)..=(url.as_str(), u64::MAX))?
+            {
+                let id = entry?.0.value().1;
+                if tombstones.get(id)?.is_none() {
+                    ids.push(id);
+                }
+            }
+            Ok(ids)
+        })
+        .await
+    }
+
+    async fn clicks(&self, id: u64) -> Result<Clicks, BackendError> {
+        self.execute(move |database| {
+            let transaction = database.begin_read()?;
+            if transaction.open_table(LINKS)?.get(id)?.is_none() {
+                return Err(BackendError::NotFound);
+            }
+            let clicks = transaction.open_table(CLICKS)?;
+            let mut result = Vec::new();
+            for entry in clicks.range((id, i64::MIN)..=(id, i64::MAX))? {
+                let (key, counter) = entry?;
+                if let Some(ts) = DateTime::from_timestamp(key.value().1, 0) {
+                    result.push((ts, counter.value()));
+                }
+            }
+            Ok(result)
+        })
+        .await
+    }
+
+    async fn restore<'a>(
+        &self,
+        id: u64,
+        link: &'a Link,
+        history: &'a [Revision],
+        clicks: &'a [(DateTime<Utc>, u64)],
+    ) -> Result<(), BackendError> {
+        let link = link.clone();
+        let history = Revision::restored(&link, history);
+        let clicks = clicks.to_vec();
+        self.execute(move |database| {
+            let transaction = database.begin_write()?;
+            {
+                let mut meta = transaction.open_table(META)?;
+                let last_id = meta.get(LAST_ID_KEY)?.map(|id| id.value()).unwrap_or(0);
+                meta.insert(LAST_ID_KEY, last_id.max(id))?;
+                let mut destinations = transaction.open_table(DESTINATIONS)?;
+                if let Some(previous) = transaction
+                    .open_table(LINKS)?
+                    .insert(id, link.url.as_str())?
+                {
+                    destinations.remove((previous.value(), id))?;
+                }
+                destinations.insert((link.url.as_str(), id), ())?;
+                transaction.open_table(VERSIONS)?.insert(id, link.version)?;
+                let mut tombstones = transaction.open_table(TOMBSTONES)?;
+                match link.deleted_at {
+                    Some(deleted_at) => tombstones.insert(id, deleted_at.timestamp_micros())?,
+                    None => tombstones.remove(id)?,
+                };
+                let mut revisions = transaction.open_table(HISTORY)?;
+                revisions.retain_in((id, 0)..=(id, u64::MAX), |_, _| false)?;
+                for revision in &history {
+                    write_revision(&mut revisions, id, revision)?;
+                }
+                let record = Record {
+                    created_at: link.created_at,
+                    updated_at: link.updated_at,
+                    metadata: link.metadata,
+                };
+                write_record(&mut transaction.open_table(RECORDS)?, id, &record)?;
+                let mut table = transaction.open_table(CLICKS)?;
+                table.retain_in((id, i64::MIN)..=(id, i64::MAX), |_, _| false)?;
+                for (ts, counter) in clicks {
+                    let key = (id, bucket(ts.timestamp()));
+                    let current = table.get(key)?.map(|current| current.value());
+                    table.insert(key, current.unwrap_or(0) + counter)?;
+                }
+            }
+            transaction.commit()?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn info(&self, id: u64) -> Result<Link, BackendError> {
+        self.execute(move |database| {
+            let transaction = database.begin_read()?;
+            let url = transaction
+                .open_table(LINKS)?
+                .get(id)?
+                .map(|url| url.value().to_owned())
+                .ok_or(BackendError::NotFound)?;
+            let version = transaction
+                .open_table(VERSIONS)?
+                .get(id)?
+                .map(|version| version.value())
+                .unwrap_or(1);
+            let record = match transaction.open_table(RECORDS)?.get(id)? {
+                Some(record) => serde_json::from_str(record.value())?,
+                None => Record::default(),
+            };
+            let deleted_at = transaction
+                .open_table(TOMBSTONES)?
+                .get(id)?
+                .and_then(|deleted_at| DateTime::from_timestamp_micros(deleted_at.value()));
+            Ok(Link {
+                url,
+                version,
+                created_at: record.created_at,
+                updated_at: record.updated_at,
+                deleted_at,
+                metadata: record.metadata,
+            })
+        })
+        .await
+    }
+
+    async fn history(&self, id: u64) -> Result<Vec<Revision>, BackendError> {
+        self.execute(move |database| {
+            let transaction = database.begin_read()?;
+            let url = transaction
+                .open_table(LINKS)?
+                .get(id)?
+                .map(|url| url.value().to_owned())
+                .ok_or(BackendError::NotFound)?;
+            let mut result = Vec::new();
+            for entry in transaction
+                .open_table(HISTORY)?
+                .range((id, 0)..=(id, u64::MAX))?
+            {
+                result.push(serde_json::from_str(entry?.1.value())?);
+            }
+            if result.is_empty() {
+                let version = transaction
+                    .open_table(VERSIONS)?
+                    .get(id)?
+                    .map(|version| version.value())
+                    .unwrap_or(1);
+                result.push(Revision {
+                    version,
+                    url,
+                    created_at: None,
+                });
+            }
+            Ok(result)
+        })
+        .await
+    }
+
+    async fn store_alias<'a>(&self, alias: &'a str, id: u64) -> Result<(), BackendError> {
+        let alias = alias.to_owned();
+        self.execute(move |database| {
+            let transaction = database.begin_write()?;
+            if transaction.open_table(LINKS)?.get(id)?.is_none() {
+                return Err(BackendError::NotFound);
+            }
+            {
+                let mut aliases = transaction.open_table(ALIASES)?;
+                let existing = aliases.get(alias.as_str())?.map(|owner| owner.value());
+                match existing {
+                    Some(owner) if owner == id => return Ok(()),
+                    Some(_) => return Err(BackendError::AlreadyExists),
+                    None => {}
+                }
+                aliases.insert(alias.as_str(), id)?;
+                transaction
+                    .open_table(LINK_ALIASES)?
+                    .insert((id, alias.as_str()), ())?;
+            }
+            transaction.commit()?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn store_with_alias<'a>(
+        &self,
+        id: u64,
+        url: &'a str,
+        alias: &'a str,
+    ) -> Result<(), BackendError> {
+        let link = Link::new(url);
+        let alias = alias.to_owned();
+        self.execute(move |database| {
+            let transaction = database.begin_write()?;
+            insert_link(&transaction, id, link)?;
+            {
+                let mut aliases = transaction.open_table(ALIASES)?;
+                if aliases.get(alias.as_str())?.is_some() {
+                    return Err(BackendError::AlreadyExists);
+                }
+                aliases.insert(alias.as_str(), id)?;
+                transaction
+                    .open_table(LINK_ALIASES)?
+                    .insert((id, alias.as_str()), ())?;
+            }
+            transaction.commit()?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn resolve_alias<'a>(&self, alias: &'a str) -> Result<u64, BackendError> {
+        let alias = alias.to_owned();
+        self.execute(move |database| {
+            database
+                .begin_read()?
+                .open_table(ALIASES)?
+                .get(alias.as_str())?
+                .map(|id| id.value())
+                .ok_or(BackendError::NotFound)
+        })
+        .await
+    }
+
+    async fn aliases(&self, id: u64) -> Result<Vec<String>, BackendError> {
+        self.execute(move |database| {
+            let transaction = database.begin_read()?;
+            let mut aliases = Vec::new();
+            for entry in transaction
+                .open_table(LINK_ALIASES)?
+                .range((id, "")..(id + 1, ""))?
+            {
+                aliases.push(entry?.0.value().1.to_owned());
+            }
+            Ok(aliases)
+        })
+        .await
+    }
+
+    async fn store_code<'a>(&self, id: u64, code: &'a str) -> Result<String, BackendError> {
+        let code = code.to_owned();
+        self.execute(move |database| {
+            let transaction = database.begin_write()?;
+            {
+                let mut link_codes = transaction.open_table(LINK_CODES)?;
+                if let Some(existing) = link_codes.get(id)? {
+                    return Ok(existing.value().to_owned());
+                }
+                let mut codes = transaction.open_table(CODES)?;
+                if codes.get(code.as_str())?.is_some() {
+                    return Err(BackendError::AlreadyExists);
+                }
+                codes.insert(code.as_str(), id)?;
+                link_codes.insert(id, code.as_str())?;
+            }
+            transaction.commit()?;
+            Ok(code)
+        })
+        .await
+    }
+
+    async fn resolve_code<'a>(&self, code: &'a str) -> Result<u64, BackendError> {
+        let code = code.to_owned();
+        self.execute(move |database| {
+            database
+                .begin_read()?
+                .open_table(CODES)?
+                .get(code.as_str())?
+                .map(|id| id.value())
+                .ok_or(BackendError::NotFound)
+        })
+        .await
+    }
+
+    async fn code(&self, id: u64) -> Result<String, BackendError> {
+        self.execute(move |database| {
+            database
+                .begin_read()?
+                .open_table(LINK_CODES)?
+                .get(id)?
+                .map(|code| code.value().to_owned())
+                .ok_or(BackendError::NotFound)
+        })
+        .await
+    }
+
+    async fn set_metadata<'a>(&self, id: u64, metadata: &'a Metadata) -> Result<(), BackendError> {
+        let metadata = metadata.clone();
+        self.execute(move |database| {
+            let transaction = database.begin_write()?;
+            check_live(&transaction, id)?;
+            {
+                let mut records = transaction.open_table(RECORDS)?;
+                let mut record = read_record(&records, id)?;
+                record.metadata = metadata;
+                record.updated_at = Some(now());
+                write_record(&mut records, id, &record)?;
+            }
+            transaction.commit()?;
+            Ok(())
+        })
+        .await
+    }
+}
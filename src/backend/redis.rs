@@ -1,38 +1,150 @@
-use std::str::FromStr;
+use std::{
+    collections::{BTreeMap, HashMap},
+    str::FromStr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        OnceLock,
+    },
+};
 
 use async_trait::async_trait;
 use chrono::{DateTime, Duration, Utc};
-use redis::{aio::ConnectionManager, Client, IntoConnectionInfo, RedisError, Script};
+use redis::{IntoConnectionInfo, RedisError, Script, Value};
 use semver::Version;
+use tokio::sync::mpsc::{self, error::TrySendError};
 use tracing::{error, info, warn};
 use uuid::Uuid;
 
-use super::{Backend, BackendError};
+use self::{connection::RedisConnection, keyspace::Keyspace, replica::Replica};
+
+use super::{link::now, Backend, BackendError, Clicks, Link, Metadata, Revision};
+
+mod connection;
+mod keyspace;
+mod replica;
 
 static STORE_SCRIPT: &str = r"
-local id = redis.call('INCR', 'LID');
-redis.call('SET', id, ARGV[1]);
+local id = redis.call('INCR', KEYS[1]);
+local key = id;
+if ARGV[2] ~= '' then
+    key = ARGV[2]..'{'..id..'}';
+end
+redis.call('SET', key, ARGV[1]);
+redis.call('HSET', ARGV[2]..'meta:{'..id..'}', 'created_at', ARGV[3], 'updated_at', ARGV[3]);
+redis.call('HSET', ARGV[2]..'hist:{'..id..'}', 1, ARGV[3]..':'..ARGV[1]);
+redis.call('SADD', ARGV[2]..'dest:'..ARGV[1], id);
+redis.call('ZADD', ARGV[2]..'ids', 0, string.format('%020d', id));
 return id;";
 
+static INSERT_SCRIPT: &str = r"
+if not redis.call('SET', KEYS[1], ARGV[1], 'NX') then
+    return 0;
+end
+redis.call('HSET', KEYS[2], 'created_at', ARGV[2], 'updated_at', ARGV[2]);
+redis.call('HSET', KEYS[3], 1, ARGV[3]);
+return 1;
+";
+
 static RETRIVE_SCRIPT: &str = r"
-local url = redis.call('GET', ARGV[1]);
-local key = 'stat:'..ARGV[1]..':'..ARGV[2];
-if url then
-    redis.call('ZADD', key, ARGV[3], ARGV[4]);
-    redis.call('EXPIRE', key, 172800, 'NX');
+local url = redis.call('GET', KEYS[1]);
+if not url then
+    return nil;
+end
+if redis.call('HEXISTS', KEYS[4], 'deleted_at') == 1 then
+    return -1;
+end
+redis.call('ZADD', KEYS[2], ARGV[1], ARGV[2]);
+redis.call('EXPIRE', KEYS[2], 172800, 'NX');
+return {url, tonumber(redis.call('GET', KEYS[3]) or '1')};
+";
+
+static UPDATE_SCRIPT: &str = r"
+if redis.call('EXISTS', KEYS[1]) == 0 then
+    return nil;
+end
+if redis.call('HEXISTS', KEYS[3], 'deleted_at') == 1 then
+    return -2;
+end
+local version = tonumber(redis.call('GET', KEYS[2]) or '1');
+if ARGV[2] ~= '' and tonumber(ARGV[2]) ~= version then
+    return -1;
+end
+redis.call('HSETNX', KEYS[4], version, ':'..redis.call('GET', KEYS[1]));
+redis.call('SET', KEYS[1], ARGV[1]);
+redis.call('SET', KEYS[2], version + 1);
+redis.call('HSET', KEYS[3], 'updated_at', ARGV[3]);
+redis.call('HSET', KEYS[4], version + 1, ARGV[3]..':'..ARGV[1]);
+return version + 1;
+";
+
+static SET_METADATA_SCRIPT: &str = r"
+if redis.call('EXISTS', KEYS[1]) == 0 then
+    return 0;
+end
+if redis.call('HEXISTS', KEYS[2], 'deleted_at') == 1 then
+    return -1;
+end
+redis.call('HSET', KEYS[2], 'metadata', ARGV[1], 'updated_at', ARGV[2]);
+return 1;
+";
+
+static CLICK_SCRIPT: &str = r"
+if redis.call('EXISTS', KEYS[1]) == 0 then
+    return 0;
+end
+if redis.call('HEXISTS', KEYS[3], 'deleted_at') == 1 then
+    return -1;
+end
+redis.call('ZADD', KEYS[2], ARGV[1], ARGV[2]);
+redis.call('EXPIRE', KEYS[2], 172800, 'NX');
+return 1;
+";
+
+static DELETE_SCRIPT: &str = r"
+if redis.call('EXISTS', KEYS[1]) == 0 then
+    return 0;
+end
+if redis.call('HSETNX', KEYS[2], 'deleted_at', ARGV[1]) == 0 then
+    return -1;
 end
-return url;
+return 1;
+";
+
+static UNDELETE_SCRIPT: &str = r"
+if redis.call('EXISTS', KEYS[1]) == 0 then
+    return 0;
+end
+redis.call('HDEL', KEYS[2], 'deleted_at');
+return 1;
+";
+
+static PURGE_SCRIPT: &str = r"
+local deleted = redis.call('HGET', KEYS[3], 'deleted_at');
+if not deleted then
+    return 0;
+end
+if tonumber(deleted) > tonumber(ARGV[1]) then
+    return -1;
+end
+redis.call('DEL', KEYS[1], KEYS[2], KEYS[3], KEYS[4], KEYS[5], KEYS[6], KEYS[7]);
+return 1;
 ";
 
 static RETRIVE_STAT: &str = r"
-local today_stat_key = 'stat:'..ARGV[1]..':'..ARGV[2];
-local yesterday_stat_key = 'stat:'..ARGV[1]..':'..ARGV[3];
-local today_stat = redis.call('ZCOUNT', today_stat_key, ARGV[4], ARGV[5]);
-local yesterday_stat = redis.call('ZCOUNT', yesterday_stat_key, ARGV[4], ARGV[5]);
+if redis.call('EXISTS', KEYS[3]) == 0 then
+    return nil;
+end
+local today_stat = redis.call('ZCOUNT', KEYS[1], ARGV[1], ARGV[2]);
+local yesterday_stat = redis.call('ZCOUNT', KEYS[2], ARGV[1], ARGV[2]);
 return today_stat + yesterday_stat;
 ";
 
-static KEY_DATE_FORMAT: &str = "%Y%m%d";
+static RESERVE_ID_SCRIPT: &str = r"
+local current = tonumber(redis.call('GET', KEYS[1]) or '0');
+if current < tonumber(ARGV[1]) then
+    redis.call('SET', KEYS[1], ARGV[1]);
+end
+";
 
 static DEFAULT_STAT_PERIOD_IN_HOURS: i64 = 24;
 
@@ -42,16 +154,163 @@ impl From<RedisError> for BackendError {
     }
 }
 
+static MIGRATION_SCAN_COUNT: usize = 1000;
+
+/// Number of ids read per round trip while listing links.
+pub(crate) static LIST_CHUNK: u64 = 1000;
+
+/// Stat keys expire after two days, so only clicks of today and yesterday
+/// are kept.
+static STAT_TTL_IN_SECONDS: i64 = 172800;
+
+/// Clicks of replica lookups waiting to be recorded on the primary, beyond
+/// them clicks are recorded before answering.
+const MAX_PENDING_CLICKS: usize = 1024;
+
+/// Timestamps in the meta hash are microseconds since epoch.
+fn to_micros(ts: DateTime<Utc>) -> i64 {
+    ts.timestamp_micros()
+}
+
+fn from_micros(micros: Option<&String>) -> Option<DateTime<Utc>> {
+    micros
+        .and_then(|micros| micros.parse().ok())
+        .and_then(DateTime::from_timestamp_micros)
+}
+
+/// Outcome of a script guarding a link: `0` for a missing link, negative
+/// for a tombstone.
+fn guarded(result: i64) -> Result<(), BackendError> {
+    match result {
+        0 => Err(BackendError::NotFound),
+        result if result < 0 => Err(BackendError::Deleted),
+        _ => Ok(()),
+    }
+}
+
+/// Value of a history hash field: microseconds the destination was set at,
+/// left empty when unknown, and the destination separated by a colon.
+fn revision_entry(created_at: Option<DateTime<Utc>>, url: &str) -> String {
+    let created_at = created_at.map(to_micros).map(|micros| micros.to_string());
+    format!("{}:{}", created_at.unwrap_or_default(), url)
+}
+
+fn parse_revision(version: &str, entry: &str) -> Option<Revision> {
+    let (created_at, url) = entry.split_once(':')?;
+    Some(Revision {
+        version: version.parse().ok()?,
+        url: url.to_owned(),
+        created_at: created_at
+            .parse()
+            .ok()
+            .and_then(DateTime::from_timestamp_micros),
+    })
+}
+
+/// Member of the ids sorted set. Ids are zero padded, so lexicographic
+/// order of members is the numeric order of ids.
+fn id_member(id: u64) -> String {
+    format!("{:020}", id)
+}
+
+/// Member of a stat sorted set: click timestamp made unique.
+fn click_member(ts: i64) -> String {
+    format!("{}:{}", ts, Uuid::new_v4())
+}
+
+async fn record_click(
+    mut con: RedisConnection,
+    keyspace: &Keyspace,
+    id: u64,
+) -> Result<(), BackendError> {
+    let now = Utc::now();
+    let recorded: i64 = Script::new(CLICK_SCRIPT)
+        .key(keyspace.link(id))
+        .key(keyspace.stat(id, now.date_naive()))
+        .key(keyspace.meta(id))
+        .arg(now.timestamp())
+        .arg(click_member(now.timestamp()))
+        .invoke_async(&mut con)
+        .await?;
+    guarded(recorded)
+}
+
 pub struct RedisBackend {
-    client: ConnectionManager,
+    client: RedisConnection,
+    keyspace: Keyspace,
+    /// Connections serving destination lookups, the primary is used for
+    /// everything else.
+    replicas: Vec<Replica>,
+    next_replica: AtomicUsize,
+    /// Clicks of replica lookups, recorded on the primary one by one by a
+    /// worker started with the first of them.
+    clicks: OnceLock<mpsc::Sender<u64>>,
 }
 
 impl RedisBackend {
     pub async fn new<T: IntoConnectionInfo>(connection_info: T) -> Result<Self, BackendError> {
         info!("Initialize Redis backend");
-        let connection = Client::open(connection_info)?;
-        let client = connection.get_tokio_connection_manager().await?;
-        let backend = Self { client };
+        Self::with_connection(RedisConnection::standalone(connection_info).await?).await
+    }
+
+    pub async fn cluster<T: IntoConnectionInfo>(nodes: Vec<T>) -> Result<Self, BackendError> {
+        info!("Initialize Redis Cluster backend");
+        Self::with_connection(RedisConnection::cluster(nodes).await?).await
+    }
+
+    /// Connect to the master `service` discovered through `sentinels`.
+    /// Database and credentials of the master are taken from `connection_info`.
+    pub async fn sentinel<T: IntoConnectionInfo, M: IntoConnectionInfo>(
+        sentinels: Vec<T>,
+        service: &str,
+        connection_info: M,
+    ) -> Result<Self, BackendError> {
+        info!("Initialize Redis Sentinel backend");
+        Self::with_connection(RedisConnection::sentinel(sentinels, service, connection_info).await?)
+            .await
+    }
+
+    /// Place all keys under `prefix`, e.g. `shortland:`.
+    pub fn with_prefix<P: Into<String>>(self, prefix: P) -> Self {
+        Self {
+            keyspace: Keyspace::new(prefix),
+            ..self
+        }
+    }
+
+    /// Serve destination lookups from `replicas`. Lookups fall back to the
+    /// primary when a replica fails or misses the link; replicas are
+    /// connected in the background and failing ones are skipped with a
+    /// growing backoff until they answer again, see [`Replica`]. Malformed
+    /// connection infos are skipped.
+    ///
+    /// Clicks of replica lookups are queued for a single worker recording
+    /// them on the primary. Up to [`MAX_PENDING_CLICKS`] may wait, further
+    /// lookups record their click before answering. Queued clicks are lost
+    /// when the process stops.
+    pub fn with_replicas<T: IntoConnectionInfo>(self, replicas: Vec<T>) -> Self {
+        let mut kept = Vec::with_capacity(replicas.len());
+        for replica in replicas {
+            match replica.into_connection_info() {
+                Ok(connection_info) => kept.push(Replica::new(connection_info)),
+                Err(error) => warn!("Skip malformed redis replica: {}", error),
+            }
+        }
+        info!("Redis read replicas: {}", kept.len());
+        Self {
+            replicas: kept,
+            ..self
+        }
+    }
+
+    async fn with_connection(client: RedisConnection) -> Result<Self, BackendError> {
+        let backend = Self {
+            client,
+            keyspace: Keyspace::default(),
+            replicas: Vec::new(),
+            next_replica: AtomicUsize::new(0),
+            clicks: OnceLock::new(),
+        };
         let version = backend.server_version().await;
         match version {
             Some(version) => {
@@ -73,6 +332,621 @@ impl RedisBackend {
         Ok(backend)
     }
 
+    /// Store link under `id` without touching the `LID` counter. Keys of
+    /// the link are written by one script, the destination and ids indexes
+    /// live in other slots in cluster and are updated afterwards.
+    pub(crate) async fn insert(&self, id: u64, url: &str) -> Result<(), BackendError> {
+        let mut con = self.client.clone();
+        let now = now();
+        let inserted: i64 = Script::new(INSERT_SCRIPT)
+            .key(self.keyspace.link(id))
+            .key(self.keyspace.meta(id))
+            .key(self.keyspace.history(id))
+            .arg(url)
+            .arg(to_micros(now))
+            .arg(revision_entry(Some(now), url))
+            .invoke_async(&mut con)
+            .await?;
+        if inserted == 0 {
+            return Err(BackendError::AlreadyExists);
+        }
+        self.index_link(id, url).await
+    }
+
+    /// Add a stored link to the destination and ids indexes, each written
+    /// by a command of its own.
+    async fn index_link(&self, id: u64, url: &str) -> Result<(), BackendError> {
+        let mut con = self.client.clone();
+        redis::cmd("SADD")
+            .arg(self.keyspace.destination(url))
+            .arg(id)
+            .query_async::<_, ()>(&mut con)
+            .await?;
+        redis::cmd("ZADD")
+            .arg(self.keyspace.ids())
+            .arg(0)
+            .arg(id_member(id))
+            .query_async::<_, ()>(&mut con)
+            .await?;
+        Ok(())
+    }
+
+    /// [`RedisBackend::insert`] for every link in one pipeline.
+    pub(crate) async fn insert_many(
+        &self,
+        links: &[(u64, &str)],
+    ) -> Result<Vec<Result<(), BackendError>>, BackendError> {
+        if links.is_empty() {
+            return Ok(Vec::new());
+        }
+        let mut con = self.client.clone();
+        let now = now();
+        let mut pipeline = redis::pipe();
+        for (id, url) in links {
+            pipeline
+                .cmd("EVAL")
+                .arg(INSERT_SCRIPT)
+                .arg(3)
+                .arg(self.keyspace.link(*id))
+                .arg(self.keyspace.meta(*id))
+                .arg(self.keyspace.history(*id))
+                .arg(*url)
+                .arg(to_micros(now))
+                .arg(revision_entry(Some(now), url));
+        }
+        let results: Vec<i64> = pipeline.query_async(&mut con).await?;
+        let mut pipeline = redis::pipe();
+        let mut members = Vec::new();
+        for ((id, url), _) in links
+            .iter()
+            .zip(&results)
+            .filter(|(_, result)| **result > 0)
+        {
+            pipeline
+                .cmd("SADD")
+                .arg(self.keyspace.destination(url))
+                .arg(*id)
+                .ignore();
+            members.push(id_member(*id));
+        }
+        if !members.is_empty() {
+            let ids = pipeline.cmd("ZADD").arg(self.keyspace.ids());
+            for member in members {
+                ids.arg(0).arg(member);
+            }
+            ids.ignore();
+        }
+        pipeline.query_async::<_, ()>(&mut con).await?;
+        Ok(results
+            .into_iter()
+            .map(|result| match result {
+                0 => Err(BackendError::AlreadyExists),
+                _ => Ok(()),
+            })
+            .collect())
+    }
+
+    /// Last allocated link id.
+    pub async fn last_id(&self) -> Result<u64, BackendError> {
+        let mut con = self.client.clone();
+        let id: Option<u64> = redis::cmd("GET")
+            .arg(self.keyspace.last_id())
+            .query_async(&mut con)
+            .await?;
+        Ok(id.unwrap_or(0))
+    }
+
+    /// Make sure the id counter never allocates `id` or below.
+    pub(crate) async fn reserve_id(&self, id: u64) -> Result<(), BackendError> {
+        let mut con = self.client.clone();
+        Script::new(RESERVE_ID_SCRIPT)
+            .key(self.keyspace.last_id())
+            .arg(id)
+            .invoke_async::<_, ()>(&mut con)
+            .await?;
+        Ok(())
+    }
+
+    /// Number of shards recorded by the first start of a sharded deployment,
+    /// `count` is recorded when there is none.
+    pub(crate) async fn shard_count(&self, count: usize) -> Result<usize, BackendError> {
+        let mut con = self.client.clone();
+        let key = self.keyspace.shards();
+        redis::cmd("SET")
+            .arg(&key)
+            .arg(count)
+            .arg("NX")
+            .query_async::<_, ()>(&mut con)
+            .await?;
+        Ok(redis::cmd("GET").arg(&key).query_async(&mut con).await?)
+    }
+
+    /// Identity of this instance as a shard, drawn on first use.
+    pub(crate) async fn shard_identity(&self) -> Result<String, BackendError> {
+        self.set_once(self.keyspace.shard_identity(), Uuid::new_v4().to_string())
+            .await
+    }
+
+    /// Shard identities recorded by the first start of a sharded deployment,
+    /// `list` is recorded when there is none.
+    pub(crate) async fn shard_list(&self, list: String) -> Result<String, BackendError> {
+        self.set_once(self.keyspace.shard_list(), list).await
+    }
+
+    /// Value of `key`, set to `value` when missing.
+    async fn set_once(&self, key: String, value: String) -> Result<String, BackendError> {
+        let mut con = self.client.clone();
+        redis::cmd("SET")
+            .arg(&key)
+            .arg(value)
+            .arg("NX")
+            .query_async::<_, ()>(&mut con)
+            .await?;
+        Ok(redis::cmd("GET").arg(&key).query_async(&mut con).await?)
+    }
+
+    pub(crate) async fn exists(&self, id: u64) -> Result<bool, BackendError> {
+        let mut con = self.client.clone();
+        Ok(redis::cmd("EXISTS")
+            .arg(self.keyspace.link(id))
+            .query_async(&mut con)
+            .await?)
+    }
+
+    /// Point `alias` to `id` unless it names another link.
+    pub(crate) async fn claim_alias(&self, alias: &str, id: u64) -> Result<(), BackendError> {
+        self.claim(self.keyspace.alias(alias), id).await
+    }
+
+    /// Point `code` to `id` unless it belongs to another link.
+    pub(crate) async fn claim_code(&self, code: &str, id: u64) -> Result<(), BackendError> {
+        self.claim(self.keyspace.code(code), id).await
+    }
+
+    /// Make `code` the code of the link unless it has one, the code of the
+    /// link is returned.
+    pub(crate) async fn link_code(&self, id: u64, code: &str) -> Result<String, BackendError> {
+        let mut con = self.client.clone();
+        let key = self.keyspace.link_code(id);
+        let linked: Option<()> = redis::cmd("SET")
+            .arg(&key)
+            .arg(code)
+            .arg("NX")
+            .query_async(&mut con)
+            .await?;
+        if linked.is_some() {
+            return Ok(code.to_owned());
+        }
+        let existing: Option<String> = redis::cmd("GET").arg(&key).query_async(&mut con).await?;
+        existing.ok_or_else(|| BackendError::Internal("Code of the link vanished".into()))
+    }
+
+    /// Free `code` claimed for a link which turned out to have another one.
+    pub(crate) async fn release_code(&self, code: &str) -> Result<(), BackendError> {
+        let mut con = self.client.clone();
+        redis::cmd("DEL")
+            .arg(self.keyspace.code(code))
+            .query_async::<_, ()>(&mut con)
+            .await?;
+        Ok(())
+    }
+
+    async fn claim(&self, key: String, id: u64) -> Result<(), BackendError> {
+        let mut con = self.client.clone();
+        let claimed: Option<()> = redis::cmd("SET")
+            .arg(&key)
+            .arg(id)
+            .arg("NX")
+            .query_async(&mut con)
+            .await?;
+        if claimed.is_some() {
+            return Ok(());
+        }
+        let owner: Option<u64> = redis::cmd("GET").arg(&key).query_async(&mut con).await?;
+        match owner {
+            Some(owner) if owner == id => Ok(()),
+            _ => Err(BackendError::AlreadyExists),
+        }
+    }
+
+    /// Add `alias` to the aliases of the link.
+    pub(crate) async fn link_alias(&self, id: u64, alias: &str) -> Result<(), BackendError> {
+        let mut con = self.client.clone();
+        redis::cmd("SADD")
+            .arg(self.keyspace.aliases(id))
+            .arg(alias)
+            .query_async::<_, ()>(&mut con)
+            .await?;
+        Ok(())
+    }
+
+    /// Remove keys of `aliases`. Every key is deleted by a command of its
+    /// own, in cluster they live in different slots.
+    pub(crate) async fn drop_aliases(&self, aliases: &[String]) -> Result<(), BackendError> {
+        if aliases.is_empty() {
+            return Ok(());
+        }
+        let mut con = self.client.clone();
+        let mut pipeline = redis::pipe();
+        for alias in aliases {
+            pipeline.cmd("DEL").arg(self.keyspace.alias(alias)).ignore();
+        }
+        pipeline.query_async::<_, ()>(&mut con).await?;
+        Ok(())
+    }
+
+    /// [`Backend::purge`] leaving keys of aliases of purged links, which are
+    /// returned, to the caller.
+    pub(crate) async fn purge_links(
+        &self,
+        before: DateTime<Utc>,
+    ) -> Result<(u64, Vec<String>), BackendError> {
+        let mut con = self.client.clone();
+        let before = to_micros(before);
+        let today = Utc::now().date_naive();
+        let yesterday = today.pred_opt().ok_or(BackendError::DateTimeOverflow)?;
+        let ids: Vec<u64> = redis::cmd("ZRANGE")
+            .arg(self.keyspace.tombstones())
+            .arg("-inf")
+            .arg(before)
+            .arg("BYSCORE")
+            .query_async(&mut con)
+            .await?;
+        let mut purged = 0;
+        let mut aliases = Vec::new();
+        for id in ids {
+            let named: Vec<String> = redis::cmd("SMEMBERS")
+                .arg(self.keyspace.aliases(id))
+                .query_async(&mut con)
+                .await?;
+            let result: i64 = Script::new(PURGE_SCRIPT)
+                .key(self.keyspace.link(id))
+                .key(self.keyspace.version(id))
+                .key(self.keyspace.meta(id))
+                .key(self.keyspace.stat(id, today))
+                .key(self.keyspace.stat(id, yesterday))
+                .key(self.keyspace.history(id))
+                .key(self.keyspace.aliases(id))
+                .arg(before)
+                .invoke_async(&mut con)
+                .await?;
+            if result < 0 {
+                // Deleted again after the set was read
+                continue;
+            }
+            purged += result as u64;
+            aliases.extend(named);
+            redis::pipe()
+                .cmd("ZREM")
+                .arg(self.keyspace.tombstones())
+                .arg(id)
+                .ignore()
+                .cmd("ZREM")
+                .arg(self.keyspace.ids())
+                .arg(id_member(id))
+                .ignore()
+                .query_async::<_, ()>(&mut con)
+                .await?;
+        }
+        Ok((purged, aliases))
+    }
+
+    /// Ids of stored links following `after`, tombstones included.
+    pub(crate) async fn ids(
+        &self,
+        after: Option<u64>,
+        count: u64,
+    ) -> Result<Vec<u64>, BackendError> {
+        let mut con = self.client.clone();
+        let start = after
+            .map(|after| format!("({}", id_member(after)))
+            .unwrap_or_else(|| "-".to_owned());
+        let members: Vec<String> = redis::cmd("ZRANGE")
+            .arg(self.keyspace.ids())
+            .arg(start)
+            .arg("+")
+            .arg("BYLEX")
+            .arg("LIMIT")
+            .arg(0)
+            .arg(count)
+            .query_async(&mut con)
+            .await?;
+        Ok(members
+            .iter()
+            .filter_map(|member| member.parse().ok())
+            .collect())
+    }
+
+    /// Whether ids of links stored before the ids sorted set was introduced
+    /// are indexed already, see [`RedisBackend::index_ids`].
+    pub(crate) async fn ids_indexed(&self) -> Result<bool, BackendError> {
+        let mut con = self.client.clone();
+        Ok(redis::cmd("EXISTS")
+            .arg(self.keyspace.ids_indexed())
+            .query_async(&mut con)
+            .await?)
+    }
+
+    pub(crate) async fn mark_ids_indexed(&self) -> Result<(), BackendError> {
+        let mut con = self.client.clone();
+        redis::cmd("SET")
+            .arg(self.keyspace.ids_indexed())
+            .arg(1)
+            .query_async::<_, ()>(&mut con)
+            .await?;
+        Ok(())
+    }
+
+    /// Add those of `ids` which are stored to the ids sorted set.
+    pub(crate) async fn index_existing(&self, ids: &[u64]) -> Result<(), BackendError> {
+        if ids.is_empty() {
+            return Ok(());
+        }
+        let mut con = self.client.clone();
+        let mut pipeline = redis::pipe();
+        for id in ids {
+            pipeline.cmd("EXISTS").arg(self.keyspace.link(*id));
+        }
+        let exists: Vec<bool> = pipeline.query_async(&mut con).await?;
+        let members = ids
+            .iter()
+            .zip(exists)
+            .filter(|(_, exists)| *exists)
+            .map(|(id, _)| id_member(*id))
+            .collect::<Vec<_>>();
+        if members.is_empty() {
+            return Ok(());
+        }
+        let mut command = redis::cmd("ZADD");
+        command.arg(self.keyspace.ids());
+        for member in members {
+            command.arg(0).arg(member);
+        }
+        command.query_async::<_, ()>(&mut con).await?;
+        Ok(())
+    }
+
+    /// Index links stored before the ids sorted set was introduced. Ids of
+    /// those were allocated sequentially, so ids up to the `LID` counter are
+    /// probed in pipelined batches. Runs once per keyspace.
+    pub async fn index_ids(&self) -> Result<(), BackendError> {
+        if self.ids_indexed().await? {
+            return Ok(());
+        }
+        let last_id = self.last_id().await?;
+        info!("Index ids of links up to {}", last_id);
+        let mut start = 1;
+        while start <= last_id {
+            let end = start.saturating_add(LIST_CHUNK - 1).min(last_id);
+            self.index_existing(&(start..=end).collect::<Vec<_>>())
+                .await?;
+            start = end.saturating_add(1);
+        }
+        self.mark_ids_indexed().await
+    }
+
+    /// Destination of a link without recording a click, a tombstoned link
+    /// fails with [`BackendError::Deleted`].
+    async fn get(&self, id: u64) -> Result<String, BackendError> {
+        let mut con = self.client.clone();
+        let (url, deleted): (Option<String>, bool) = redis::pipe()
+            .cmd("GET")
+            .arg(self.keyspace.link(id))
+            .cmd("HEXISTS")
+            .arg(self.keyspace.meta(id))
+            .arg("deleted_at")
+            .query_async(&mut con)
+            .await?;
+        match url {
+            Some(_) if deleted => Err(BackendError::Deleted),
+            Some(url) => Ok(url),
+            None => Err(BackendError::NotFound),
+        }
+    }
+
+    /// Destinations of `ids` fetched in one pipeline, without recording clicks.
+    /// Tombstoned links fail with [`BackendError::Deleted`].
+    pub async fn get_many(
+        &self,
+        ids: &[u64],
+    ) -> Result<Vec<Result<String, BackendError>>, BackendError> {
+        Ok(self
+            .lookup_many(ids)
+            .await?
+            .into_iter()
+            .map(|(url, deleted)| match url {
+                Some(_) if deleted => Err(BackendError::Deleted),
+                Some(url) => Ok(url),
+                None => Err(BackendError::NotFound),
+            })
+            .collect())
+    }
+
+    /// Destinations of `ids`, when stored, and whether they are tombstoned.
+    async fn lookup_many(&self, ids: &[u64]) -> Result<Vec<(Option<String>, bool)>, BackendError> {
+        if ids.is_empty() {
+            return Ok(Vec::new());
+        }
+        let mut con = self.client.clone();
+        let mut pipeline = redis::pipe();
+        for id in ids {
+            pipeline
+                .cmd("GET")
+                .arg(self.keyspace.link(*id))
+                .cmd("HEXISTS")
+                .arg(self.keyspace.meta(*id))
+                .arg("deleted_at");
+        }
+        Ok(pipeline.query_async(&mut con).await?)
+    }
+
+    /// Links following `after` read from the ids sorted set in batches,
+    /// tombstones only with `tombstones` set.
+    async fn links(
+        &self,
+        after: Option<u64>,
+        limit: usize,
+        tombstones: bool,
+    ) -> Result<Vec<(u64, String)>, BackendError> {
+        let mut links = Vec::new();
+        let mut after = after;
+        while links.len() < limit {
+            let ids = self.ids(after, LIST_CHUNK).await?;
+            let Some(last) = ids.last().copied() else {
+                break;
+            };
+            let found = self.lookup_many(&ids).await?;
+            links.extend(
+                ids.into_iter()
+                    .zip(found)
+                    .filter_map(|(id, (url, deleted))| {
+                        url.filter(|_| tombstones || !deleted).map(|url| (id, url))
+                    }),
+            );
+            after = Some(last);
+        }
+        links.truncate(limit);
+        Ok(links)
+    }
+
+    /// Queue of clicks to record on the primary, its worker is started on
+    /// first use so it sees the final keyspace.
+    fn pending_clicks(&self) -> &mpsc::Sender<u64> {
+        self.clicks.get_or_init(|| {
+            let (sender, mut receiver) = mpsc::channel(MAX_PENDING_CLICKS);
+            let con = self.client.clone();
+            let keyspace = self.keyspace.clone();
+            tokio::spawn(async move {
+                while let Some(id) = receiver.recv().await {
+                    if let Err(error) = record_click(con.clone(), &keyspace, id).await {
+                        warn!("Unable to record click for {}: {}", id, error);
+                    }
+                }
+            });
+            sender
+        })
+    }
+
+    /// Look the destination and its version up on the next available
+    /// replica in turn.
+    async fn replica_lookup(&self, id: u64) -> Option<(String, u64)> {
+        if self.replicas.is_empty() {
+            return None;
+        }
+        let start = self.next_replica.fetch_add(1, Ordering::Relaxed);
+        let replica = (0..self.replicas.len())
+            .map(|offset| &self.replicas[(start + offset) % self.replicas.len()])
+            .find(|replica| replica.is_available())?;
+        let mut con = replica.connection()?;
+        match redis::pipe()
+            .cmd("GET")
+            .arg(self.keyspace.link(id))
+            .cmd("GET")
+            .arg(self.keyspace.version(id))
+            .cmd("HEXISTS")
+            .arg(self.keyspace.meta(id))
+            .arg("deleted_at")
+            .query_async::<_, (Option<String>, Option<u64>, bool)>(&mut con)
+            .await
+        {
+            // Tombstones are reported by the primary
+            Ok((_, _, true)) => {
+                replica.succeeded();
+                None
+            }
+            Ok((url, version, _)) => {
+                replica.succeeded();
+                url.map(|url| (url, version.unwrap_or(1)))
+            }
+            Err(error) => {
+                warn!(
+                    "Redis replica lookup failed, fallback to primary: {}",
+                    error
+                );
+                replica.failed();
+                None
+            }
+        }
+    }
+
+    /// Move keys written before namespaces were introduced into the
+    /// configured keyspace, see [`Keyspace::migrated`] for the keys
+    /// concerned. Keys already existing in the keyspace are left untouched.
+    /// With `dry_run` keys are only listed. Returns number of moved keys.
+    ///
+    /// Ids are indexed at startup, before moved links were in place, so the
+    /// index is built again afterwards.
+    pub async fn migrate_keyspace(&self, dry_run: bool) -> Result<u64, BackendError> {
+        let moved = self.move_legacy_keys(dry_run).await?;
+        if !dry_run && moved > 0 {
+            self.index_ids().await?;
+        }
+        Ok(moved)
+    }
+
+    /// Keyspace migration without indexing, the index is marked to be built
+    /// again when keys were moved.
+    pub(crate) async fn move_legacy_keys(&self, dry_run: bool) -> Result<u64, BackendError> {
+        if self.client.is_cluster() {
+            return Err(BackendError::Internal(
+                "Keyspace migration is not supported for Redis Cluster".into(),
+            ));
+        }
+        let mut con = self.client.clone();
+        let mut cursor = 0;
+        let mut moved = 0;
+        loop {
+            let (next, keys): (u64, Vec<String>) = redis::cmd("SCAN")
+                .arg(cursor)
+                .arg("COUNT")
+                .arg(MIGRATION_SCAN_COUNT)
+                .query_async(&mut con)
+                .await?;
+            for key in keys {
+                let Some(target) = self.keyspace.migrated(&key) else {
+                    continue;
+                };
+                if dry_run {
+                    let exists: bool = redis::cmd("EXISTS")
+                        .arg(&target)
+                        .query_async(&mut con)
+                        .await?;
+                    if exists {
+                        warn!("Would skip migration of {}: {} already exists", key, target);
+                    } else {
+                        info!("Would move {} to {}", key, target);
+                        moved += 1;
+                    }
+                    continue;
+                }
+                let renamed: bool = redis::cmd("RENAMENX")
+                    .arg(&key)
+                    .arg(&target)
+                    .query_async(&mut con)
+                    .await?;
+                if renamed {
+                    moved += 1;
+                } else {
+                    warn!("Skip migration of {}: {} already exists", key, target);
+                }
+            }
+            if next == 0 {
+                break;
+            }
+            cursor = next;
+        }
+        if !dry_run {
+            info!("Moved {} legacy keys", moved);
+            if moved > 0 {
+                redis::cmd("DEL")
+                    .arg(self.keyspace.ids_indexed())
+                    .query_async::<_, ()>(&mut con)
+                    .await?;
+            }
+        }
+        Ok(moved)
+    }
+
     async fn server_version(&self) -> Option<Version> {
         let info = redis::cmd("INFO")
             .arg("SERVER")
@@ -94,77 +968,606 @@ impl RedisBackend {
 #[async_trait]
 impl Backend for RedisBackend {
     async fn store<'a>(&self, url: &'a str) -> Result<u64, BackendError> {
+        if self.client.is_cluster() {
+            // LID counter and the link key live in different slots
+            let id = self.allocate_ids(1).await?[0];
+            self.insert(id, url).await?;
+            return Ok(id);
+        }
         let mut con = self.client.clone();
         let script = Script::new(STORE_SCRIPT);
-        let result = script.arg(url).invoke_async(&mut con).await?;
+        let result = script
+            .key(self.keyspace.last_id())
+            .arg(url)
+            .arg(self.keyspace.prefix())
+            .arg(to_micros(now()))
+            .invoke_async(&mut con)
+            .await?;
         Ok(result)
     }
 
-    async fn retrive(&self, id: u64) -> Result<String, BackendError> {
+    async fn allocate_ids(&self, count: u64) -> Result<Vec<u64>, BackendError> {
+        let mut con = self.client.clone();
+        let last: u64 = redis::cmd("INCRBY")
+            .arg(self.keyspace.last_id())
+            .arg(count)
+            .query_async(&mut con)
+            .await?;
+        Ok((last + 1 - count..=last).collect())
+    }
+
+    async fn reserve_until(&self, id: u64) -> Result<(), BackendError> {
+        self.reserve_id(id).await
+    }
+
+    async fn store_with_id<'a>(&self, id: u64, url: &'a str) -> Result<(), BackendError> {
+        self.insert(id, url).await?;
+        self.reserve_id(id).await
+    }
+
+    async fn retrive_versioned(&self, id: u64) -> Result<(String, u64), BackendError> {
+        if let Some(link) = self.replica_lookup(id).await {
+            if let Err(TrySendError::Full(id) | TrySendError::Closed(id)) =
+                self.pending_clicks().try_send(id)
+            {
+                if let Err(error) = record_click(self.client.clone(), &self.keyspace, id).await {
+                    warn!("Unable to record click for {}: {}", id, error);
+                }
+            }
+            return Ok(link);
+        }
         let mut con = self.client.clone();
-        let uuid = Uuid::new_v4();
         let now = Utc::now();
-        let date = now.date().format(KEY_DATE_FORMAT).to_string();
         let ts = now.timestamp();
-        let member = format!("{}:{}", ts, uuid);
+        let member = click_member(ts);
         let script = Script::new(RETRIVE_SCRIPT);
         let result = script
-            .arg(id)
-            .arg(date)
+            .key(self.keyspace.link(id))
+            .key(self.keyspace.stat(id, now.date_naive()))
+            .key(self.keyspace.version(id))
+            .key(self.keyspace.meta(id))
             .arg(ts)
             .arg(member)
-            .invoke_async::<_, Option<String>>(&mut con)
-            .await?
-            .ok_or(BackendError::NotFound)?;
-        Ok(result)
+            .invoke_async::<_, Value>(&mut con)
+            .await?;
+        match result {
+            Value::Nil => Err(BackendError::NotFound),
+            Value::Int(_) => Err(BackendError::Deleted),
+            result => Ok(redis::from_redis_value(&result)?),
+        }
     }
 
     async fn stat(&self, id: u64, since: Option<DateTime<Utc>>) -> Result<u64, BackendError> {
         let mut con = self.client.clone();
         let now = Utc::now();
-        let today = now.date();
-        let yesterday = today.pred();
+        let today = now.date_naive();
+        let yesterday = today.pred_opt().ok_or(BackendError::DateTimeOverflow)?;
         let since = since
             .or_else(|| now.checked_sub_signed(Duration::hours(DEFAULT_STAT_PERIOD_IN_HOURS)))
             .ok_or(BackendError::DateTimeOverflow)?;
         let stat = Script::new(RETRIVE_STAT)
-            .arg(id)
-            .arg(today.format(KEY_DATE_FORMAT).to_string())
-            .arg(yesterday.format(KEY_DATE_FORMAT).to_string())
+            .key(self.keyspace.stat(id, today))
+            .key(self.keyspace.stat(id, yesterday))
+            .key(self.keyspace.link(id))
             .arg(since.timestamp())
             .arg(now.timestamp())
+            .invoke_async::<_, Option<u64>>(&mut con)
+            .await?
+            .ok_or(BackendError::NotFound)?;
+        Ok(stat)
+    }
+
+    /// The new destination is indexed after the update, see
+    /// [`RedisBackend::find`].
+    async fn update_versioned<'a>(
+        &self,
+        id: u64,
+        url: &'a str,
+        expected: Option<u64>,
+    ) -> Result<u64, BackendError> {
+        let mut con = self.client.clone();
+        let version = Script::new(UPDATE_SCRIPT)
+            .key(self.keyspace.link(id))
+            .key(self.keyspace.version(id))
+            .key(self.keyspace.meta(id))
+            .key(self.keyspace.history(id))
+            .arg(url)
+            .arg(
+                expected
+                    .map(|version| version.to_string())
+                    .unwrap_or_default(),
+            )
+            .arg(to_micros(now()))
+            .invoke_async::<_, Option<i64>>(&mut con)
+            .await?
+            .ok_or(BackendError::NotFound)?;
+        let version = match version {
+            -2 => Err(BackendError::Deleted),
+            version => u64::try_from(version).map_err(|_| BackendError::VersionMismatch),
+        }?;
+        redis::cmd("SADD")
+            .arg(self.keyspace.destination(url))
+            .arg(id)
+            .query_async::<_, ()>(&mut con)
+            .await?;
+        Ok(version)
+    }
+
+    /// The tombstone is written to the link meta hash, the tombstones set
+    /// indexing it for purging is updated afterwards.
+    async fn delete(&self, id: u64) -> Result<(), BackendError> {
+        let mut con = self.client.clone();
+        let deleted_at = to_micros(now());
+        let result: i64 = Script::new(DELETE_SCRIPT)
+            .key(self.keyspace.link(id))
+            .key(self.keyspace.meta(id))
+            .arg(deleted_at)
             .invoke_async(&mut con)
             .await?;
-        Ok(stat)
+        guarded(result)?;
+        redis::cmd("ZADD")
+            .arg(self.keyspace.tombstones())
+            .arg(deleted_at)
+            .arg(id)
+            .query_async::<_, ()>(&mut con)
+            .await?;
+        Ok(())
     }
 
-    async fn update<'a>(&self, id: u64, url: &'a str) -> Result<(), BackendError> {
+    async fn undelete(&self, id: u64) -> Result<(), BackendError> {
         let mut con = self.client.clone();
-        let res: Option<()> = redis::cmd("SET")
+        let result: i64 = Script::new(UNDELETE_SCRIPT)
+            .key(self.keyspace.link(id))
+            .key(self.keyspace.meta(id))
+            .invoke_async(&mut con)
+            .await?;
+        guarded(result)?;
+        redis::cmd("ZREM")
+            .arg(self.keyspace.tombstones())
             .arg(id)
-            .arg(url)
-            .arg("XX")
+            .query_async::<_, ()>(&mut con)
+            .await?;
+        Ok(())
+    }
+
+    /// Tombstones are found through the tombstones set, each one is checked
+    /// against its meta hash before removal, so links undeleted meanwhile
+    /// survive.
+    async fn purge(&self, before: DateTime<Utc>) -> Result<u64, BackendError> {
+        let (purged, aliases) = self.purge_links(before).await?;
+        self.drop_aliases(&aliases).await?;
+        Ok(purged)
+    }
+
+    async fn click(&self, id: u64) -> Result<(), BackendError> {
+        record_click(self.client.clone(), &self.keyspace, id).await
+    }
+
+    /// Ids are read from the ids sorted set in batches, tombstones are
+    /// skipped after fetching the destinations.
+    async fn list(
+        &self,
+        after: Option<u64>,
+        limit: usize,
+    ) -> Result<Vec<(u64, String)>, BackendError> {
+        self.links(after, limit, false).await
+    }
+
+    async fn list_with_tombstones(
+        &self,
+        after: Option<u64>,
+        limit: usize,
+    ) -> Result<Vec<(u64, String)>, BackendError> {
+        self.links(after, limit, true).await
+    }
+
+    /// Destination sets are only added to, in cluster they live in other
+    /// slots than the links. Members are looked up one by one, those which
+    /// point elsewhere by now or were purged are dropped here.
+    async fn find<'a>(&self, url: &'a str) -> Result<Vec<u64>, BackendError> {
+        let mut con = self.client.clone();
+        let key = self.keyspace.destination(url);
+        let mut ids: Vec<u64> = redis::cmd("SMEMBERS")
+            .arg(&key)
             .query_async(&mut con)
-            .await
-            .map_err(BackendError::from)?;
-        res.ok_or(BackendError::NotFound)
+            .await?;
+        ids.sort_unstable();
+        let mut found = Vec::new();
+        let mut stale = Vec::new();
+        for id in ids {
+            match self.get(id).await {
+                Ok(current) if current == url => found.push(id),
+                Ok(_) | Err(BackendError::NotFound) => stale.push(id),
+                Err(BackendError::Deleted) => {}
+                Err(error) => return Err(error),
+            }
+        }
+        if !stale.is_empty() {
+            redis::cmd("SREM")
+                .arg(&key)
+                .arg(stale)
+                .query_async::<_, ()>(&mut con)
+                .await?;
+        }
+        Ok(found)
     }
 
-    async fn delete(&self, id: u64) -> Result<(), BackendError> {
+    async fn clicks(&self, id: u64) -> Result<Clicks, BackendError> {
+        let mut con = self.client.clone();
+        let today = Utc::now().date_naive();
+        let yesterday = today.pred_opt().ok_or(BackendError::DateTimeOverflow)?;
+        let exists: bool = redis::cmd("EXISTS")
+            .arg(self.keyspace.link(id))
+            .query_async(&mut con)
+            .await?;
+        if !exists {
+            return Err(BackendError::NotFound);
+        }
+        let mut clicks = BTreeMap::new();
+        for date in [yesterday, today] {
+            let members: Vec<(String, i64)> = redis::cmd("ZRANGE")
+                .arg(self.keyspace.stat(id, date))
+                .arg(0)
+                .arg(-1)
+                .arg("WITHSCORES")
+                .query_async(&mut con)
+                .await?;
+            for (_, ts) in members {
+                *clicks.entry(ts).or_insert(0) += 1;
+            }
+        }
+        Ok(clicks
+            .into_iter()
+            .filter_map(|(ts, counter)| DateTime::from_timestamp(ts, 0).map(|ts| (ts, counter)))
+            .collect())
+    }
+
+    /// Clicks older than yesterday are dropped, as they would already have
+    /// expired.
+    async fn restore<'a>(
+        &self,
+        id: u64,
+        link: &'a Link,
+        history: &'a [Revision],
+        clicks: &'a [(DateTime<Utc>, u64)],
+    ) -> Result<(), BackendError> {
         let mut con = self.client.clone();
-        let today = Utc::now().date();
-        let yesterday = today.pred();
-        let res: u64 = redis::cmd("DEL")
+        let today = Utc::now().date_naive();
+        let yesterday = today.pred_opt().ok_or(BackendError::DateTimeOverflow)?;
+        let mut pipeline = redis::pipe();
+        pipeline
+            .cmd("SET")
+            .arg(self.keyspace.link(id))
+            .arg(&link.url)
+            .ignore()
+            .cmd("SET")
+            .arg(self.keyspace.version(id))
+            .arg(link.version)
+            .ignore()
+            .cmd("DEL")
+            .arg(self.keyspace.meta(id))
+            .arg(self.keyspace.history(id))
+            .arg(self.keyspace.stat(id, today))
+            .arg(self.keyspace.stat(id, yesterday))
+            .ignore()
+            .cmd("SADD")
+            .arg(self.keyspace.destination(&link.url))
             .arg(id)
-            .arg(format!("stat:{}:{}", id, today.format(KEY_DATE_FORMAT)))
-            .arg(format!("stat:{}:{}", id, yesterday.format(KEY_DATE_FORMAT)))
+            .ignore()
+            .cmd("ZADD")
+            .arg(self.keyspace.ids())
+            .arg(0)
+            .arg(id_member(id))
+            .ignore();
+        let revisions = pipeline.cmd("HSET").arg(self.keyspace.history(id));
+        for revision in Revision::restored(link, history) {
+            revisions
+                .arg(revision.version)
+                .arg(revision_entry(revision.created_at, &revision.url));
+        }
+        revisions.ignore();
+        let meta = pipeline
+            .cmd("HSET")
+            .arg(self.keyspace.meta(id))
+            .arg("metadata")
+            .arg(serde_json::to_string(&link.metadata)?);
+        if let Some(created_at) = link.created_at {
+            meta.arg("created_at").arg(to_micros(created_at));
+        }
+        if let Some(updated_at) = link.updated_at {
+            meta.arg("updated_at").arg(to_micros(updated_at));
+        }
+        if let Some(deleted_at) = link.deleted_at {
+            meta.arg("deleted_at").arg(to_micros(deleted_at));
+        }
+        meta.ignore();
+        match link.deleted_at {
+            Some(deleted_at) => pipeline
+                .cmd("ZADD")
+                .arg(self.keyspace.tombstones())
+                .arg(to_micros(deleted_at))
+                .arg(id)
+                .ignore(),
+            None => pipeline
+                .cmd("ZREM")
+                .arg(self.keyspace.tombstones())
+                .arg(id)
+                .ignore(),
+        };
+        for (ts, counter) in clicks {
+            let date = ts.date_naive();
+            if date != today && date != yesterday {
+                continue;
+            }
+            let key = self.keyspace.stat(id, date);
+            for _ in 0..*counter {
+                pipeline
+                    .cmd("ZADD")
+                    .arg(&key)
+                    .arg(ts.timestamp())
+                    .arg(click_member(ts.timestamp()))
+                    .ignore();
+            }
+            pipeline
+                .cmd("EXPIRE")
+                .arg(&key)
+                .arg(STAT_TTL_IN_SECONDS)
+                .arg("NX")
+                .ignore();
+        }
+        pipeline.query_async::<_, ()>(&mut con).await?;
+        self.reserve_id(id).await
+    }
+
+    /// Ids are allocated with a single `INCRBY`, links are written in one
+    /// pipeline.
+    async fn store_many<'a>(
+        &self,
+        urls: &'a [String],
+    ) -> Result<Vec<Result<u64, BackendError>>, BackendError> {
+        if urls.is_empty() {
+            return Ok(Vec::new());
+        }
+        let ids = self.allocate_ids(urls.len() as u64).await?;
+        let links = ids
+            .into_iter()
+            .zip(urls.iter().map(String::as_str))
+            .collect::<Vec<_>>();
+        let results = self.insert_many(&links).await?;
+        Ok(links
+            .into_iter()
+            .zip(results)
+            .map(|((id, _), result)| result.map(|_| id))
+            .collect())
+    }
+
+    /// Links are written in one pipeline, the counter is moved once.
+    async fn store_many_with_ids<'a>(
+        &self,
+        links: &'a [(u64, &'a str)],
+    ) -> Result<Vec<Result<(), BackendError>>, BackendError> {
+        let results = self.insert_many(links).await?;
+        if let Some(last) = links.iter().map(|(id, _)| *id).max() {
+            self.reserve_id(last).await?;
+        }
+        Ok(results)
+    }
+
+    async fn retrive_many<'a>(
+        &self,
+        ids: &'a [u64],
+    ) -> Result<Vec<Result<String, BackendError>>, BackendError> {
+        let urls = self.get_many(ids).await?;
+        let now = Utc::now();
+        let ts = now.timestamp();
+        let found = ids
+            .iter()
+            .zip(&urls)
+            .filter_map(|(id, url)| url.as_ref().ok().map(|_| *id))
+            .collect::<Vec<_>>();
+        if found.is_empty() {
+            return Ok(urls);
+        }
+        let mut pipeline = redis::pipe();
+        for id in found {
+            let key = self.keyspace.stat(id, now.date_naive());
+            pipeline
+                .cmd("ZADD")
+                .arg(&key)
+                .arg(ts)
+                .arg(click_member(ts))
+                .ignore()
+                .cmd("EXPIRE")
+                .arg(&key)
+                .arg(STAT_TTL_IN_SECONDS)
+                .arg("NX")
+                .ignore();
+        }
+        pipeline
+            .query_async::<_, ()>(&mut self.client.clone())
+            .await?;
+        Ok(urls)
+    }
+
+    /// Tombstones are written with pipelined `EVAL`s.
+    async fn delete_many<'a>(
+        &self,
+        ids: &'a [u64],
+    ) -> Result<Vec<Result<(), BackendError>>, BackendError> {
+        if ids.is_empty() {
+            return Ok(Vec::new());
+        }
+        let mut con = self.client.clone();
+        let deleted_at = to_micros(now());
+        let mut pipeline = redis::pipe();
+        for id in ids {
+            pipeline
+                .cmd("EVAL")
+                .arg(DELETE_SCRIPT)
+                .arg(2)
+                .arg(self.keyspace.link(*id))
+                .arg(self.keyspace.meta(*id))
+                .arg(deleted_at);
+        }
+        let results: Vec<i64> = pipeline.query_async(&mut con).await?;
+        let results = results.into_iter().map(guarded).collect::<Vec<_>>();
+        let mut pipeline = redis::pipe();
+        for (id, _) in ids
+            .iter()
+            .zip(&results)
+            .filter(|(_, result)| result.is_ok())
+        {
+            pipeline
+                .cmd("ZADD")
+                .arg(self.keyspace.tombstones())
+                .arg(deleted_at)
+                .arg(*id)
+                .ignore();
+        }
+        pipeline.query_async::<_, ()>(&mut con).await?;
+        Ok(results)
+    }
+
+    async fn info(&self, id: u64) -> Result<Link, BackendError> {
+        let mut con = self.client.clone();
+        let (url, version, meta): (Option<String>, Option<u64>, HashMap<String, String>) =
+            redis::pipe()
+                .cmd("GET")
+                .arg(self.keyspace.link(id))
+                .cmd("GET")
+                .arg(self.keyspace.version(id))
+                .cmd("HGETALL")
+                .arg(self.keyspace.meta(id))
+                .query_async(&mut con)
+                .await?;
+        let url = url.ok_or(BackendError::NotFound)?;
+        let metadata = meta
+            .get("metadata")
+            .map(|metadata| serde_json::from_str(metadata))
+            .transpose()?
+            .unwrap_or_default();
+        Ok(Link {
+            url,
+            version: version.unwrap_or(1),
+            created_at: from_micros(meta.get("created_at")),
+            updated_at: from_micros(meta.get("updated_at")),
+            deleted_at: from_micros(meta.get("deleted_at")),
+            metadata,
+        })
+    }
+
+    async fn history(&self, id: u64) -> Result<Vec<Revision>, BackendError> {
+        let mut con = self.client.clone();
+        let (url, version, history): (Option<String>, Option<u64>, HashMap<String, String>) =
+            redis::pipe()
+                .cmd("GET")
+                .arg(self.keyspace.link(id))
+                .cmd("GET")
+                .arg(self.keyspace.version(id))
+                .cmd("HGETALL")
+                .arg(self.keyspace.history(id))
+                .query_async(&mut con)
+                .await?;
+        let url = url.ok_or(BackendError::NotFound)?;
+        let mut history = history
+            .iter()
+            .filter_map(|(version, entry)| parse_revision(version, entry))
+            .collect::<Vec<_>>();
+        if history.is_empty() {
+            history.push(Revision {
+                version: version.unwrap_or(1),
+                url,
+                created_at: None,
+            });
+        }
+        history.sort_unstable_by_key(|revision| revision.version);
+        Ok(history)
+    }
+
+    /// The alias key is claimed before the alias is added to the set of the
+    /// link, in cluster they live in different slots.
+    async fn store_alias<'a>(&self, alias: &'a str, id: u64) -> Result<(), BackendError> {
+        if !self.exists(id).await? {
+            return Err(BackendError::NotFound);
+        }
+        self.claim_alias(alias, id).await?;
+        self.link_alias(id, alias).await
+    }
+
+    /// The alias is claimed first and released again when the link can not
+    /// be stored.
+    async fn store_with_alias<'a>(
+        &self,
+        id: u64,
+        url: &'a str,
+        alias: &'a str,
+    ) -> Result<(), BackendError> {
+        self.claim_alias(alias, id).await?;
+        if let Err(error) = self.store_with_id(id, url).await {
+            self.drop_aliases(&[alias.to_owned()]).await?;
+            return Err(error);
+        }
+        self.link_alias(id, alias).await
+    }
+
+    async fn resolve_alias<'a>(&self, alias: &'a str) -> Result<u64, BackendError> {
+        let mut con = self.client.clone();
+        let id: Option<u64> = redis::cmd("GET")
+            .arg(self.keyspace.alias(alias))
             .query_async(&mut con)
-            .await
-            .map_err(BackendError::from)?;
-        if res == 0 {
-            Err(BackendError::NotFound)
-        } else {
-            Ok(())
+            .await?;
+        id.ok_or(BackendError::NotFound)
+    }
+
+    async fn aliases(&self, id: u64) -> Result<Vec<String>, BackendError> {
+        let mut con = self.client.clone();
+        let mut aliases: Vec<String> = redis::cmd("SMEMBERS")
+            .arg(self.keyspace.aliases(id))
+            .query_async(&mut con)
+            .await?;
+        aliases.sort_unstable();
+        Ok(aliases)
+    }
+
+    /// The code key is claimed before the link key is set, in cluster they
+    /// live in different slots. A claim of a link having a code already is
+    /// released.
+    async fn store_code<'a>(&self, id: u64, code: &'a str) -> Result<String, BackendError> {
+        self.claim_code(code, id).await?;
+        let linked = self.link_code(id, code).await?;
+        if linked != code {
+            self.release_code(code).await?;
         }
+        Ok(linked)
+    }
+
+    async fn resolve_code<'a>(&self, code: &'a str) -> Result<u64, BackendError> {
+        let mut con = self.client.clone();
+        let id: Option<u64> = redis::cmd("GET")
+            .arg(self.keyspace.code(code))
+            .query_async(&mut con)
+            .await?;
+        id.ok_or(BackendError::NotFound)
+    }
+
+    async fn code(&self, id: u64) -> Result<String, BackendError> {
+        let mut con = self.client.clone();
+        let code: Option<String> = redis::cmd("GET")
+            .arg(self.keyspace.link_code(id))
+            .query_async(&mut con)
+            .await?;
+        code.ok_or(BackendError::NotFound)
+    }
+
+    async fn set_metadata<'a>(&self, id: u64, metadata: &'a Metadata) -> Result<(), BackendError> {
+        let mut con = self.client.clone();
+        let updated: i64 = Script::new(SET_METADATA_SCRIPT)
+            .key(self.keyspace.link(id))
+            .key(self.keyspace.meta(id))
+            .arg(serde_json::to_string(metadata)?)
+            .arg(to_micros(now()))
+            .invoke_async(&mut con)
+            .await?;
+        guarded(updated)
     }
 }
@@ -0,0 +1,248 @@
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, RwLock},
+};
+
+use redis::{
+    aio::{ConnectionLike, ConnectionManager},
+    cluster::ClusterClient,
+    cluster_async::ClusterConnection,
+    cluster_routing::get_slot,
+    sentinel::{Sentinel, SentinelNodeConnectionInfo},
+    Arg, Client, Cmd, ErrorKind, IntoConnectionInfo, Pipeline, RedisError, RedisFuture,
+    RedisResult, Value,
+};
+use tokio::sync::Mutex;
+use tracing::{info, warn};
+
+/// Connection to the primary of a Sentinel managed deployment.
+///
+/// The master address is resolved through the sentinels at startup and
+/// again whenever the current master stops answering or turns read-only.
+pub(crate) struct SentinelConnection {
+    sentinel: Mutex<Sentinel>,
+    service: String,
+    node: SentinelNodeConnectionInfo,
+    manager: RwLock<ConnectionManager>,
+}
+
+impl SentinelConnection {
+    async fn connect(
+        mut sentinel: Sentinel,
+        service: String,
+        node: SentinelNodeConnectionInfo,
+    ) -> RedisResult<Self> {
+        let manager = Self::master(&mut sentinel, &service, &node).await?;
+        Ok(Self {
+            sentinel: Mutex::new(sentinel),
+            service,
+            node,
+            manager: RwLock::new(manager),
+        })
+    }
+
+    async fn master(
+        sentinel: &mut Sentinel,
+        service: &str,
+        node: &SentinelNodeConnectionInfo,
+    ) -> RedisResult<ConnectionManager> {
+        let client = sentinel.async_master_for(service, Some(node)).await?;
+        info!(
+            "Redis sentinel master for {}: {}",
+            service,
+            client.get_connection_info().addr
+        );
+        client.get_connection_manager().await
+    }
+
+    fn current(&self) -> ConnectionManager {
+        self.manager
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .clone()
+    }
+
+    async fn failover(&self) -> RedisResult<ConnectionManager> {
+        let mut sentinel = self.sentinel.lock().await;
+        let manager = Self::master(&mut sentinel, &self.service, &self.node).await?;
+        *self
+            .manager
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = manager.clone();
+        Ok(manager)
+    }
+}
+
+fn is_failover(error: &RedisError) -> bool {
+    error.is_io_error()
+        || error.is_connection_dropped()
+        || error.is_connection_refusal()
+        || error.kind() == ErrorKind::ReadOnly
+}
+
+/// Slot of the key of `cmd`: its first argument, or the first key of a
+/// script.
+fn slot(cmd: &Cmd) -> Option<u16> {
+    let args = cmd
+        .args_iter()
+        .map(|arg| match arg {
+            Arg::Simple(arg) => Some(arg),
+            Arg::Cursor => None,
+        })
+        .collect::<Option<Vec<_>>>()?;
+    let name = args.first()?;
+    let key = if name.eq_ignore_ascii_case(b"EVAL") || name.eq_ignore_ascii_case(b"EVALSHA") {
+        let keys: u64 = std::str::from_utf8(args.get(2)?).ok()?.parse().ok()?;
+        if keys == 0 {
+            return None;
+        }
+        args.get(3)?
+    } else {
+        args.get(1)?
+    };
+    Some(get_slot(key))
+}
+
+/// Run `pipeline` as one pipeline per slot, cluster nodes refuse pipelines
+/// spanning several slots. Commands of a slot keep their order, replies are
+/// returned in the order of the commands.
+async fn query_by_slot(
+    connection: &mut ClusterConnection,
+    pipeline: &Pipeline,
+) -> RedisResult<Vec<Value>> {
+    let commands = pipeline.cmd_iter().collect::<Vec<_>>();
+    let mut groups: BTreeMap<Option<u16>, Vec<usize>> = BTreeMap::new();
+    for (index, cmd) in commands.iter().enumerate() {
+        groups.entry(slot(cmd)).or_default().push(index);
+    }
+    if groups.len() <= 1 {
+        return connection
+            .req_packed_commands(pipeline, 0, commands.len())
+            .await;
+    }
+    let mut values = vec![Value::Nil; commands.len()];
+    for indexes in groups.into_values() {
+        let mut group = redis::pipe();
+        for index in &indexes {
+            group.add_command(commands[*index].clone());
+        }
+        let replies = connection
+            .req_packed_commands(&group, 0, indexes.len())
+            .await?;
+        for (index, reply) in indexes.into_iter().zip(replies) {
+            values[index] = reply;
+        }
+    }
+    Ok(values)
+}
+
+/// Connection to any of the supported topologies. Pipelines sent to a
+/// cluster are split by slot, see [`query_by_slot`], so callers may mix
+/// keys of several links and the global indexes in one pipeline. Such
+/// pipelines are not atomic on any topology.
+#[derive(Clone)]
+pub(crate) enum RedisConnection {
+    Standalone(ConnectionManager),
+    Cluster(ClusterConnection),
+    Sentinel(Arc<SentinelConnection>),
+}
+
+impl RedisConnection {
+    pub async fn standalone<T: IntoConnectionInfo>(connection_info: T) -> RedisResult<Self> {
+        let client = Client::open(connection_info)?;
+        Ok(Self::Standalone(client.get_connection_manager().await?))
+    }
+
+    pub async fn cluster<T: IntoConnectionInfo>(nodes: Vec<T>) -> RedisResult<Self> {
+        let client = ClusterClient::new(nodes)?;
+        Ok(Self::Cluster(client.get_async_connection().await?))
+    }
+
+    /// `connection_info` supplies database and credentials of the master,
+    /// its address is ignored.
+    pub async fn sentinel<T: IntoConnectionInfo, M: IntoConnectionInfo>(
+        sentinels: Vec<T>,
+        service: &str,
+        connection_info: M,
+    ) -> RedisResult<Self> {
+        let sentinel = Sentinel::build(sentinels)?;
+        let node = SentinelNodeConnectionInfo {
+            tls_mode: None,
+            redis_connection_info: Some(connection_info.into_connection_info()?.redis),
+        };
+        let connection = SentinelConnection::connect(sentinel, service.to_owned(), node).await?;
+        Ok(Self::Sentinel(Arc::new(connection)))
+    }
+
+    pub fn is_cluster(&self) -> bool {
+        matches!(self, Self::Cluster(_))
+    }
+}
+
+impl ConnectionLike for RedisConnection {
+    fn req_packed_command<'a>(&'a mut self, cmd: &'a Cmd) -> RedisFuture<'a, Value> {
+        match self {
+            Self::Standalone(connection) => connection.req_packed_command(cmd),
+            Self::Cluster(connection) => connection.req_packed_command(cmd),
+            Self::Sentinel(sentinel) => Box::pin(async move {
+                match sentinel.current().req_packed_command(cmd).await {
+                    Err(error) if is_failover(&error) => {
+                        warn!(
+                            "Redis master failure, ask sentinels for a new one: {}",
+                            error
+                        );
+                        sentinel.failover().await?.req_packed_command(cmd).await
+                    }
+                    result => result,
+                }
+            }),
+        }
+    }
+
+    fn req_packed_commands<'a>(
+        &'a mut self,
+        cmd: &'a Pipeline,
+        offset: usize,
+        count: usize,
+    ) -> RedisFuture<'a, Vec<Value>> {
+        match self {
+            Self::Standalone(connection) => connection.req_packed_commands(cmd, offset, count),
+            // Only atomic pipelines skip replies, those must not span slots
+            Self::Cluster(connection) => {
+                if offset > 0 {
+                    connection.req_packed_commands(cmd, offset, count)
+                } else {
+                    Box::pin(query_by_slot(connection, cmd))
+                }
+            }
+            Self::Sentinel(sentinel) => Box::pin(async move {
+                match sentinel
+                    .current()
+                    .req_packed_commands(cmd, offset, count)
+                    .await
+                {
+                    Err(error) if is_failover(&error) => {
+                        warn!(
+                            "Redis master failure, ask sentinels for a new one: {}",
+                            error
+                        );
+                        sentinel
+                            .failover()
+                            .await?
+                            .req_packed_commands(cmd, offset, count)
+                            .await
+                    }
+                    result => result,
+                }
+            }),
+        }
+    }
+
+    fn get_db(&self) -> i64 {
+        match self {
+            Self::Standalone(connection) => connection.get_db(),
+            Self::Cluster(connection) => connection.get_db(),
+            Self::Sentinel(sentinel) => sentinel.current().get_db(),
+        }
+    }
+}
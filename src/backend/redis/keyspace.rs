@@ -0,0 +1,153 @@
+use chrono::NaiveDate;
+
+static KEY_DATE_FORMAT: &str = "%Y%m%d";
+
+/// Names of all keys used by the backend.
+///
+/// With an empty prefix links are stored under their bare numeric id, as
+/// before namespaces were introduced. Otherwise the id becomes a hash tag:
+/// `<prefix>{<id>}`. Stat keys always carry the id as a hash tag, so in
+/// Redis Cluster they share a slot with the link key.
+#[derive(Clone, Default, Debug)]
+pub(crate) struct Keyspace {
+    prefix: String,
+}
+
+impl Keyspace {
+    pub fn new<P: Into<String>>(prefix: P) -> Self {
+        Self {
+            prefix: prefix.into(),
+        }
+    }
+
+    pub fn prefix(&self) -> &str {
+        &self.prefix
+    }
+
+    pub fn last_id(&self) -> String {
+        format!("{}LID", self.prefix)
+    }
+
+    pub fn link(&self, id: u64) -> String {
+        if self.prefix.is_empty() {
+            id.to_string()
+        } else {
+            format!("{}{{{}}}", self.prefix, id)
+        }
+    }
+
+    pub fn stat(&self, id: u64, date: NaiveDate) -> String {
+        format!(
+            "{}stat:{{{}}}:{}",
+            self.prefix,
+            id,
+            date.format(KEY_DATE_FORMAT)
+        )
+    }
+
+    pub fn version(&self, id: u64) -> String {
+        format!("{}ver:{{{}}}", self.prefix, id)
+    }
+
+    /// Hash of link timestamps and metadata.
+    pub fn meta(&self, id: u64) -> String {
+        format!("{}meta:{{{}}}", self.prefix, id)
+    }
+
+    /// Hash of destinations keyed by version, see [`super::revision_entry`].
+    pub fn history(&self, id: u64) -> String {
+        format!("{}hist:{{{}}}", self.prefix, id)
+    }
+
+    /// Set of ids of links which pointed to `url` at some time.
+    pub fn destination(&self, url: &str) -> String {
+        format!("{}dest:{}", self.prefix, url)
+    }
+
+    /// Id of the link named `alias`.
+    pub fn alias(&self, alias: &str) -> String {
+        format!("{}alias:{}", self.prefix, alias)
+    }
+
+    /// Set of aliases of the link.
+    pub fn aliases(&self, id: u64) -> String {
+        format!("{}aliases:{{{}}}", self.prefix, id)
+    }
+
+    /// Id of the link with short code `code`.
+    pub fn code(&self, code: &str) -> String {
+        format!("{}code:{}", self.prefix, code)
+    }
+
+    /// Short code of the link.
+    pub fn link_code(&self, id: u64) -> String {
+        format!("{}code_of:{{{}}}", self.prefix, id)
+    }
+
+    /// Sorted set of all stored link ids, see [`super::id_member`].
+    pub fn ids(&self) -> String {
+        format!("{}ids", self.prefix)
+    }
+
+    /// Set once ids of links stored before [`Keyspace::ids`] existed are
+    /// indexed.
+    pub fn ids_indexed(&self) -> String {
+        format!("{}ids:indexed", self.prefix)
+    }
+
+    /// Number of shards links are placed over, kept on the first shard.
+    pub fn shards(&self) -> String {
+        format!("{}shards", self.prefix)
+    }
+
+    /// Identities of the shards links are placed over in ring order,
+    /// separated by commas, kept on the first shard.
+    pub fn shard_list(&self) -> String {
+        format!("{}shards:list", self.prefix)
+    }
+
+    /// Random identity of the instance, drawn by the first start which uses
+    /// it as a shard.
+    pub fn shard_identity(&self) -> String {
+        format!("{}shards:identity", self.prefix)
+    }
+
+    /// Sorted set of tombstoned link ids scored by deletion time.
+    pub fn tombstones(&self) -> String {
+        format!("{}tombstones", self.prefix)
+    }
+
+    /// Name in this keyspace of a key written before namespaces were
+    /// introduced. Only the shapes written back then are claimed: links
+    /// under their bare id, `LID` and `stat:<id>:<date>`. `None` for any
+    /// other key, which may belong to another application sharing the
+    /// database, and for keys already named as in this keyspace. With an
+    /// empty prefix only stat keys are renamed, they gained the hash tag.
+    pub fn migrated(&self, legacy: &str) -> Option<String> {
+        self.legacy_target(legacy)
+            .filter(|target| target.as_str() != legacy)
+    }
+
+    fn legacy_target(&self, legacy: &str) -> Option<String> {
+        if legacy == "LID" {
+            return Some(self.last_id());
+        }
+        if let Ok(id) = legacy.parse::<u64>() {
+            // Ids start at 1 and were written without leading zeros
+            return (id > 0 && id.to_string() == legacy).then(|| self.link(id));
+        }
+        let (id, date) = legacy.strip_prefix("stat:")?.split_once(':')?;
+        let id = parse_tag(id)?;
+        let date = NaiveDate::parse_from_str(date, KEY_DATE_FORMAT).ok()?;
+        Some(self.stat(id, date))
+    }
+}
+
+/// Id written either bare or as a hash tag.
+fn parse_tag(id: &str) -> Option<u64> {
+    id.strip_prefix('{')
+        .and_then(|id| id.strip_suffix('}'))
+        .unwrap_or(id)
+        .parse()
+        .ok()
+}
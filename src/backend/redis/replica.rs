@@ -0,0 +1,105 @@
+use std::{
+    sync::{Arc, Mutex, Weak},
+    time::{Duration, Instant},
+};
+
+use redis::ConnectionInfo;
+use tracing::{info, warn};
+
+use super::connection::RedisConnection;
+
+/// Pause after the first failure of a replica, doubled on each further one.
+const MIN_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+#[derive(Default)]
+struct ReplicaState {
+    connection: Option<RedisConnection>,
+    /// Failures since the last successful lookup
+    failures: u32,
+    /// Lookups skip the replica until then
+    retry_at: Option<Instant>,
+}
+
+/// Read replica kept for the whole life of the backend.
+///
+/// The replica is connected by a background task, retrying with a growing
+/// backoff, so lookups never wait for a connection: until it is connected
+/// the replica is skipped. A replica failing to answer is skipped for a
+/// backoff period, after which a single lookup probes it again.
+pub(crate) struct Replica {
+    state: Arc<Mutex<ReplicaState>>,
+}
+
+impl Replica {
+    pub fn new(connection_info: ConnectionInfo) -> Self {
+        let state = Arc::default();
+        tokio::spawn(connect(connection_info, Arc::downgrade(&state)));
+        Self { state }
+    }
+
+    /// Whether lookups may use the replica now.
+    pub fn is_available(&self) -> bool {
+        let state = self.state.lock().unwrap();
+        state.connection.is_some() && state.retry_at.is_none_or(|at| at <= Instant::now())
+    }
+
+    /// Connection for a lookup, none while connecting or backing off. The
+    /// backoff of a failing replica is extended before probing, so
+    /// concurrent lookups do not all wait for it.
+    pub fn connection(&self) -> Option<RedisConnection> {
+        let mut state = self.state.lock().unwrap();
+        if state.retry_at.is_some_and(|at| at > Instant::now()) {
+            return None;
+        }
+        if state.failures > 0 {
+            state.retry_at = Some(Instant::now() + backoff(state.failures + 1));
+        }
+        state.connection.clone()
+    }
+
+    pub fn failed(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.failures = state.failures.saturating_add(1);
+        state.retry_at = Some(Instant::now() + backoff(state.failures));
+    }
+
+    pub fn succeeded(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.failures = 0;
+        state.retry_at = None;
+    }
+}
+
+/// Connect until the replica answers or is dropped.
+async fn connect(connection_info: ConnectionInfo, state: Weak<Mutex<ReplicaState>>) {
+    let mut failures = 0;
+    loop {
+        match RedisConnection::standalone(connection_info.clone()).await {
+            Ok(connection) => {
+                info!("Connected to redis replica {}", connection_info.addr);
+                if let Some(state) = state.upgrade() {
+                    state.lock().unwrap().connection = Some(connection);
+                }
+                return;
+            }
+            Err(error) => {
+                warn!(
+                    "Redis replica {} is unavailable: {}",
+                    connection_info.addr, error
+                );
+                failures += 1;
+                tokio::time::sleep(backoff(failures)).await;
+                if state.strong_count() == 0 {
+                    return;
+                }
+            }
+        }
+    }
+}
+
+fn backoff(failures: u32) -> Duration {
+    MIN_BACKOFF
+        .saturating_mul(2u32.saturating_pow(failures.saturating_sub(1)))
+        .min(MAX_BACKOFF)
+}
@@ -0,0 +1,463 @@
+use std::collections::{BTreeMap, HashMap};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use redis::IntoConnectionInfo;
+use tracing::info;
+
+use super::{
+    redis::{RedisBackend, LIST_CHUNK},
+    Backend, BackendError, Clicks, Link, Metadata, Revision,
+};
+
+/// Number of points every shard owns on the hash ring.
+static VIRTUAL_NODES: usize = 160;
+
+/// FNV-1a. Placement of links must not change between builds, so the
+/// std hasher is not an option.
+fn hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// Spreads links over several Redis instances with consistent hashing.
+///
+/// Ids are allocated from the `LID` counter of the first shard so they stay
+/// globally unique, so every write depends on the first shard being up.
+///
+/// The shard list is fixed once links are stored. Keys are never moved
+/// between shards, so a changed list would look up links, aliases and codes
+/// on shards which do not have them; [`ShardedRedisBackend::check_shards`]
+/// refuses a list of another length, order or with replaced shards.
+pub struct ShardedRedisBackend {
+    shards: Vec<RedisBackend>,
+    ring: BTreeMap<u64, usize>,
+}
+
+impl ShardedRedisBackend {
+    pub async fn new<T: IntoConnectionInfo>(connections: Vec<T>) -> Result<Self, BackendError> {
+        info!("Initialize sharded Redis backend");
+        if connections.is_empty() {
+            return Err(BackendError::Internal("No redis shards configured".into()));
+        }
+        let mut shards = Vec::with_capacity(connections.len());
+        for connection in connections {
+            shards.push(RedisBackend::new(connection).await?);
+        }
+        let ring = (0..shards.len())
+            .flat_map(|shard| {
+                (0..VIRTUAL_NODES)
+                    .map(move |node| (hash(format!("shard-{}-{}", shard, node).as_bytes()), shard))
+            })
+            .collect();
+        Ok(Self { shards, ring })
+    }
+
+    /// Place keys of all shards under `prefix`.
+    pub fn with_prefix(self, prefix: &str) -> Self {
+        Self {
+            shards: self
+                .shards
+                .into_iter()
+                .map(|shard| shard.with_prefix(prefix))
+                .collect(),
+            ..self
+        }
+    }
+
+    /// Fail when the shards differ from the ones links were placed over.
+    /// Every instance keeps a random identity and the first start records
+    /// the ordered list of them on the first shard, so reordered, replaced
+    /// or repeated shards are refused even when their number is unchanged.
+    /// Deployments recorded only their number before, their order is
+    /// recorded by the first start checking it.
+    pub async fn check_shards(&self) -> Result<(), BackendError> {
+        let recorded = self.shards[0].shard_count(self.shards.len()).await?;
+        if recorded != self.shards.len() {
+            return Err(BackendError::Internal(
+                format!(
+                    "Links are placed over {} shards, {} are configured",
+                    recorded,
+                    self.shards.len()
+                )
+                .into(),
+            ));
+        }
+        let mut identities = Vec::with_capacity(self.shards.len());
+        for (index, shard) in self.shards.iter().enumerate() {
+            let identity = shard.shard_identity().await?;
+            if identities.contains(&identity) {
+                return Err(BackendError::Internal(
+                    format!("Shard {} is configured more than once", index).into(),
+                ));
+            }
+            identities.push(identity);
+        }
+        let configured = identities.join(",");
+        let recorded = self.shards[0].shard_list(configured.clone()).await?;
+        if recorded != configured {
+            let moved = recorded
+                .split(',')
+                .zip(&identities)
+                .position(|(recorded, identity)| recorded != identity)
+                .unwrap_or_default();
+            return Err(BackendError::Internal(
+                format!(
+                    "Shard {} is not the one links were placed with, shards must not be \
+                    reordered or replaced",
+                    moved
+                )
+                .into(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Index ids of links stored before the ids sorted set was introduced,
+    /// see [`RedisBackend::index_ids`]. The `LID` counter lives on the first
+    /// shard, so ids are probed on the owning shard here.
+    pub async fn index_ids(&self) -> Result<(), BackendError> {
+        let mut pending = Vec::new();
+        for (index, shard) in self.shards.iter().enumerate() {
+            if !shard.ids_indexed().await? {
+                pending.push(index);
+            }
+        }
+        if pending.is_empty() {
+            return Ok(());
+        }
+        let last_id = self.shards[0].last_id().await?;
+        info!("Index ids of links up to {}", last_id);
+        let mut start = 1;
+        while start <= last_id {
+            let end = start.saturating_add(LIST_CHUNK - 1).min(last_id);
+            let ids = (start..=end).collect::<Vec<_>>();
+            for (shard, positions) in self.placement(ids.iter().copied()) {
+                if !pending.contains(&shard) {
+                    continue;
+                }
+                let ids = positions
+                    .iter()
+                    .map(|position| ids[*position])
+                    .collect::<Vec<_>>();
+                self.shards[shard].index_existing(&ids).await?;
+            }
+            start = end.saturating_add(1);
+        }
+        for shard in pending {
+            self.shards[shard].mark_ids_indexed().await?;
+        }
+        Ok(())
+    }
+
+    /// Move un-prefixed keys of every shard under the configured namespace,
+    /// see [`RedisBackend::migrate_keyspace`].
+    pub async fn migrate_keyspace(&self, dry_run: bool) -> Result<u64, BackendError> {
+        let mut moved = 0;
+        for shard in &self.shards {
+            moved += shard.move_legacy_keys(dry_run).await?;
+        }
+        if !dry_run && moved > 0 {
+            self.index_ids().await?;
+        }
+        Ok(moved)
+    }
+
+    fn shard_index(&self, id: u64) -> usize {
+        self.ring_index(hash(&id.to_be_bytes()))
+    }
+
+    fn ring_index(&self, point: u64) -> usize {
+        self.ring
+            .range(point..)
+            .next()
+            .or_else(|| self.ring.iter().next())
+            .map(|(_, shard)| *shard)
+            .unwrap_or_default()
+    }
+
+    fn shard(&self, id: u64) -> &RedisBackend {
+        &self.shards[self.shard_index(id)]
+    }
+
+    /// Shard keeping the key of `alias`, which is looked up without an id.
+    fn alias_shard(&self, alias: &str) -> &RedisBackend {
+        &self.shards[self.ring_index(hash(alias.as_bytes()))]
+    }
+
+    /// Shard keeping the key of `code`, which is looked up without an id.
+    fn code_shard(&self, code: &str) -> &RedisBackend {
+        &self.shards[self.ring_index(hash(code.as_bytes()))]
+    }
+
+    /// Positions in `ids` grouped by owning shard.
+    fn placement(&self, ids: impl Iterator<Item = u64>) -> HashMap<usize, Vec<usize>> {
+        let mut placement: HashMap<usize, Vec<usize>> = HashMap::new();
+        for (position, id) in ids.enumerate() {
+            placement
+                .entry(self.shard_index(id))
+                .or_default()
+                .push(position);
+        }
+        placement
+    }
+}
+
+#[async_trait]
+impl Backend for ShardedRedisBackend {
+    async fn store<'a>(&self, url: &'a str) -> Result<u64, BackendError> {
+        let id = self.shards[0].allocate_ids(1).await?[0];
+        self.shard(id).insert(id, url).await?;
+        Ok(id)
+    }
+
+    async fn allocate_ids(&self, count: u64) -> Result<Vec<u64>, BackendError> {
+        self.shards[0].allocate_ids(count).await
+    }
+
+    async fn reserve_until(&self, id: u64) -> Result<(), BackendError> {
+        self.shards[0].reserve_id(id).await
+    }
+
+    async fn store_with_id<'a>(&self, id: u64, url: &'a str) -> Result<(), BackendError> {
+        self.shard(id).insert(id, url).await?;
+        self.shards[0].reserve_id(id).await
+    }
+
+    async fn retrive_versioned(&self, id: u64) -> Result<(String, u64), BackendError> {
+        self.shard(id).retrive_versioned(id).await
+    }
+
+    async fn click(&self, id: u64) -> Result<(), BackendError> {
+        self.shard(id).click(id).await
+    }
+
+    async fn stat(&self, id: u64, since: Option<DateTime<Utc>>) -> Result<u64, BackendError> {
+        self.shard(id).stat(id, since).await
+    }
+
+    async fn update_versioned<'a>(
+        &self,
+        id: u64,
+        url: &'a str,
+        expected: Option<u64>,
+    ) -> Result<u64, BackendError> {
+        self.shard(id).update_versioned(id, url, expected).await
+    }
+
+    async fn delete(&self, id: u64) -> Result<(), BackendError> {
+        self.shard(id).delete(id).await
+    }
+
+    async fn list(
+        &self,
+        after: Option<u64>,
+        limit: usize,
+    ) -> Result<Vec<(u64, String)>, BackendError> {
+        let mut links = Vec::new();
+        for shard in &self.shards {
+            links.extend(shard.list(after, limit).await?);
+        }
+        links.sort_unstable_by_key(|(id, _)| *id);
+        links.truncate(limit);
+        Ok(links)
+    }
+
+    async fn list_with_tombstones(
+        &self,
+        after: Option<u64>,
+        limit: usize,
+    ) -> Result<Vec<(u64, String)>, BackendError> {
+        let mut links = Vec::new();
+        for shard in &self.shards {
+            links.extend(shard.list_with_tombstones(after, limit).await?);
+        }
+        links.sort_unstable_by_key(|(id, _)| *id);
+        links.truncate(limit);
+        Ok(links)
+    }
+
+    /// Every shard indexes destinations of links it holds.
+    async fn find<'a>(&self, url: &'a str) -> Result<Vec<u64>, BackendError> {
+        let mut ids = Vec::new();
+        for shard in &self.shards {
+            ids.extend(shard.find(url).await?);
+        }
+        ids.sort_unstable();
+        Ok(ids)
+    }
+
+    async fn clicks(&self, id: u64) -> Result<Clicks, BackendError> {
+        self.shard(id).clicks(id).await
+    }
+
+    async fn restore<'a>(
+        &self,
+        id: u64,
+        link: &'a Link,
+        history: &'a [Revision],
+        clicks: &'a [(DateTime<Utc>, u64)],
+    ) -> Result<(), BackendError> {
+        self.shard(id).restore(id, link, history, clicks).await?;
+        self.shards[0].reserve_id(id).await
+    }
+
+    async fn undelete(&self, id: u64) -> Result<(), BackendError> {
+        self.shard(id).undelete(id).await
+    }
+
+    async fn purge(&self, before: DateTime<Utc>) -> Result<u64, BackendError> {
+        let mut purged = 0;
+        for shard in &self.shards {
+            let (count, aliases) = shard.purge_links(before).await?;
+            purged += count;
+            for alias in aliases {
+                self.alias_shard(&alias).drop_aliases(&[alias]).await?;
+            }
+        }
+        Ok(purged)
+    }
+
+    async fn history(&self, id: u64) -> Result<Vec<Revision>, BackendError> {
+        self.shard(id).history(id).await
+    }
+
+    async fn info(&self, id: u64) -> Result<Link, BackendError> {
+        self.shard(id).info(id).await
+    }
+
+    async fn store_alias<'a>(&self, alias: &'a str, id: u64) -> Result<(), BackendError> {
+        if !self.shard(id).exists(id).await? {
+            return Err(BackendError::NotFound);
+        }
+        self.alias_shard(alias).claim_alias(alias, id).await?;
+        self.shard(id).link_alias(id, alias).await
+    }
+
+    /// The alias is claimed first and released again when the link can not
+    /// be stored.
+    async fn store_with_alias<'a>(
+        &self,
+        id: u64,
+        url: &'a str,
+        alias: &'a str,
+    ) -> Result<(), BackendError> {
+        self.alias_shard(alias).claim_alias(alias, id).await?;
+        if let Err(error) = self.shard(id).store_with_id(id, url).await {
+            self.alias_shard(alias)
+                .drop_aliases(&[alias.to_owned()])
+                .await?;
+            return Err(error);
+        }
+        self.shard(id).link_alias(id, alias).await
+    }
+
+    async fn resolve_alias<'a>(&self, alias: &'a str) -> Result<u64, BackendError> {
+        self.alias_shard(alias).resolve_alias(alias).await
+    }
+
+    async fn aliases(&self, id: u64) -> Result<Vec<String>, BackendError> {
+        self.shard(id).aliases(id).await
+    }
+
+    async fn store_code<'a>(&self, id: u64, code: &'a str) -> Result<String, BackendError> {
+        self.code_shard(code).claim_code(code, id).await?;
+        let linked = self.shard(id).link_code(id, code).await?;
+        if linked != code {
+            self.code_shard(code).release_code(code).await?;
+        }
+        Ok(linked)
+    }
+
+    async fn resolve_code<'a>(&self, code: &'a str) -> Result<u64, BackendError> {
+        self.code_shard(code).resolve_code(code).await
+    }
+
+    async fn code(&self, id: u64) -> Result<String, BackendError> {
+        self.shard(id).code(id).await
+    }
+
+    async fn set_metadata<'a>(&self, id: u64, metadata: &'a Metadata) -> Result<(), BackendError> {
+        self.shard(id).set_metadata(id, metadata).await
+    }
+
+    async fn store_many<'a>(
+        &self,
+        urls: &'a [String],
+    ) -> Result<Vec<Result<u64, BackendError>>, BackendError> {
+        if urls.is_empty() {
+            return Ok(Vec::new());
+        }
+        let ids = self.shards[0].allocate_ids(urls.len() as u64).await?;
+        let mut results = urls.iter().map(|_| None).collect::<Vec<_>>();
+        for (shard, positions) in self.placement(ids.iter().copied()) {
+            let links = positions
+                .iter()
+                .map(|position| (ids[*position], urls[*position].as_str()))
+                .collect::<Vec<_>>();
+            let stored = self.shards[shard].insert_many(&links).await?;
+            for (position, result) in positions.into_iter().zip(stored) {
+                results[position] = Some(result.map(|_| ids[position]));
+            }
+        }
+        Ok(results.into_iter().flatten().collect())
+    }
+
+    async fn store_many_with_ids<'a>(
+        &self,
+        links: &'a [(u64, &'a str)],
+    ) -> Result<Vec<Result<(), BackendError>>, BackendError> {
+        let mut results = links.iter().map(|_| None).collect::<Vec<_>>();
+        for (shard, positions) in self.placement(links.iter().map(|(id, _)| *id)) {
+            let shard_links = positions
+                .iter()
+                .map(|position| links[*position])
+                .collect::<Vec<_>>();
+            let stored = self.shards[shard].insert_many(&shard_links).await?;
+            for (position, result) in positions.into_iter().zip(stored) {
+                results[position] = Some(result);
+            }
+        }
+        if let Some(last) = links.iter().map(|(id, _)| *id).max() {
+            self.shards[0].reserve_id(last).await?;
+        }
+        Ok(results.into_iter().flatten().collect())
+    }
+
+    async fn retrive_many<'a>(
+        &self,
+        ids: &'a [u64],
+    ) -> Result<Vec<Result<String, BackendError>>, BackendError> {
+        let mut results = ids.iter().map(|_| None).collect::<Vec<_>>();
+        for (shard, positions) in self.placement(ids.iter().copied()) {
+            let shard_ids = positions
+                .iter()
+                .map(|position| ids[*position])
+                .collect::<Vec<_>>();
+            let urls = self.shards[shard].retrive_many(&shard_ids).await?;
+            for (position, result) in positions.into_iter().zip(urls) {
+                results[position] = Some(result);
+            }
+        }
+        Ok(results.into_iter().flatten().collect())
+    }
+
+    async fn delete_many<'a>(
+        &self,
+        ids: &'a [u64],
+    ) -> Result<Vec<Result<(), BackendError>>, BackendError> {
+        let mut results = ids.iter().map(|_| None).collect::<Vec<_>>();
+        for (shard, positions) in self.placement(ids.iter().copied()) {
+            let shard_ids = positions
+                .iter()
+                .map(|position| ids[*position])
+                .collect::<Vec<_>>();
+            let deleted = self.shards[shard].delete_many(&shard_ids).await?;
+            for (position, result) in positions.into_iter().zip(deleted) {
+                results[position] = Some(result);
+            }
+        }
+        Ok(results.into_iter().flatten().collect())
+    }
+}
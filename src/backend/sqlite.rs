@@ -0,0 +1,722 @@
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use rusqlite::{params, Connection, OptionalExtension, Transaction};
+use tokio::task::{spawn_blocking, JoinError};
+use tracing::info;
+
+use super::{link::now, Backend, BackendError, Clicks, Link, Metadata, Revision};
+
+static SCHEMA: &str = r"
+CREATE TABLE IF NOT EXISTS links (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    url TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS clicks (
+    link_id INTEGER NOT NULL,
+    ts INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS clicks_link_id_ts ON clicks (link_id, ts);
+";
+
+/// Schema changes applied on top of `SCHEMA`. The number of applied ones is
+/// kept in `user_version`, so new migrations must only be appended.
+static MIGRATIONS: &[&str] = &[
+    "ALTER TABLE links ADD COLUMN version INTEGER NOT NULL DEFAULT 1;",
+    r"
+ALTER TABLE links ADD COLUMN created_at INTEGER;
+ALTER TABLE links ADD COLUMN updated_at INTEGER;
+ALTER TABLE links ADD COLUMN metadata TEXT;
+",
+    "ALTER TABLE links ADD COLUMN deleted_at INTEGER;",
+    r"
+CREATE TABLE history (
+    link_id INTEGER NOT NULL,
+    version INTEGER NOT NULL,
+    url TEXT NOT NULL,
+    created_at INTEGER,
+    PRIMARY KEY (link_id, version)
+);
+INSERT INTO history (link_id, version, url) SELECT id, version, url FROM links;
+",
+    "CREATE INDEX links_url ON links (url);",
+    r"
+CREATE TABLE aliases (
+    alias TEXT PRIMARY KEY,
+    link_id INTEGER NOT NULL
+);
+CREATE INDEX aliases_link_id ON aliases (link_id);
+",
+    r"
+CREATE TABLE codes (
+    code TEXT PRIMARY KEY,
+    link_id INTEGER NOT NULL UNIQUE
+);
+",
+];
+
+static DEFAULT_STAT_PERIOD_IN_HOURS: i64 = 24;
+
+/// Timestamps are kept as microseconds since epoch.
+fn to_micros(ts: Option<DateTime<Utc>>) -> Option<i64> {
+    ts.map(|ts| ts.timestamp_micros())
+}
+
+fn from_micros(micros: Option<i64>) -> Option<DateTime<Utc>> {
+    micros.and_then(DateTime::from_timestamp_micros)
+}
+
+/// Fails unless the link exists and is not a tombstone.
+fn check_live(connection: &Connection, id: u64) -> Result<(), BackendError> {
+    let deleted_at: Option<i64> = connection
+        .query_row(
+            "SELECT deleted_at FROM links WHERE id = ?1",
+            params![id],
+            |row| row.get(0),
+        )
+        .optional()?
+        .ok_or(BackendError::NotFound)?;
+    match deleted_at {
+        Some(_) => Err(BackendError::Deleted),
+        None => Ok(()),
+    }
+}
+
+/// Insert a new link with its first revision. Without `id` the next one of
+/// `sqlite_sequence` is taken, explicit ids move it on their own.
+fn insert_link(
+    transaction: &Transaction,
+    id: Option<u64>,
+    link: &Link,
+) -> Result<u64, BackendError> {
+    let inserted = transaction.execute(
+        "INSERT OR IGNORE INTO links (id, url, created_at, updated_at) VALUES (?1, ?2, ?3, ?4)",
+        params![
+            id,
+            link.url,
+            to_micros(link.created_at),
+            to_micros(link.updated_at)
+        ],
+    )?;
+    if inserted == 0 {
+        return Err(BackendError::AlreadyExists);
+    }
+    let id = transaction.last_insert_rowid() as u64;
+    transaction.execute(
+        "INSERT INTO history (link_id, version, url, created_at) VALUES (?1, 1, ?2, ?3)",
+        params![id, link.url, to_micros(link.created_at)],
+    )?;
+    Ok(id)
+}
+
+fn migrate(connection: &mut Connection) -> Result<(), BackendError> {
+    let transaction = connection.transaction()?;
+    let applied: usize = transaction.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+    for (version, migration) in MIGRATIONS.iter().enumerate().skip(applied) {
+        info!("Apply sqlite migration {}", version + 1);
+        transaction.execute_batch(migration)?;
+    }
+    transaction.pragma_update(None, "user_version", MIGRATIONS.len())?;
+    transaction.commit()?;
+    Ok(())
+}
+
+impl From<rusqlite::Error> for BackendError {
+    fn from(error: rusqlite::Error) -> Self {
+        BackendError::Internal(Box::new(error))
+    }
+}
+
+impl From<JoinError> for BackendError {
+    fn from(error: JoinError) -> Self {
+        BackendError::Internal(Box::new(error))
+    }
+}
+
+pub struct SqliteBackend {
+    connection: Arc<Mutex<Connection>>,
+}
+
+impl SqliteBackend {
+    pub async fn new(path: &str) -> Result<Self, BackendError> {
+        info!("Initialize Sqlite backend");
+        let path = path.to_owned();
+        let connection = spawn_blocking(move || -> Result<Connection, BackendError> {
+            let mut connection = Connection::open(path)?;
+            connection.execute_batch(SCHEMA)?;
+            migrate(&mut connection)?;
+            Ok(connection)
+        })
+        .await??;
+        Ok(Self {
+            connection: Arc::new(Mutex::new(connection)),
+        })
+    }
+
+    async fn execute<T, F>(&self, operation: F) -> Result<T, BackendError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Connection) -> Result<T, BackendError> + Send + 'static,
+    {
+        let connection = self.connection.clone();
+        spawn_blocking(move || {
+            let mut connection = connection
+                .lock()
+                .map_err(|error| BackendError::Internal(error.to_string().into()))?;
+            operation(&mut connection)
+        })
+        .await?
+    }
+
+    /// Links following `after` ordered by id, tombstones only with
+    /// `tombstones` set.
+    async fn links(
+        &self,
+        after: Option<u64>,
+        limit: usize,
+        tombstones: bool,
+    ) -> Result<Vec<(u64, String)>, BackendError> {
+        self.execute(move |connection| {
+            let mut statement = connection.prepare(
+                "SELECT id, url FROM links WHERE id > ?1 AND (?3 OR deleted_at IS NULL)
+                ORDER BY id LIMIT ?2",
+            )?;
+            let links = statement
+                .query_map(
+                    params![after.unwrap_or(0), limit as i64, tombstones],
+                    |row| Ok((row.get(0)?, row.get(1)?)),
+                )?
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(links)
+        })
+        .await
+    }
+}
+
+#[async_trait]
+impl Backend for SqliteBackend {
+    async fn store<'a>(&self, url: &'a str) -> Result<u64, BackendError> {
+        let link = Link::new(url);
+        self.execute(move |connection| {
+            let transaction = connection.transaction()?;
+            let id = insert_link(&transaction, None, &link)?;
+            transaction.commit()?;
+            Ok(id)
+        })
+        .await
+    }
+
+    /// Ids are taken by moving `sqlite_sequence`, which backs `AUTOINCREMENT`.
+    async fn allocate_ids(&self, count: u64) -> Result<Vec<u64>, BackendError> {
+        self.execute(move |connection| {
+            let transaction = connection.transaction()?;
+            transaction.execute(
+                "INSERT INTO sqlite_sequence (name, seq)
+                SELECT 'links', 0 WHERE NOT EXISTS
+                (SELECT 1 FROM sqlite_sequence WHERE name = 'links')",
+                [],
+            )?;
+            let last: u64 = transaction.query_row(
+                "UPDATE sqlite_sequence SET seq = seq + ?1 WHERE name = 'links' RETURNING seq",
+                params![count],
+                |row| row.get(0),
+            )?;
+            transaction.commit()?;
+            Ok((last + 1 - count..=last).collect())
+        })
+        .await
+    }
+
+    async fn reserve_until(&self, id: u64) -> Result<(), BackendError> {
+        self.execute(move |connection| {
+            let transaction = connection.transaction()?;
+            transaction.execute(
+                "INSERT INTO sqlite_sequence (name, seq)
+                SELECT 'links', 0 WHERE NOT EXISTS
+                (SELECT 1 FROM sqlite_sequence WHERE name = 'links')",
+                [],
+            )?;
+            transaction.execute(
+                "UPDATE sqlite_sequence SET seq = MAX(seq, ?1) WHERE name = 'links'",
+                params![id],
+            )?;
+            transaction.commit()?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn store_with_id<'a>(&self, id: u64, url: &'a str) -> Result<(), BackendError> {
+        let link = Link::new(url);
+        self.execute(move |connection| {
+            let transaction = connection.transaction()?;
+            insert_link(&transaction, Some(id), &link)?;
+            transaction.commit()?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn retrive_versioned(&self, id: u64) -> Result<(String, u64), BackendError> {
+        let ts = Utc::now().timestamp();
+        self.execute(move |connection| {
+            let transaction = connection.transaction()?;
+            check_live(&transaction, id)?;
+            let link = transaction.query_row(
+                "SELECT url, version FROM links WHERE id = ?1",
+                params![id],
+                |row| Ok((row.get::<_, String>(0)?, row.get::<_, u64>(1)?)),
+            )?;
+            transaction.execute(
+                "INSERT INTO clicks (link_id, ts) VALUES (?1, ?2)",
+                params![id, ts],
+            )?;
+            transaction.commit()?;
+            Ok(link)
+        })
+        .await
+    }
+
+    async fn click(&self, id: u64) -> Result<(), BackendError> {
+        let ts = Utc::now().timestamp();
+        self.execute(move |connection| {
+            let transaction = connection.transaction()?;
+            check_live(&transaction, id)?;
+            transaction.execute(
+                "INSERT INTO clicks (link_id, ts) VALUES (?1, ?2)",
+                params![id, ts],
+            )?;
+            transaction.commit()?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn stat(&self, id: u64, since: Option<DateTime<Utc>>) -> Result<u64, BackendError> {
+        let now = Utc::now();
+        let since = since
+            .or_else(|| now.checked_sub_signed(Duration::hours(DEFAULT_STAT_PERIOD_IN_HOURS)))
+            .ok_or(BackendError::DateTimeOverflow)?;
+        self.execute(move |connection| {
+            let exists = connection
+                .query_row("SELECT 1 FROM links WHERE id = ?1", params![id], |_| Ok(()))
+                .optional()?;
+            exists.ok_or(BackendError::NotFound)?;
+            let count: u64 = connection.query_row(
+                "SELECT COUNT(*) FROM clicks WHERE link_id = ?1 AND ts BETWEEN ?2 AND ?3",
+                params![id, since.timestamp(), now.timestamp()],
+                |row| row.get(0),
+            )?;
+            Ok(count)
+        })
+        .await
+    }
+
+    async fn update_versioned<'a>(
+        &self,
+        id: u64,
+        url: &'a str,
+        expected: Option<u64>,
+    ) -> Result<u64, BackendError> {
+        let url = url.to_owned();
+        let updated_at = to_micros(Some(now()));
+        self.execute(move |connection| {
+            let transaction = connection.transaction()?;
+            check_live(&transaction, id)?;
+            let version: u64 = transaction.query_row(
+                "SELECT version FROM links WHERE id = ?1",
+                params![id],
+                |row| row.get(0),
+            )?;
+            if expected.is_some_and(|expected| expected != version) {
+                return Err(BackendError::VersionMismatch);
+            }
+            transaction.execute(
+                "UPDATE links SET url = ?1, version = ?2, updated_at = ?3 WHERE id = ?4",
+                params![url, version + 1, updated_at, id],
+            )?;
+            transaction.execute(
+                "INSERT OR REPLACE INTO history (link_id, version, url, created_at)
+                VALUES (?1, ?2, ?3, ?4)",
+                params![id, version + 1, url, updated_at],
+            )?;
+            transaction.commit()?;
+            Ok(version + 1)
+        })
+        .await
+    }
+
+    async fn delete(&self, id: u64) -> Result<(), BackendError> {
+        let deleted_at = to_micros(Some(now()));
+        self.execute(move |connection| {
+            let deleted = connection.execute(
+                "UPDATE links SET deleted_at = ?1 WHERE id = ?2 AND deleted_at IS NULL",
+                params![deleted_at, id],
+            )?;
+            if deleted == 0 {
+                check_live(connection, id)?;
+            }
+            Ok(())
+        })
+        .await
+    }
+
+    async fn undelete(&self, id: u64) -> Result<(), BackendError> {
+        self.execute(move |connection| {
+            let updated = connection.execute(
+                "UPDATE links SET deleted_at = NULL WHERE id = ?1",
+                params![id],
+            )?;
+            if updated == 0 {
+                Err(BackendError::NotFound)
+            } else {
+                Ok(())
+            }
+        })
+        .await
+    }
+
+    async fn purge(&self, before: DateTime<Utc>) -> Result<u64, BackendError> {
+        let before = before.timestamp_micros();
+        self.execute(move |connection| {
+            let transaction = connection.transaction()?;
+            transaction.execute(
+                "DELETE FROM clicks WHERE link_id IN (
+                    SELECT id FROM links WHERE deleted_at <= ?1
+                )",
+                params![before],
+            )?;
+            transaction.execute(
+                "DELETE FROM history WHERE link_id IN (
+                    SELECT id FROM links WHERE deleted_at <= ?1
+                )",
+                params![before],
+            )?;
+            transaction.execute(
+                "DELETE FROM aliases WHERE link_id IN (
+                    SELECT id FROM links WHERE deleted_at <= ?1
+                )",
+                params![before],
+            )?;
+            // Codes stay taken, see `Backend::purge`
+            let purged =
+                transaction.execute("DELETE FROM links WHERE deleted_at <= ?1", params![before])?;
+            transaction.commit()?;
+            Ok(purged as u64)
+        })
+        .await
+    }
+
+    async fn list(
+        &self,
+        after: Option<u64>,
+        limit: usize,
+    ) -> Result<Vec<(u64, String)>, BackendError> {
+        self.links(after, limit, false).await
+    }
+
+    async fn list_with_tombstones(
+        &self,
+        after: Option<u64>,
+        limit: usize,
+    ) -> Result<Vec<(u64, String)>, BackendError> {
+        self.links(after, limit, true).await
+    }
+
+    async fn find<'a>(&self, url: &'a str) -> Result<Vec<u64>, BackendError> {
+        let url = url.to_owned();
+        self.execute(move |connection| {
+            let mut statement = connection.prepare(
+                "SELECT id FROM links WHERE url = ?1 AND deleted_at IS NULL ORDER BY id",
+            )?;
+            let ids = statement
+                .query_map(params![url], |row| row.get(0))?
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(ids)
+        })
+        .await
+    }
+
+    async fn clicks(&self, id: u64) -> Result<Clicks, BackendError> {
+        self.execute(move |connection| {
+            connection
+                .query_row("SELECT 1 FROM links WHERE id = ?1", params![id], |_| Ok(()))
+                .optional()?
+                .ok_or(BackendError::NotFound)?;
+            let mut statement = connection.prepare(
+                "SELECT ts, COUNT(*) FROM clicks WHERE link_id = ?1 GROUP BY ts ORDER BY ts",
+            )?;
+            let clicks = statement
+                .query_map(params![id], |row| {
+                    Ok((row.get::<_, i64>(0)?, row.get::<_, u64>(1)?))
+                })?
+                .collect::<Result<Vec<_>, _>>()?
+                .into_iter()
+                .filter_map(|(ts, counter)| DateTime::from_timestamp(ts, 0).map(|ts| (ts, counter)))
+                .collect();
+            Ok(clicks)
+        })
+        .await
+    }
+
+    async fn restore<'a>(
+        &self,
+        id: u64,
+        link: &'a Link,
+        history: &'a [Revision],
+        clicks: &'a [(DateTime<Utc>, u64)],
+    ) -> Result<(), BackendError> {
+        let link = link.clone();
+        let metadata = serde_json::to_string(&link.metadata)?;
+        let history = Revision::restored(&link, history);
+        let clicks = clicks.to_vec();
+        self.execute(move |connection| {
+            let transaction = connection.transaction()?;
+            transaction.execute(
+                "INSERT OR REPLACE INTO links
+                (id, url, version, created_at, updated_at, deleted_at, metadata)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                params![
+                    id,
+                    link.url,
+                    link.version,
+                    to_micros(link.created_at),
+                    to_micros(link.updated_at),
+                    to_micros(link.deleted_at),
+                    metadata
+                ],
+            )?;
+            transaction.execute("DELETE FROM history WHERE link_id = ?1", params![id])?;
+            {
+                let mut statement = transaction.prepare(
+                    "INSERT INTO history (link_id, version, url, created_at)
+                    VALUES (?1, ?2, ?3, ?4)",
+                )?;
+                for revision in history {
+                    statement.execute(params![
+                        id,
+                        revision.version,
+                        revision.url,
+                        to_micros(revision.created_at)
+                    ])?;
+                }
+            }
+            transaction.execute("DELETE FROM clicks WHERE link_id = ?1", params![id])?;
+            {
+                let mut statement =
+                    transaction.prepare("INSERT INTO clicks (link_id, ts) VALUES (?1, ?2)")?;
+                for (ts, counter) in clicks {
+                    for _ in 0..counter {
+                        statement.execute(params![id, ts.timestamp()])?;
+                    }
+                }
+            }
+            transaction.commit()?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn info(&self, id: u64) -> Result<Link, BackendError> {
+        self.execute(move |connection| {
+            let (url, version, created_at, updated_at, deleted_at, metadata) = connection
+                .query_row(
+                    "SELECT url, version, created_at, updated_at, deleted_at, metadata
+                    FROM links WHERE id = ?1",
+                    params![id],
+                    |row| {
+                        Ok((
+                            row.get::<_, String>(0)?,
+                            row.get::<_, u64>(1)?,
+                            row.get::<_, Option<i64>>(2)?,
+                            row.get::<_, Option<i64>>(3)?,
+                            row.get::<_, Option<i64>>(4)?,
+                            row.get::<_, Option<String>>(5)?,
+                        ))
+                    },
+                )
+                .optional()?
+                .ok_or(BackendError::NotFound)?;
+            Ok(Link {
+                url,
+                version,
+                created_at: from_micros(created_at),
+                updated_at: from_micros(updated_at),
+                deleted_at: from_micros(deleted_at),
+                metadata: metadata
+                    .map(|metadata| serde_json::from_str(&metadata))
+                    .transpose()?
+                    .unwrap_or_default(),
+            })
+        })
+        .await
+    }
+
+    async fn history(&self, id: u64) -> Result<Vec<Revision>, BackendError> {
+        self.execute(move |connection| {
+            connection
+                .query_row("SELECT 1 FROM links WHERE id = ?1", params![id], |_| Ok(()))
+                .optional()?
+                .ok_or(BackendError::NotFound)?;
+            let mut statement = connection.prepare(
+                "SELECT version, url, created_at FROM history WHERE link_id = ?1 ORDER BY version",
+            )?;
+            let history = statement
+                .query_map(params![id], |row| {
+                    Ok(Revision {
+                        version: row.get(0)?,
+                        url: row.get(1)?,
+                        created_at: from_micros(row.get(2)?),
+                    })
+                })?
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(history)
+        })
+        .await
+    }
+
+    async fn store_alias<'a>(&self, alias: &'a str, id: u64) -> Result<(), BackendError> {
+        let alias = alias.to_owned();
+        self.execute(move |connection| {
+            let transaction = connection.transaction()?;
+            transaction
+                .query_row("SELECT 1 FROM links WHERE id = ?1", params![id], |_| Ok(()))
+                .optional()?
+                .ok_or(BackendError::NotFound)?;
+            transaction.execute(
+                "INSERT OR IGNORE INTO aliases (alias, link_id) VALUES (?1, ?2)",
+                params![alias, id],
+            )?;
+            let owner: u64 = transaction.query_row(
+                "SELECT link_id FROM aliases WHERE alias = ?1",
+                params![alias],
+                |row| row.get(0),
+            )?;
+            if owner != id {
+                return Err(BackendError::AlreadyExists);
+            }
+            transaction.commit()?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn store_with_alias<'a>(
+        &self,
+        id: u64,
+        url: &'a str,
+        alias: &'a str,
+    ) -> Result<(), BackendError> {
+        let link = Link::new(url);
+        let alias = alias.to_owned();
+        self.execute(move |connection| {
+            let transaction = connection.transaction()?;
+            insert_link(&transaction, Some(id), &link)?;
+            let inserted = transaction.execute(
+                "INSERT OR IGNORE INTO aliases (alias, link_id) VALUES (?1, ?2)",
+                params![alias, id],
+            )?;
+            if inserted == 0 {
+                return Err(BackendError::AlreadyExists);
+            }
+            transaction.commit()?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn resolve_alias<'a>(&self, alias: &'a str) -> Result<u64, BackendError> {
+        let alias = alias.to_owned();
+        self.execute(move |connection| {
+            connection
+                .query_row(
+                    "SELECT link_id FROM aliases WHERE alias = ?1",
+                    params![alias],
+                    |row| row.get(0),
+                )
+                .optional()?
+                .ok_or(BackendError::NotFound)
+        })
+        .await
+    }
+
+    async fn aliases(&self, id: u64) -> Result<Vec<String>, BackendError> {
+        self.execute(move |connection| {
+            let mut statement = connection
+                .prepare("SELECT alias FROM aliases WHERE link_id = ?1 ORDER BY alias")?;
+            let aliases = statement
+                .query_map(params![id], |row| row.get(0))?
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(aliases)
+        })
+        .await
+    }
+
+    /// Conflicts on either column are ignored, the code stored for the link
+    /// afterwards tells whether `code` was taken by another one.
+    async fn store_code<'a>(&self, id: u64, code: &'a str) -> Result<String, BackendError> {
+        let code = code.to_owned();
+        self.execute(move |connection| {
+            connection.execute(
+                "INSERT OR IGNORE INTO codes (code, link_id) VALUES (?1, ?2)",
+                params![code, id],
+            )?;
+            connection
+                .query_row(
+                    "SELECT code FROM codes WHERE link_id = ?1",
+                    params![id],
+                    |row| row.get(0),
+                )
+                .optional()?
+                .ok_or(BackendError::AlreadyExists)
+        })
+        .await
+    }
+
+    async fn resolve_code<'a>(&self, code: &'a str) -> Result<u64, BackendError> {
+        let code = code.to_owned();
+        self.execute(move |connection| {
+            connection
+                .query_row(
+                    "SELECT link_id FROM codes WHERE code = ?1",
+                    params![code],
+                    |row| row.get(0),
+                )
+                .optional()?
+                .ok_or(BackendError::NotFound)
+        })
+        .await
+    }
+
+    async fn code(&self, id: u64) -> Result<String, BackendError> {
+        self.execute(move |connection| {
+            connection
+                .query_row(
+                    "SELECT code FROM codes WHERE link_id = ?1",
+                    params![id],
+                    |row| row.get(0),
+                )
+                .optional()?
+                .ok_or(BackendError::NotFound)
+        })
+        .await
+    }
+
+    async fn set_metadata<'a>(&self, id: u64, metadata: &'a Metadata) -> Result<(), BackendError> {
+        let metadata = serde_json::to_string(metadata)?;
+        let updated_at = to_micros(Some(now()));
+        self.execute(move |connection| {
+            let updated = connection.execute(
+                "UPDATE links SET metadata = ?1, updated_at = ?2
+                WHERE id = ?3 AND deleted_at IS NULL",
+                params![metadata, updated_at, id],
+            )?;
+            if updated == 0 {
+                check_live(connection, id)?;
+            }
+            Ok(())
+        })
+        .await
+    }
+}
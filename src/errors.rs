@@ -1,11 +1,23 @@
 use axum::{
     http::{uri::InvalidUri, StatusCode},
-    response::{IntoResponse, Response},
+    response::{Html, IntoResponse, Response},
 };
 use thiserror::Error;
 
 use crate::{backend::BackendError, shortener::ShortnerError};
 
+/// Shown instead of the destination of a deleted link.
+static DELETED_PAGE: &str = r#"<!DOCTYPE html>
+<html>
+<head><title>Link deleted</title></head>
+<body>
+<h1>This link has been deleted</h1>
+<p>The owner of this short link has deleted it, so it no longer leads anywhere.
+If you believe this is a mistake, please contact whoever shared the link with you.</p>
+</body>
+</html>
+"#;
+
 #[derive(Error, Debug)]
 pub enum ServiceError {
     #[error(transparent)]
@@ -16,15 +28,41 @@ pub enum ServiceError {
     State(&'static str),
     #[error("Invalid URI")]
     InvalidURI(#[from] InvalidUri),
+    #[error("Invalid batch: {0}")]
+    InvalidBatch(#[from] serde_json::Error),
+    #[error("Batch exceeds {0} entries")]
+    BatchTooLarge(usize),
+    #[error("Invalid alias: {0}")]
+    InvalidAlias(&'static str),
 }
 
 impl IntoResponse for ServiceError {
     fn into_response(self) -> Response {
         match self {
+            ServiceError::Sortner(ShortnerError::Backend(error)) => {
+                ServiceError::Backend(error).into_response()
+            }
             ServiceError::Backend(BackendError::NotFound) => StatusCode::NOT_FOUND.into_response(),
+            ServiceError::Backend(BackendError::AlreadyExists) => {
+                StatusCode::CONFLICT.into_response()
+            }
+            ServiceError::Backend(BackendError::VersionMismatch) => {
+                StatusCode::PRECONDITION_FAILED.into_response()
+            }
+            ServiceError::Backend(BackendError::Deleted) => {
+                (StatusCode::GONE, Html(DELETED_PAGE)).into_response()
+            }
             ServiceError::Sortner(ShortnerError::Decode(_))
+            | ServiceError::Sortner(ShortnerError::Malformed)
             | ServiceError::Backend(BackendError::DateTimeOverflow)
-            | ServiceError::InvalidURI(_) => StatusCode::BAD_REQUEST.into_response(),
+            | ServiceError::InvalidURI(_)
+            | ServiceError::InvalidBatch(_) => StatusCode::BAD_REQUEST.into_response(),
+            ServiceError::InvalidAlias(_) => {
+                (StatusCode::BAD_REQUEST, self.to_string()).into_response()
+            }
+            ServiceError::BatchTooLarge(_) => {
+                (StatusCode::PAYLOAD_TOO_LARGE, self.to_string()).into_response()
+            }
             _ => (StatusCode::INTERNAL_SERVER_ERROR, self.to_string()).into_response(),
         }
     }
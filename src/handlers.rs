@@ -1,63 +1,440 @@
 use std::sync::Arc;
 
 use axum::{
-    extract::{Path, State},
-    http::{StatusCode, Uri},
-    response::Redirect,
+    extract::{Path, Query, State},
+    http::{
+        header::{CONTENT_TYPE, ETAG, IF_MATCH},
+        HeaderMap, HeaderName, StatusCode, Uri,
+    },
+    response::{IntoResponse, Redirect},
+    Json,
 };
+use serde::{Deserialize, Serialize};
+use tracing::info;
 
-use crate::{errors::ServiceError, service, shortener::Shortner};
+use crate::{
+    backend::{BackendError, Link, Metadata, Revision},
+    errors::ServiceError,
+    service,
+    settings::Deduplication,
+    shortener::Shortner,
+};
+
+static DEFAULT_PAGE_SIZE: usize = 100;
+static MAX_PAGE_SIZE: usize = 1000;
+static MAX_BATCH_SIZE: usize = 10000;
+static MAX_ALIAS_LENGTH: usize = 64;
+/// Route segments under `/urls` which would shadow an alias
+static RESERVED_ALIASES: [&str; 1] = ["batch"];
+
+#[derive(Deserialize, Debug)]
+pub struct ListQuery {
+    /// `next` of the previous page
+    pub cursor: Option<String>,
+    pub limit: Option<usize>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct CreateQuery {
+    /// Stored as owner of a new link, scopes deduplication by owner
+    pub owner: Option<String>,
+    /// Code requested instead of a generated one
+    pub alias: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ShortenEntry {
+    pub shorten: String,
+    pub url: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ShortenPage {
+    pub urls: Vec<ShortenEntry>,
+    /// Cursor of the next page, absent on the last one
+    pub next: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ShortenInfo {
+    pub shorten: String,
+    #[serde(flatten)]
+    pub link: Link,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub aliases: Vec<String>,
+    /// Generation of the retired shortener which made `shorten`, absent
+    /// for current codes and aliases
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub generation: Option<usize>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ShortenHistory {
+    pub shorten: String,
+    /// Destinations ordered by version, the last one is current
+    pub history: Vec<Revision>,
+}
+
+/// Outcome of a single batch entry: either `shorten` or `error` is set.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct BatchEntry {
+    pub url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shorten: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+fn etag(version: u64) -> [(HeaderName, String); 1] {
+    [(ETAG, format!("\"{}\"", version))]
+}
+
+/// Version required by `If-Match`, none without the header or for `*`.
+/// Tags which are not versions never match.
+fn expected_version(headers: &HeaderMap) -> Result<Option<u64>, ServiceError> {
+    let Some(value) = headers.get(IF_MATCH) else {
+        return Ok(None);
+    };
+    let value = value
+        .to_str()
+        .map_err(|_| BackendError::VersionMismatch)?
+        .trim();
+    if value == "*" {
+        return Ok(None);
+    }
+    let version = value
+        .trim_start_matches("W/")
+        .trim_matches('"')
+        .parse()
+        .map_err(|_| BackendError::VersionMismatch)?;
+    Ok(Some(version))
+}
+
+/// Id of the link behind `shorten`, an alias or a generated code, with the
+/// generation of the code, `0` for aliases. Codes of retired shorteners are
+/// logged, so their traffic can be measured.
+async fn resolve_generation<S: Shortner>(
+    state: &service::State<S>,
+    shorten: &str,
+) -> Result<(u64, usize), ServiceError> {
+    match state.backend.resolve_alias(shorten).await {
+        Ok(id) => return Ok((id, 0)),
+        Err(BackendError::NotFound) => {}
+        Err(error) => return Err(error.into()),
+    }
+    let (id, generation) = state.shortner.decode_generation(shorten).await?;
+    if generation > 0 {
+        info!(
+            "Code {} of retired shortener generation {}",
+            shorten, generation
+        );
+    }
+    Ok((id, generation))
+}
 
+/// Id of the link behind `shorten`, an alias or a generated code.
+async fn resolve<S: Shortner>(
+    state: &service::State<S>,
+    shorten: &str,
+) -> Result<u64, ServiceError> {
+    Ok(resolve_generation(state, shorten).await?.0)
+}
+
+/// Aliases are path segments next to generated codes, so they are limited to
+/// characters needing no escaping and must not read as a code or a route.
+async fn validate_alias<S: Shortner>(
+    state: &service::State<S>,
+    alias: &str,
+) -> Result<(), ServiceError> {
+    if alias.is_empty() || alias.len() > MAX_ALIAS_LENGTH {
+        return Err(ServiceError::InvalidAlias("length must be 1 to 64"));
+    }
+    if !alias
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        return Err(ServiceError::InvalidAlias(
+            "only letters, digits, '-' and '_' are allowed",
+        ));
+    }
+    let reserved = RESERVED_ALIASES
+        .iter()
+        .copied()
+        .chain(state.config.aliases.reserved.iter().map(String::as_str));
+    if reserved
+        .into_iter()
+        .any(|word| word.eq_ignore_ascii_case(alias))
+    {
+        return Err(ServiceError::InvalidAlias("reserved word"));
+    }
+    if state.shortner.decode(alias).await.is_ok() {
+        return Err(BackendError::AlreadyExists.into());
+    }
+    match state.backend.resolve_alias(alias).await {
+        Ok(_) => Err(BackendError::AlreadyExists.into()),
+        Err(BackendError::NotFound) => Ok(()),
+        Err(error) => Err(error.into()),
+    }
+}
+
+/// Existing link to `url` reusable for a new one under the configured
+/// deduplication mode.
+async fn duplicate<S: Shortner>(
+    state: &service::State<S>,
+    url: &str,
+    owner: Option<&str>,
+) -> Result<Option<u64>, ServiceError> {
+    let ids = match state.config.deduplication {
+        Deduplication::Disabled => return Ok(None),
+        Deduplication::Global => return Ok(state.backend.find(url).await?.first().copied()),
+        Deduplication::Owner => state.backend.find(url).await?,
+    };
+    for id in ids {
+        match state.backend.info(id).await {
+            Ok(link) if link.metadata.owner.as_deref() == owner => return Ok(Some(id)),
+            Ok(_) | Err(BackendError::NotFound) => {}
+            Err(error) => return Err(error.into()),
+        }
+    }
+    Ok(None)
+}
+
+/// Shorten the url in the body. With deduplication enabled an existing link
+/// to it is returned with `200 OK` instead of `201 Created`.
+///
+/// A requested `alias` is returned instead of the generated code, such links
+/// are never deduplicated. Aliases taken by another link or colliding with
+/// generated codes are rejected with `409 Conflict`.
 pub async fn create_shorten<S: Shortner>(
     State(state): State<Arc<service::State<S>>>,
+    Query(query): Query<CreateQuery>,
     uri: String,
 ) -> std::result::Result<(StatusCode, String), ServiceError> {
-    let validated_uri = uri.trim().parse::<Uri>()?;
-    let id = state
-        .backend
-        .store(validated_uri.to_string().as_str())
-        .await?;
-    Ok((StatusCode::CREATED, state.shortner.encode(id).await?))
+    let url = uri.trim().parse::<Uri>()?.to_string();
+    let owner = query.owner.as_deref();
+    if let Some(alias) = &query.alias {
+        validate_alias(&state, alias).await?;
+    } else if let Some(id) = duplicate(&state, &url, owner).await? {
+        return Ok((StatusCode::OK, state.shortner.encode(id).await?));
+    }
+    // The alias is claimed together with the link, so a link is never left
+    // behind without the alias it was created for
+    let id = match &query.alias {
+        Some(alias) => {
+            let id = state.backend.allocate_ids(1).await?.pop().ok_or_else(|| {
+                BackendError::Internal("Backend allocated no id for the link".into())
+            })?;
+            state.backend.store_with_alias(id, &url, alias).await?;
+            id
+        }
+        None => state.backend.store(&url).await?,
+    };
+    if let Some(owner) = owner {
+        let metadata = Metadata {
+            owner: Some(owner.to_owned()),
+            ..Default::default()
+        };
+        state.backend.set_metadata(id, &metadata).await?;
+    }
+    // Aliased links get a code as well, listings show it
+    let code = state.shortner.assign(id).await?;
+    Ok((StatusCode::CREATED, query.alias.unwrap_or(code)))
 }
 
 pub async fn expand_shorten<S: Shortner>(
     State(state): State<Arc<service::State<S>>>,
     Path(shorten): Path<String>,
-) -> Result<Redirect, ServiceError> {
-    let id = state.shortner.decode(&shorten).await?;
-    let uri = state.backend.retrive(id).await?;
+) -> Result<impl IntoResponse, ServiceError> {
+    let id = resolve(&state, &shorten).await?;
+    let (uri, version) = state.backend.retrive_versioned(id).await?;
     let validated_uri = uri.trim().parse::<Uri>()?;
-    Ok(Redirect::temporary(validated_uri.to_string().as_str()))
+    Ok((
+        etag(version),
+        Redirect::temporary(validated_uri.to_string().as_str()),
+    ))
 }
 
 pub async fn get_stat_by_shorten<S: Shortner>(
     State(state): State<Arc<service::State<S>>>,
     Path(shorten): Path<String>,
 ) -> Result<String, ServiceError> {
-    let id = state.shortner.decode(&shorten).await?;
+    let id = resolve(&state, &shorten).await?;
     let stat = state.backend.stat(id, None).await?;
     Ok(stat.to_string())
 }
 
+/// Full link record. Unlike redirects no click is recorded.
+pub async fn get_shorten_info<S: Shortner>(
+    State(state): State<Arc<service::State<S>>>,
+    Path(shorten): Path<String>,
+) -> Result<impl IntoResponse, ServiceError> {
+    let (id, generation) = resolve_generation(&state, &shorten).await?;
+    let link = state.backend.info(id).await?;
+    let aliases = state.backend.aliases(id).await?;
+    Ok((
+        etag(link.version),
+        Json(ShortenInfo {
+            shorten,
+            link,
+            aliases,
+            generation: (generation > 0).then_some(generation),
+        }),
+    ))
+}
+
+/// Replace title, notes, owner and flags of a link.
+pub async fn update_shorten_info<S: Shortner>(
+    State(state): State<Arc<service::State<S>>>,
+    Path(shorten): Path<String>,
+    Json(metadata): Json<Metadata>,
+) -> Result<StatusCode, ServiceError> {
+    let id = resolve(&state, &shorten).await?;
+    state.backend.set_metadata(id, &metadata).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Replace the destination. With `If-Match` the update is applied only to
+/// the version the client has seen.
 pub async fn update_shorten<S: Shortner>(
     State(state): State<Arc<service::State<S>>>,
     Path(shorten): Path<String>,
+    headers: HeaderMap,
     uri: String,
-) -> Result<StatusCode, ServiceError> {
+) -> Result<impl IntoResponse, ServiceError> {
     let validated_uri = uri.trim().parse::<Uri>()?;
-    let id = state.shortner.decode(&shorten).await?;
-    state
+    let id = resolve(&state, &shorten).await?;
+    let expected = expected_version(&headers)?;
+    let version = state
         .backend
-        .update(id, validated_uri.to_string().as_str())
+        .update_versioned(id, validated_uri.to_string().as_str(), expected)
         .await?;
-    Ok(StatusCode::CREATED)
+    Ok((StatusCode::CREATED, etag(version)))
+}
+
+pub async fn get_shorten_history<S: Shortner>(
+    State(state): State<Arc<service::State<S>>>,
+    Path(shorten): Path<String>,
+) -> Result<Json<ShortenHistory>, ServiceError> {
+    let id = resolve(&state, &shorten).await?;
+    let history = state.backend.history(id).await?;
+    Ok(Json(ShortenHistory { shorten, history }))
+}
+
+/// Point the link back to the destination it had at `version`, which is
+/// recorded as a new version. `If-Match` is honored as for updates.
+pub async fn rollback_shorten<S: Shortner>(
+    State(state): State<Arc<service::State<S>>>,
+    Path((shorten, version)): Path<(String, u64)>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, ServiceError> {
+    let id = resolve(&state, &shorten).await?;
+    let expected = expected_version(&headers)?;
+    let version = state.backend.rollback(id, version, expected).await?;
+    Ok((StatusCode::CREATED, etag(version)))
 }
 
 pub async fn delete_shorten<S: Shortner>(
     State(state): State<Arc<service::State<S>>>,
     Path(shorten): Path<String>,
 ) -> Result<StatusCode, ServiceError> {
-    let id = state.shortner.decode(&shorten).await?;
+    let id = resolve(&state, &shorten).await?;
     state.backend.delete(id).await?;
     Ok(StatusCode::GONE)
 }
+
+/// Bring back a deleted link which is not purged yet.
+pub async fn restore_shorten<S: Shortner>(
+    State(state): State<Arc<service::State<S>>>,
+    Path(shorten): Path<String>,
+) -> Result<StatusCode, ServiceError> {
+    let id = resolve(&state, &shorten).await?;
+    state.backend.undelete(id).await?;
+    Ok(StatusCode::OK)
+}
+
+pub async fn list_shortens<S: Shortner>(
+    State(state): State<Arc<service::State<S>>>,
+    Query(query): Query<ListQuery>,
+) -> Result<Json<ShortenPage>, ServiceError> {
+    let after = match &query.cursor {
+        Some(cursor) => Some(state.shortner.decode(cursor).await?),
+        None => None,
+    };
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_PAGE_SIZE)
+        .clamp(1, MAX_PAGE_SIZE);
+    let links = state.backend.list(after, limit).await?;
+    let next = match links.last() {
+        Some((id, _)) if links.len() == limit => Some(state.shortner.encode(*id).await?),
+        _ => None,
+    };
+    let mut urls = Vec::with_capacity(links.len());
+    for (id, url) in links {
+        urls.push(ShortenEntry {
+            shorten: state.shortner.encode(id).await?,
+            url,
+        });
+    }
+    Ok(Json(ShortenPage { urls, next }))
+}
+
+/// Shorten a batch of urls given as a JSON array of strings or, for other
+/// content types, one url per line. Entries fail independently.
+pub async fn create_shorten_batch<S: Shortner>(
+    State(state): State<Arc<service::State<S>>>,
+    headers: HeaderMap,
+    body: String,
+) -> Result<Json<Vec<BatchEntry>>, ServiceError> {
+    let is_json = headers
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("application/json"));
+    let urls: Vec<String> = if is_json {
+        serde_json::from_str(&body)?
+    } else {
+        body.lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(str::to_owned)
+            .collect()
+    };
+    if urls.len() > MAX_BATCH_SIZE {
+        return Err(ServiceError::BatchTooLarge(MAX_BATCH_SIZE));
+    }
+    let validated = urls
+        .iter()
+        .map(|url| url.trim().parse::<Uri>().map(|uri| uri.to_string()))
+        .collect::<Vec<_>>();
+    let valid = validated
+        .iter()
+        .filter_map(|uri| uri.as_ref().ok().cloned())
+        .collect::<Vec<_>>();
+    let mut stored = state.backend.store_many(&valid).await?.into_iter();
+    let mut entries = Vec::with_capacity(urls.len());
+    for (url, uri) in urls.into_iter().zip(validated) {
+        let result = match uri {
+            Ok(_) => match stored.next() {
+                Some(Ok(id)) => state.shortner.assign(id).await.map_err(ServiceError::from),
+                Some(Err(error)) => Err(error.into()),
+                None => Err(ServiceError::Backend(BackendError::Internal(
+                    "Backend returned fewer results than stored urls".into(),
+                ))),
+            },
+            Err(error) => Err(error.into()),
+        };
+        entries.push(match result {
+            Ok(shorten) => BatchEntry {
+                url,
+                shorten: Some(shorten),
+                error: None,
+            },
+            Err(error) => BatchEntry {
+                url,
+                shorten: None,
+                error: Some(error.to_string()),
+            },
+        });
+    }
+    Ok(Json(entries))
+}
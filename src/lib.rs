@@ -1,11 +1,11 @@
-use service::State;
-use shortener::HashIds;
+use service::{BoxedShortner, State};
 
 pub mod backend;
 pub mod errors;
 pub mod handlers;
+pub mod migration;
 pub mod service;
 pub mod settings;
 pub mod shortener;
 
-pub type AppState = State<HashIds>;
+pub type AppState = State<Box<BoxedShortner>>;
@@ -1,12 +1,13 @@
-use std::net::SocketAddr;
+use std::{net::SocketAddr, path::Path};
 
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use axum::Server;
 use shortland::{
-    service::application,
-    settings::{Config, LoggingLevel},
+    migration,
+    service::{application, backend, redis_backend, sharded_redis_backend},
+    settings::{self, Config, LoggingLevel},
 };
-use tracing::info;
+use tracing::{info, warn};
 use tracing_subscriber::{filter::LevelFilter, layer::SubscriberExt, util::SubscriberInitExt};
 
 fn initialize_logging(level: &LoggingLevel) {
@@ -16,13 +17,89 @@ fn initialize_logging(level: &LoggingLevel) {
         .init();
 }
 
+async fn migrate_redis_keyspace(config: &Config, dry_run: bool) -> Result<()> {
+    let moved = match &config.backend {
+        settings::Backend::Redis(backend_config) => {
+            redis_backend(backend_config)
+                .await
+                .context("Unable initialize redis backend")?
+                .migrate_keyspace(dry_run)
+                .await?
+        }
+        settings::Backend::ShardedRedis(backend_config) => {
+            sharded_redis_backend(backend_config)
+                .await
+                .context("Unable initialize sharded redis backend")?
+                .migrate_keyspace(dry_run)
+                .await?
+        }
+        _ => bail!("Keyspace migration requires Redis backend"),
+    };
+    if dry_run {
+        info!(
+            "Keyspace migration dry run finished, {} keys would be moved",
+            moved
+        );
+    } else {
+        info!("Keyspace migration finished, {} keys moved", moved);
+    }
+    Ok(())
+}
+
+async fn migrate_backend(config: &Config) -> Result<()> {
+    let target_config = config
+        .migration
+        .target
+        .as_ref()
+        .context("Migration target backend is not configured")?;
+    let source = backend(&config.backend).await?;
+    let target = backend(target_config).await?;
+    let checkpoint = config.migration.checkpoint.as_deref().map(Path::new);
+    let report = migration::migrate(&source, &target, config.migration.batch_size, checkpoint)
+        .await
+        .context("Migration failed")?;
+    info!(
+        "Migration finished, {} links, {} clicks, {} aliases and {} codes copied",
+        report.links, report.clicks, report.aliases, report.codes
+    );
+    let verification = migration::verify(&source, &target, config.migration.batch_size)
+        .await
+        .context("Verification failed")?;
+    if !verification.is_consistent() {
+        warn!(
+            "Missing links: {:?}, mismatched links: {:?}",
+            verification.missing, verification.mismatched
+        );
+        bail!(
+            "Target is inconsistent: {} of {} links missing, {} mismatched",
+            verification.missing.len(),
+            verification.checked,
+            verification.mismatched.len()
+        );
+    }
+    info!("Verified {} links", verification.checked);
+    Ok(())
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
     let config = Config::load().await.context("Configuration load error")?;
     initialize_logging(&config.logging.level);
+    match std::env::args().nth(1).as_deref() {
+        None | Some("serve") => serve(&config).await,
+        Some("migrate-redis-keyspace") => {
+            let dry_run = std::env::args().nth(2).as_deref() == Some("--dry-run");
+            migrate_redis_keyspace(&config, dry_run).await
+        }
+        Some("migrate-backend") => migrate_backend(&config).await,
+        Some(command) => bail!("Unknown command: {}", command),
+    }
+}
+
+async fn serve(config: &Config) -> Result<()> {
     info!("Startup application");
 
-    let app = application(&config).await?;
+    let app = application(config).await?;
 
     let address = SocketAddr::new(
         config
@@ -0,0 +1,225 @@
+//! Copying links with their click statistics between backends.
+//!
+//! Links keep their numeric ids, so issued short codes stay valid after the
+//! switch. Progress is written to an optional checkpoint file after every
+//! batch and an interrupted migration continues from it.
+
+use std::{collections::VecDeque, io::ErrorKind, path::Path};
+
+use thiserror::Error;
+use tokio::fs;
+use tracing::{info, warn};
+
+use crate::backend::{Backend, BackendError};
+
+#[derive(Error, Debug)]
+pub enum MigrationError {
+    #[error("Backend error: {0}")]
+    Backend(#[from] BackendError),
+    #[error("Checkpoint error: {0}")]
+    Checkpoint(#[from] std::io::Error),
+    #[error("Malformed checkpoint: {0}")]
+    MalformedCheckpoint(String),
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct MigrationReport {
+    /// Number of links copied by this run
+    pub links: u64,
+    /// Number of clicks copied by this run
+    pub clicks: u64,
+    /// Number of aliases copied by this run
+    pub aliases: u64,
+    /// Number of random short codes copied by this run
+    pub codes: u64,
+    /// Id the migration started after, when resumed from a checkpoint
+    pub resumed_after: Option<u64>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct VerificationReport {
+    /// Number of source links checked
+    pub checked: u64,
+    /// Source links absent in the target
+    pub missing: Vec<u64>,
+    /// Links whose destination, tombstone, history or click count differ
+    pub mismatched: Vec<u64>,
+}
+
+impl VerificationReport {
+    pub fn is_consistent(&self) -> bool {
+        self.missing.is_empty() && self.mismatched.is_empty()
+    }
+}
+
+async fn read_checkpoint(path: &Path) -> Result<Option<u64>, MigrationError> {
+    match fs::read_to_string(path).await {
+        Ok(content) => content
+            .trim()
+            .parse()
+            .map(Some)
+            .map_err(|_| MigrationError::MalformedCheckpoint(content)),
+        Err(error) if error.kind() == ErrorKind::NotFound => Ok(None),
+        Err(error) => Err(error.into()),
+    }
+}
+
+async fn write_checkpoint(path: &Path, id: u64) -> Result<(), MigrationError> {
+    let temporary = path.with_extension("tmp");
+    fs::write(&temporary, id.to_string()).await?;
+    fs::rename(&temporary, path).await?;
+    Ok(())
+}
+
+/// Copy every link of `source` into `target` in batches of `batch_size`.
+/// Versions with their history, timestamps, metadata, aliases and short codes
+/// are kept. Tombstones are copied as tombstones, so they can still be
+/// restored and their ids are never handed out again.
+///
+/// Links already present in the target are overwritten, so rerunning a
+/// migration is safe. Links purged from the source while migrating are
+/// skipped.
+///
+/// Afterwards the target counter is moved past the last id of the source
+/// counter, so ids of purged links are not handed out again and their codes
+/// never point to unrelated links. This takes one id of the source counter.
+pub async fn migrate<S, T>(
+    source: &S,
+    target: &T,
+    batch_size: usize,
+    checkpoint: Option<&Path>,
+) -> Result<MigrationReport, MigrationError>
+where
+    S: Backend + Sync + ?Sized,
+    T: Backend + Sync + ?Sized,
+{
+    let mut after = match checkpoint {
+        Some(path) => read_checkpoint(path).await?,
+        None => None,
+    };
+    let mut report = MigrationReport {
+        resumed_after: after,
+        ..Default::default()
+    };
+    if let Some(id) = after {
+        info!("Resume migration after id {}", id);
+    }
+    loop {
+        let links = source.list_with_tombstones(after, batch_size).await?;
+        let Some((last, _)) = links.last() else {
+            break;
+        };
+        let last = *last;
+        for (id, _) in links {
+            let (link, history, clicks) = match (
+                source.info(id).await,
+                source.history(id).await,
+                source.clicks(id).await,
+            ) {
+                (Ok(link), Ok(history), Ok(clicks)) => (link, history, clicks),
+                (Err(BackendError::NotFound), _, _)
+                | (_, Err(BackendError::NotFound), _)
+                | (_, _, Err(BackendError::NotFound)) => {
+                    warn!("Skip link {} purged during migration", id);
+                    continue;
+                }
+                (Err(error), _, _) | (_, Err(error), _) | (_, _, Err(error)) => {
+                    return Err(error.into())
+                }
+            };
+            target.restore(id, &link, &history, &clicks).await?;
+            for alias in source.aliases(id).await? {
+                target.store_alias(&alias, id).await?;
+                report.aliases += 1;
+            }
+            match source.code(id).await {
+                Ok(code) => {
+                    target.store_code(id, &code).await?;
+                    report.codes += 1;
+                }
+                Err(BackendError::NotFound) => {}
+                Err(error) => return Err(error.into()),
+            }
+            report.links += 1;
+            report.clicks += clicks.iter().map(|(_, counter)| counter).sum::<u64>();
+        }
+        if let Some(path) = checkpoint {
+            write_checkpoint(path, last).await?;
+        }
+        info!("Migrated {} links, last id {}", report.links, last);
+        after = Some(last);
+    }
+    let last_id = source.allocate_ids(1).await?;
+    if let Some(id) = last_id.first() {
+        target.reserve_until(*id).await?;
+    }
+    Ok(report)
+}
+
+/// Compare destinations, tombstones, histories and click totals of every
+/// source link, tombstones included, with the target. Links existing only in
+/// the target are ignored.
+pub async fn verify<S, T>(
+    source: &S,
+    target: &T,
+    batch_size: usize,
+) -> Result<VerificationReport, MigrationError>
+where
+    S: Backend + Sync + ?Sized,
+    T: Backend + Sync + ?Sized,
+{
+    let mut report = VerificationReport::default();
+    let mut after = None;
+    let mut target_after = None;
+    let mut target_exhausted = false;
+    let mut pending: VecDeque<(u64, String)> = VecDeque::new();
+    loop {
+        let links = source.list_with_tombstones(after, batch_size).await?;
+        let Some((last, _)) = links.last() else {
+            break;
+        };
+        after = Some(*last);
+        for (id, url) in links {
+            while !target_exhausted && pending.back().is_none_or(|(next, _)| *next < id) {
+                let batch = target
+                    .list_with_tombstones(target_after, batch_size)
+                    .await?;
+                match batch.last() {
+                    Some((last, _)) => target_after = Some(*last),
+                    None => target_exhausted = true,
+                }
+                pending.extend(batch);
+            }
+            while pending.front().is_some_and(|(next, _)| *next < id) {
+                pending.pop_front();
+            }
+            report.checked += 1;
+            match pending.front() {
+                Some((next, target_url)) if *next == id => {
+                    if *target_url != url
+                        || source.info(id).await?.deleted_at != target.info(id).await?.deleted_at
+                        || source.history(id).await? != target.history(id).await?
+                        || total(source, id).await? != total(target, id).await?
+                    {
+                        warn!("Link {} differs in target", id);
+                        report.mismatched.push(id);
+                    }
+                }
+                _ => {
+                    warn!("Link {} is missing in target", id);
+                    report.missing.push(id);
+                }
+            }
+        }
+    }
+    Ok(report)
+}
+
+async fn total<B: Backend + Sync + ?Sized>(backend: &B, id: u64) -> Result<u64, BackendError> {
+    Ok(backend
+        .clicks(id)
+        .await?
+        .iter()
+        .map(|(_, counter)| counter)
+        .sum())
+}
@@ -1,33 +1,50 @@
-use std::sync::Arc;
+use std::{num::NonZeroUsize, sync::Arc, time::Duration};
 
 use anyhow::Context;
 use axum::{
     routing::{get, post},
     Router,
 };
+use chrono::Utc;
 use tower::ServiceBuilder;
 use tower_http::trace::{DefaultOnResponse, TraceLayer};
-use tracing::Level;
+use tracing::{info, warn, Level};
 
 use crate::{
-    backend::{memory::InMemoryBackend, redis::RedisBackend, Backend},
+    backend::{
+        allocator::{AllocatedBackend, Block, Snowflake},
+        cached::CachedBackend,
+        chaos::{ChaosBackend, Fault, Operation},
+        memory::InMemoryBackend,
+        postgres::PostgresBackend,
+        redb::RedbBackend,
+        redis::RedisBackend,
+        sharded::ShardedRedisBackend,
+        sqlite::SqliteBackend,
+        Backend,
+    },
     errors::ServiceError,
     handlers::{
-        create_shorten, delete_shorten, expand_shorten, get_stat_by_shorten, update_shorten,
+        create_shorten, create_shorten_batch, delete_shorten, expand_shorten, get_shorten_history,
+        get_shorten_info, get_stat_by_shorten, list_shortens, restore_shorten, rollback_shorten,
+        update_shorten, update_shorten_info,
     },
     settings::{self, Config},
-    shortener::{HashIds, Shortner},
+    shortener::{
+        validate_rotation, Base58, Base62, HashIds, RandomCodes, Rotation, Shortner, ShortnerError,
+    },
     AppState,
 };
 
-type BoxedBackend = dyn Backend + Send + Sync;
+pub type BoxedBackend = dyn Backend + Send + Sync;
+pub type BoxedShortner = dyn Shortner + Send + Sync;
 
 pub struct State<S>
 where
     S: Shortner,
 {
     pub shortner: S,
-    pub backend: Box<BoxedBackend>,
+    pub backend: Arc<BoxedBackend>,
     pub config: Config,
 }
 
@@ -51,7 +68,7 @@ where
 {
     pub config: Option<Config>,
     pub shortner: Option<S>,
-    pub backend: Option<Box<BoxedBackend>>,
+    pub backend: Option<Arc<BoxedBackend>>,
 }
 
 impl<S> StateBuilder<S>
@@ -73,7 +90,7 @@ where
         }
     }
 
-    pub fn backend(self, backend: Box<BoxedBackend>) -> StateBuilder<S> {
+    pub fn backend(self, backend: Arc<BoxedBackend>) -> StateBuilder<S> {
         Self {
             backend: Some(backend),
             ..self
@@ -95,16 +112,205 @@ where
     }
 }
 
-pub async fn application(config: &Config) -> anyhow::Result<Router> {
-    let shortner = HashIds::new(None).context("Unable to initialize shortner")?;
-    let backend: Box<BoxedBackend> = match &config.backend {
+pub async fn redis_backend(config: &settings::RedisBackend) -> anyhow::Result<RedisBackend> {
+    let nodes = config.nodes.iter().map(String::as_str).collect::<Vec<_>>();
+    let backend = match config.topology {
+        settings::RedisTopology::Standalone => {
+            RedisBackend::new(config.connection.as_str()).await?
+        }
+        settings::RedisTopology::Cluster if nodes.is_empty() => {
+            RedisBackend::cluster(vec![config.connection.as_str()]).await?
+        }
+        settings::RedisTopology::Cluster => RedisBackend::cluster(nodes).await?,
+        settings::RedisTopology::Sentinel => {
+            let service = config
+                .service
+                .as_deref()
+                .context("Sentinel topology requires master service name")?;
+            RedisBackend::sentinel(nodes, service, config.connection.as_str()).await?
+        }
+    };
+    let backend = backend.with_prefix(config.prefix.as_str());
+    backend.index_ids().await?;
+    if config.replicas.is_empty() {
+        Ok(backend)
+    } else {
+        let replicas = config.replicas.iter().map(String::as_str).collect();
+        Ok(backend.with_replicas(replicas))
+    }
+}
+
+pub async fn sharded_redis_backend(
+    config: &settings::ShardedRedisBackend,
+) -> anyhow::Result<ShardedRedisBackend> {
+    let shards = config.shards.iter().map(String::as_str).collect::<Vec<_>>();
+    let backend = ShardedRedisBackend::new(shards)
+        .await?
+        .with_prefix(&config.prefix);
+    backend.check_shards().await?;
+    backend.index_ids().await?;
+    Ok(backend)
+}
+
+fn chaos_backend(
+    backend: Box<BoxedBackend>,
+    config: &settings::Chaos,
+) -> ChaosBackend<Box<BoxedBackend>> {
+    let fault = |fault: &settings::Fault| Fault {
+        failure_rate: fault.failure_rate,
+        timeout_rate: fault.timeout_rate,
+        latency_rate: fault.latency_rate,
+        latency: Duration::from_millis(fault.latency),
+    };
+    ChaosBackend::new(backend)
+        .with_timeout(Duration::from_millis(config.timeout))
+        .with_fault(Operation::Store, fault(&config.store))
+        .with_fault(Operation::Retrive, fault(&config.retrive))
+        .with_fault(Operation::Stat, fault(&config.stat))
+        .with_fault(Operation::Update, fault(&config.update))
+        .with_fault(Operation::Delete, fault(&config.delete))
+        .with_fault(Operation::Click, fault(&config.click))
+        .with_fault(Operation::List, fault(&config.list))
+        .with_fault(Operation::Clicks, fault(&config.clicks))
+        .with_fault(Operation::Restore, fault(&config.restore))
+        .with_fault(Operation::Info, fault(&config.info))
+        .with_fault(Operation::SetMetadata, fault(&config.set_metadata))
+        .with_fault(Operation::Undelete, fault(&config.undelete))
+        .with_fault(Operation::Purge, fault(&config.purge))
+        .with_fault(Operation::History, fault(&config.history))
+        .with_fault(Operation::Find, fault(&config.find))
+        .with_fault(Operation::Allocate, fault(&config.allocate))
+        .with_fault(Operation::Alias, fault(&config.alias))
+        .with_fault(Operation::Code, fault(&config.code))
+}
+
+/// Storage described by `config`, without cache and chaos decorators.
+pub async fn backend(config: &settings::Backend) -> anyhow::Result<Box<BoxedBackend>> {
+    let backend: Box<BoxedBackend> = match config {
         settings::Backend::Redis(backend_config) => Box::new(
-            RedisBackend::new(backend_config.connection.as_str())
+            redis_backend(backend_config)
                 .await
                 .context("Unable initialize redis backend")?,
         ),
-        settings::Backend::InMemory => Box::new(InMemoryBackend::new()),
+        settings::Backend::ShardedRedis(backend_config) => Box::new(
+            sharded_redis_backend(backend_config)
+                .await
+                .context("Unable initialize sharded redis backend")?,
+        ),
+        settings::Backend::Sqlite(backend_config) => Box::new(
+            SqliteBackend::new(backend_config.path.as_str())
+                .await
+                .context("Unable initialize sqlite backend")?,
+        ),
+        settings::Backend::Postgres(backend_config) => Box::new(
+            PostgresBackend::new(backend_config.connection.as_str(), backend_config.pool_size)
+                .await
+                .context("Unable initialize postgres backend")?,
+        ),
+        settings::Backend::Redb(backend_config) => Box::new(
+            RedbBackend::new(backend_config.path.as_str())
+                .await
+                .context("Unable initialize redb backend")?,
+        ),
+        settings::Backend::InMemory(backend_config) => match &backend_config.persistence {
+            Some(persistence) => {
+                let backend = Arc::new(
+                    InMemoryBackend::with_persistence(
+                        persistence.journal.as_str(),
+                        persistence.snapshot.as_str(),
+                        Duration::from_secs(persistence.snapshot_interval),
+                    )
+                    .await
+                    .context("Unable initialize persistent inmemory backend")?,
+                );
+                backend.spawn_snapshots();
+                Box::new(backend)
+            }
+            None => Box::new(InMemoryBackend::new()),
+        },
     };
+    Ok(backend)
+}
+
+/// Shortener described by `config`. Random codes are kept in `backend`.
+pub fn shortner(
+    config: &settings::Shortener,
+    backend: Arc<BoxedBackend>,
+) -> Result<Box<BoxedShortner>, ShortnerError> {
+    let shortner: Box<BoxedShortner> = match config {
+        settings::Shortener::HashIds(config) => Box::new(HashIds::new(config)?),
+        settings::Shortener::Random(config) => Box::new(RandomCodes::new(backend, config)?),
+        settings::Shortener::Base62 => Box::new(Base62),
+        settings::Shortener::Base58 => Box::new(Base58),
+    };
+    Ok(shortner)
+}
+
+/// Periodically purge tombstones older than `purge_after`.
+async fn purge_tombstones<S: Shortner>(state: Arc<State<S>>, config: settings::Tombstones) {
+    let mut interval = tokio::time::interval(Duration::from_secs(config.purge_interval));
+    let purge_after = chrono::Duration::seconds(config.purge_after as i64);
+    loop {
+        interval.tick().await;
+        let Some(before) = Utc::now().checked_sub_signed(purge_after) else {
+            continue;
+        };
+        match state.backend.purge(before).await {
+            Ok(0) => {}
+            Ok(purged) => info!("Purged {} deleted links", purged),
+            Err(error) => warn!("Unable to purge deleted links: {}", error),
+        }
+    }
+}
+
+pub async fn application(config: &Config) -> anyhow::Result<Router> {
+    let mut backend = backend(&config.backend).await?;
+    match config.allocator {
+        settings::IdAllocator::Counter => {}
+        settings::IdAllocator::Block { size } => {
+            backend = Box::new(AllocatedBackend::new(backend, Block::new(size)?));
+        }
+        settings::IdAllocator::Snowflake { node } => {
+            backend = Box::new(AllocatedBackend::new(backend, Snowflake::new(node)?));
+        }
+    }
+    if config.chaos.enabled {
+        backend = Box::new(chaos_backend(backend, &config.chaos));
+    }
+    if config.cache.enabled {
+        let capacity =
+            NonZeroUsize::new(config.cache.capacity).context("Cache capacity must be positive")?;
+        backend = Box::new(CachedBackend::new(
+            backend,
+            capacity,
+            Duration::from_secs(config.cache.ttl),
+        ));
+    }
+
+    let backend: Arc<BoxedBackend> = Arc::from(backend);
+    if let settings::Shortener::Random(random) = &config.shortener {
+        let assigned = RandomCodes::new(backend.clone(), random)
+            .context("Unable to initialize shortner")?
+            .assign_missing()
+            .await
+            .context("Unable to assign random codes")?;
+        if assigned > 0 {
+            info!("Assigned random codes to {} links", assigned);
+        }
+    }
+    let mut shortner =
+        shortner(&config.shortener, backend.clone()).context("Unable to initialize shortner")?;
+    if !config.retired_shorteners.is_empty() {
+        validate_rotation(&config.shortener, &config.retired_shorteners)
+            .context("Unable to initialize retired shortner")?;
+        let retired = config
+            .retired_shorteners
+            .iter()
+            .map(|retired| self::shortner(retired, backend.clone()))
+            .collect::<Result<Vec<_>, _>>()
+            .context("Unable to initialize retired shortner")?;
+        shortner = Box::new(Rotation::new(shortner, retired));
+    }
 
     let state = AppState::builder()
         .shortner(shortner)
@@ -112,19 +318,31 @@ pub async fn application(config: &Config) -> anyhow::Result<Router> {
         .backend(backend)
         .build()
         .context("Unable to initialize application state")?;
+    let state = Arc::new(state);
+    if config.tombstones.purge_interval > 0 {
+        tokio::spawn(purge_tombstones(state.clone(), config.tombstones.clone()));
+    }
 
     let app = Router::new()
-        .route("/urls", post(create_shorten))
+        .route("/urls", get(list_shortens).post(create_shorten))
+        .route("/urls/batch", post(create_shorten_batch))
         .route(
             "/urls/:shorten",
             get(expand_shorten)
                 .put(update_shorten)
                 .delete(delete_shorten),
         )
+        .route(
+            "/urls/:shorten/info",
+            get(get_shorten_info).put(update_shorten_info),
+        )
+        .route("/urls/:shorten/restore", post(restore_shorten))
+        .route("/urls/:shorten/history", get(get_shorten_history))
+        .route("/urls/:shorten/rollback/:version", post(rollback_shorten))
         .route("/urls/:shorten/stats", get(get_stat_by_shorten))
         .layer(ServiceBuilder::new().layer(
             TraceLayer::new_for_http().on_response(DefaultOnResponse::new().level(Level::INFO)),
         ))
-        .with_state(Arc::new(state));
+        .with_state(state);
     Ok(app)
 }
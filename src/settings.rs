@@ -44,25 +44,379 @@ pub struct Logging {
     pub level: LoggingLevel,
 }
 
+#[derive(Default, Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum RedisTopology {
+    #[default]
+    Standalone,
+    Cluster,
+    Sentinel,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct RedisBackend {
+    /// Standalone server. For Sentinel only database and credentials are used.
     pub connection: String,
+    #[serde(default)]
+    pub topology: RedisTopology,
+    /// Cluster seed nodes or sentinel addresses
+    #[serde(default)]
+    pub nodes: Vec<String>,
+    /// Name of the master monitored by sentinels
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub service: Option<String>,
+    /// Namespace of all keys, e.g. `shortland:`
+    #[serde(default)]
+    pub prefix: String,
+    /// Read replicas serving destination lookups
+    #[serde(default)]
+    pub replicas: Vec<String>,
 }
 
 impl Default for RedisBackend {
     fn default() -> Self {
         Self {
             connection: "redis://localhost:6379/0".to_owned(),
+            topology: RedisTopology::default(),
+            nodes: Vec::new(),
+            service: None,
+            prefix: String::new(),
+            replicas: Vec::new(),
         }
     }
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ShardedRedisBackend {
+    /// Shard connections, fixed once links are stored. Ids are allocated on
+    /// the first one, which every write depends on.
+    pub shards: Vec<String>,
+    /// Namespace of all keys, e.g. `shortland:`
+    #[serde(default)]
+    pub prefix: String,
+}
+
+impl Default for ShardedRedisBackend {
+    fn default() -> Self {
+        Self {
+            shards: vec!["redis://localhost:6379/0".to_owned()],
+            prefix: String::new(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SqliteBackend {
+    pub path: String,
+}
+
+impl Default for SqliteBackend {
+    fn default() -> Self {
+        Self {
+            path: "shortland.db".to_owned(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RedbBackend {
+    pub path: String,
+}
+
+impl Default for RedbBackend {
+    fn default() -> Self {
+        Self {
+            path: "shortland.redb".to_owned(),
+        }
+    }
+}
+
+fn default_pool_size() -> usize {
+    16
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PostgresBackend {
+    pub connection: String,
+    #[serde(default = "default_pool_size")]
+    pub pool_size: usize,
+}
+
+impl Default for PostgresBackend {
+    fn default() -> Self {
+        Self {
+            connection: "postgres://postgres@localhost:5432/shortland".to_owned(),
+            pool_size: default_pool_size(),
+        }
+    }
+}
+
+fn default_snapshot_interval() -> u64 {
+    300
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MemoryPersistence {
+    pub journal: String,
+    pub snapshot: String,
+    /// Snapshot interval in seconds
+    #[serde(default = "default_snapshot_interval")]
+    pub snapshot_interval: u64,
+}
+
 #[derive(Default, Serialize, Deserialize, Debug, Clone)]
+pub struct InMemoryBackend {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub persistence: Option<MemoryPersistence>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(tag = "type")]
 pub enum Backend {
     Redis(RedisBackend),
+    ShardedRedis(ShardedRedisBackend),
+    Sqlite(SqliteBackend),
+    Postgres(PostgresBackend),
+    Redb(RedbBackend),
+    InMemory(InMemoryBackend),
+}
+
+impl Default for Backend {
+    fn default() -> Self {
+        Backend::InMemory(InMemoryBackend::default())
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Cache {
+    pub enabled: bool,
+    pub capacity: usize,
+    /// Entry time to live in seconds
+    pub ttl: u64,
+}
+
+impl Default for Cache {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            capacity: 10000,
+            ttl: 60,
+        }
+    }
+}
+
+/// Faults injected into a backend operation. Rates are probabilities in `0.0..=1.0`.
+#[derive(Default, Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct Fault {
+    pub failure_rate: f64,
+    pub timeout_rate: f64,
+    pub latency_rate: f64,
+    /// Injected latency in milliseconds
+    pub latency: u64,
+}
+
+/// Faults per backend operation, see [`crate::backend::chaos::Operation`].
+/// Batch operations share the faults of their single counterparts, aliases
+/// and codes cover storing as well as resolving them.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct Chaos {
+    pub enabled: bool,
+    /// How long an injected timeout hangs in milliseconds
+    pub timeout: u64,
+    pub store: Fault,
+    pub retrive: Fault,
+    pub stat: Fault,
+    pub update: Fault,
+    pub delete: Fault,
+    pub click: Fault,
+    pub list: Fault,
+    pub clicks: Fault,
+    pub restore: Fault,
+    pub info: Fault,
+    pub set_metadata: Fault,
+    pub undelete: Fault,
+    pub purge: Fault,
+    pub history: Fault,
+    pub find: Fault,
+    pub allocate: Fault,
+    pub alias: Fault,
+    pub code: Fault,
+}
+
+impl Default for Chaos {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            timeout: 30000,
+            store: Fault::default(),
+            retrive: Fault::default(),
+            stat: Fault::default(),
+            update: Fault::default(),
+            delete: Fault::default(),
+            click: Fault::default(),
+            list: Fault::default(),
+            clicks: Fault::default(),
+            restore: Fault::default(),
+            info: Fault::default(),
+            set_metadata: Fault::default(),
+            undelete: Fault::default(),
+            purge: Fault::default(),
+            history: Fault::default(),
+            find: Fault::default(),
+            allocate: Fault::default(),
+            alias: Fault::default(),
+            code: Fault::default(),
+        }
+    }
+}
+
+/// Deleted links are kept as tombstones and may be restored until purged.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct Tombstones {
+    /// Seconds a tombstone is kept before it is purged
+    pub purge_after: u64,
+    /// Seconds between purges, `0` keeps tombstones forever
+    pub purge_interval: u64,
+}
+
+impl Default for Tombstones {
+    fn default() -> Self {
+        Self {
+            purge_after: 30 * 24 * 3600,
+            purge_interval: 3600,
+        }
+    }
+}
+
+/// Whether creating a link to an already shortened destination returns the
+/// existing link instead of a new one.
+#[derive(Default, Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum Deduplication {
+    #[default]
+    Disabled,
+    /// Any live link to the destination is returned
+    Global,
+    /// Only a link of the same owner is returned
+    Owner,
+}
+
+/// Parameters of hashids codes. Changing any of them changes codes of all
+/// existing links.
+#[derive(Default, Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct HashIdsShortener {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub salt: Option<String>,
+    /// At least 16 distinct characters, without spaces
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub alphabet: Option<String>,
+    /// Codes are padded up to this length
+    pub min_length: usize,
+    /// Characters of the alphabet used as separators
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub separators: Option<String>,
+}
+
+fn default_code_length() -> usize {
+    8
+}
+
+/// Parameters of random codes. Codes are kept in the backend, so changing
+/// them affects only new links.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RandomShortener {
+    #[serde(default = "default_code_length")]
+    pub length: usize,
+    /// At least 16 distinct characters, without spaces
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub alphabet: Option<String>,
+}
+
+impl Default for RandomShortener {
+    fn default() -> Self {
+        Self {
+            length: default_code_length(),
+            alphabet: None,
+        }
+    }
+}
+
+/// How short codes of links are made.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type")]
+pub enum Shortener {
+    /// Hashids of link ids
+    HashIds(HashIdsShortener),
+    /// Random codes stored in the backend, which do not reveal link ids
+    Random(RandomShortener),
+    /// Link ids in base 62, digits `0-9A-Za-z`
+    Base62,
+    /// Link ids in base 58, the Bitcoin alphabet
+    Base58,
+}
+
+impl Default for Shortener {
+    fn default() -> Self {
+        Self::HashIds(HashIdsShortener::default())
+    }
+}
+
+/// Custom aliases requested on link creation.
+#[derive(Default, Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct Aliases {
+    /// Words never accepted as aliases, compared case-insensitively, in
+    /// addition to route names
+    pub reserved: Vec<String>,
+}
+
+fn default_block_size() -> u64 {
+    1000
+}
+
+/// Where ids of new links come from.
+#[derive(Default, Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type")]
+pub enum IdAllocator {
+    /// Counter of the backend, one id per round trip
     #[default]
-    InMemory,
+    Counter,
+    /// Ranges of `size` ids taken from the backend counter
+    Block {
+        #[serde(default = "default_block_size")]
+        size: u64,
+    },
+    /// Time based ids, `node` must be distinct for every instance and below 1024
+    Snowflake { node: u16 },
+}
+
+fn default_migration_batch_size() -> usize {
+    500
+}
+
+/// Backend-to-backend migration, run with the `migrate-backend` command.
+/// Links are copied from `backend` to `target`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Migration {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target: Option<Backend>,
+    #[serde(default = "default_migration_batch_size")]
+    pub batch_size: usize,
+    /// File keeping the last migrated id, so an interrupted migration resumes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub checkpoint: Option<String>,
+}
+
+impl Default for Migration {
+    fn default() -> Self {
+        Self {
+            target: None,
+            batch_size: default_migration_batch_size(),
+            checkpoint: None,
+        }
+    }
 }
 
 #[derive(Default, Debug, Deserialize, Serialize, Clone)]
@@ -71,6 +425,17 @@ pub struct Config {
     pub http: Http,
     pub logging: Logging,
     pub backend: Backend,
+    pub cache: Cache,
+    pub chaos: Chaos,
+    pub tombstones: Tombstones,
+    pub deduplication: Deduplication,
+    pub allocator: IdAllocator,
+    pub shortener: Shortener,
+    /// Shorteners replaced by `shortener`, from the newest to the oldest.
+    /// Their codes are still accepted, new codes are made by `shortener`.
+    pub retired_shorteners: Vec<Shortener>,
+    pub aliases: Aliases,
+    pub migration: Migration,
 }
 
 impl Config {
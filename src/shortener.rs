@@ -1,28 +1,129 @@
+use std::{collections::HashSet, sync::Arc};
+
 use async_trait::async_trait;
 use harsh::{BuildError, Harsh, HarshBuilder};
+use rand::{thread_rng, Rng};
 use thiserror::Error;
+use tracing::{debug, warn};
+
+use crate::{backend::BackendError, service::BoxedBackend, settings};
+
+/// Random codes tried for a link before giving up.
+static MAX_CODE_ATTEMPTS: usize = 8;
+/// Links checked at once for missing random codes.
+static ASSIGN_BATCH_SIZE: usize = 500;
+/// Digits of [`Base62`], in order of value.
+static BASE62_ALPHABET: &[u8] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+/// Digits of [`Base58`], in order of value. The Bitcoin alphabet, without
+/// `0`, `O`, `I` and `l`.
+static BASE58_ALPHABET: &[u8] = b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
 
 #[derive(Error, Debug)]
 pub enum ShortnerError {
     #[error("Shortner initialization error: {0}")]
     Initialization(#[from] BuildError),
+    #[error("Code length must be positive")]
+    CodeLength,
     #[error("Shorten Decode error")]
     Decode(#[from] harsh::Error),
+    #[error("Malformed code")]
+    Malformed,
+    #[error("No free code found in {0} attempts")]
+    Exhausted(usize),
+    #[error("Link {0} has no code")]
+    Unassigned(u64),
+    #[error("Invalid shortener rotation: {0}")]
+    Rotation(&'static str),
+    #[error(transparent)]
+    Backend(#[from] BackendError),
 }
 
 #[async_trait]
-pub trait Shortner {
+pub trait Shortner: Send + Sync {
     async fn decode<'a>(&self, url: &'a str) -> Result<u64, ShortnerError>;
     async fn encode(&self, id: u64) -> Result<String, ShortnerError>;
+
+    /// Code of a link being created. Shorteners keeping codes in the backend
+    /// store it here, [`Shortner::encode`] only looks it up.
+    async fn assign(&self, id: u64) -> Result<String, ShortnerError> {
+        self.encode(id).await
+    }
+
+    /// [`Shortner::decode`] with the generation of the code, `0` unless
+    /// shorteners are rotated, see [`Rotation`].
+    async fn decode_generation<'a>(&self, url: &'a str) -> Result<(u64, usize), ShortnerError> {
+        Ok((self.decode(url).await?, 0))
+    }
+}
+
+#[async_trait]
+impl<S> Shortner for Box<S>
+where
+    S: Shortner + Send + Sync + ?Sized,
+{
+    async fn decode<'a>(&self, url: &'a str) -> Result<u64, ShortnerError> {
+        (**self).decode(url).await
+    }
+
+    async fn encode(&self, id: u64) -> Result<String, ShortnerError> {
+        (**self).encode(id).await
+    }
+
+    async fn assign(&self, id: u64) -> Result<String, ShortnerError> {
+        (**self).assign(id).await
+    }
+
+    async fn decode_generation<'a>(&self, url: &'a str) -> Result<(u64, usize), ShortnerError> {
+        (**self).decode_generation(url).await
+    }
 }
 
 pub struct HashIds {
     convertor: Harsh,
 }
 
+/// Alphabet of [`Harsh`] when none is configured.
+static DEFAULT_ALPHABET: &str = "abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ1234567890";
+
+/// Codes are path segments, so only characters which need no escaping are
+/// allowed.
+fn validate_alphabet(alphabet: &str) -> Result<(), BuildError> {
+    match alphabet
+        .chars()
+        .find(|c| !c.is_ascii_alphanumeric() && !"-_.~".contains(*c))
+    {
+        Some(illegal) => Err(BuildError::IllegalCharacter(illegal)),
+        None => Ok(()),
+    }
+}
+
+/// Separators are taken from the alphabet, `Harsh` would silently drop the
+/// others.
+fn validate(config: &settings::HashIdsShortener) -> Result<(), BuildError> {
+    let alphabet = config.alphabet.as_deref().unwrap_or(DEFAULT_ALPHABET);
+    validate_alphabet(alphabet)?;
+    let separators = config.separators.as_deref().unwrap_or_default();
+    if separators.chars().any(|c| !alphabet.contains(c)) {
+        return Err(BuildError::Separator);
+    }
+    Ok(())
+}
+
 impl HashIds {
-    pub fn new(_salt: Option<String>) -> Result<Self, ShortnerError> {
-        let convertor = HarshBuilder::new().build()?;
+    pub fn new(config: &settings::HashIdsShortener) -> Result<Self, ShortnerError> {
+        validate(config)?;
+        let mut builder = HarshBuilder::new().length(config.min_length);
+        match &config.salt {
+            Some(salt) => builder = builder.salt(salt.as_str()),
+            None => warn!("Shortener salt is not configured, codes are predictable"),
+        }
+        if let Some(alphabet) = &config.alphabet {
+            builder = builder.alphabet(alphabet.as_str());
+        }
+        if let Some(separators) = &config.separators {
+            builder = builder.separators(separators.as_str());
+        }
+        let convertor = builder.build()?;
         Ok(Self { convertor })
     }
 }
@@ -42,3 +143,268 @@ impl Shortner for HashIds {
         Ok(self.convertor.encode(&[id]))
     }
 }
+
+/// Random fixed-length codes kept as a code to id mapping in the backend,
+/// so codes do not reveal link ids and can not be enumerated.
+///
+/// A link gets its code when it is created, encoding only looks it up.
+/// Links stored without one, e.g. copied from a deployment with another
+/// shortener, get theirs from [`RandomCodes::assign_missing`]. Codes
+/// colliding with stored codes or aliases are drawn again.
+pub struct RandomCodes {
+    backend: Arc<BoxedBackend>,
+    alphabet: Vec<char>,
+    length: usize,
+}
+
+impl RandomCodes {
+    pub fn new(
+        backend: Arc<BoxedBackend>,
+        config: &settings::RandomShortener,
+    ) -> Result<Self, ShortnerError> {
+        if config.length == 0 {
+            return Err(ShortnerError::CodeLength);
+        }
+        let alphabet = config.alphabet.as_deref().unwrap_or(DEFAULT_ALPHABET);
+        validate_alphabet(alphabet)?;
+        let mut seen = HashSet::new();
+        let alphabet = alphabet
+            .chars()
+            .filter(|c| seen.insert(*c))
+            .collect::<Vec<_>>();
+        if alphabet.len() < 16 {
+            return Err(BuildError::AlphabetLength.into());
+        }
+        Ok(Self {
+            backend,
+            alphabet,
+            length: config.length,
+        })
+    }
+
+    fn generate(&self) -> String {
+        let mut rng = thread_rng();
+        (0..self.length)
+            .map(|_| self.alphabet[rng.gen_range(0..self.alphabet.len())])
+            .collect()
+    }
+
+    /// Give every stored link without a code one, tombstones included.
+    /// Returns number of assigned codes.
+    pub async fn assign_missing(&self) -> Result<u64, ShortnerError> {
+        let mut assigned = 0;
+        let mut after = None;
+        loop {
+            let links = self
+                .backend
+                .list_with_tombstones(after, ASSIGN_BATCH_SIZE)
+                .await?;
+            let Some((last, _)) = links.last() else {
+                return Ok(assigned);
+            };
+            after = Some(*last);
+            for (id, _) in links {
+                match self.backend.code(id).await {
+                    Ok(_) => {}
+                    Err(BackendError::NotFound) => {
+                        self.assign(id).await?;
+                        assigned += 1;
+                    }
+                    Err(error) => return Err(error.into()),
+                }
+            }
+        }
+    }
+
+    /// Aliases are resolved before codes, a code equal to one would never
+    /// be reached.
+    async fn is_alias(&self, code: &str) -> Result<bool, ShortnerError> {
+        match self.backend.resolve_alias(code).await {
+            Ok(_) => Ok(true),
+            Err(BackendError::NotFound) => Ok(false),
+            Err(error) => Err(error.into()),
+        }
+    }
+}
+
+#[async_trait]
+impl Shortner for RandomCodes {
+    async fn decode<'a>(&self, url: &'a str) -> Result<u64, ShortnerError> {
+        if url.chars().count() != self.length || url.chars().any(|c| !self.alphabet.contains(&c)) {
+            return Err(ShortnerError::Malformed);
+        }
+        Ok(self.backend.resolve_code(url).await?)
+    }
+
+    async fn encode(&self, id: u64) -> Result<String, ShortnerError> {
+        match self.backend.code(id).await {
+            Ok(code) => Ok(code),
+            Err(BackendError::NotFound) => Err(ShortnerError::Unassigned(id)),
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    /// Draws a code unless the link has one already.
+    async fn assign(&self, id: u64) -> Result<String, ShortnerError> {
+        match self.encode(id).await {
+            Err(ShortnerError::Unassigned(_)) => {}
+            result => return result,
+        }
+        for _ in 0..MAX_CODE_ATTEMPTS {
+            let code = self.generate();
+            if self.is_alias(&code).await? {
+                continue;
+            }
+            match self.backend.store_code(id, &code).await {
+                Ok(code) => return Ok(code),
+                Err(BackendError::AlreadyExists) => debug!("Code {} is taken, drawing again", code),
+                Err(error) => return Err(error.into()),
+            }
+        }
+        Err(ShortnerError::Exhausted(MAX_CODE_ATTEMPTS))
+    }
+}
+
+/// Id written in base `alphabet.len()`, most significant digit first.
+fn encode_base(mut id: u64, alphabet: &[u8]) -> String {
+    let base = alphabet.len() as u64;
+    let mut digits = Vec::new();
+    loop {
+        digits.push(alphabet[(id % base) as usize]);
+        id /= base;
+        if id == 0 {
+            break;
+        }
+    }
+    digits.iter().rev().map(|digit| *digit as char).collect()
+}
+
+/// Inverse of [`encode_base`]. Codes with leading zero digits are rejected,
+/// so every id has exactly one code.
+fn decode_base(code: &str, alphabet: &[u8]) -> Result<u64, ShortnerError> {
+    let base = alphabet.len() as u64;
+    if code.is_empty() || (code.len() > 1 && code.as_bytes()[0] == alphabet[0]) {
+        return Err(ShortnerError::Malformed);
+    }
+    code.bytes().try_fold(0u64, |id, byte| {
+        let digit = alphabet
+            .iter()
+            .position(|digit| *digit == byte)
+            .ok_or(ShortnerError::Malformed)?;
+        id.checked_mul(base)
+            .and_then(|id| id.checked_add(digit as u64))
+            .ok_or(ShortnerError::Malformed)
+    })
+}
+
+/// Link ids in base 62 with digits `0-9`, `A-Z`, `a-z`, so codes may be
+/// computed by other systems. Codes reveal link ids.
+pub struct Base62;
+
+#[async_trait]
+impl Shortner for Base62 {
+    async fn decode<'a>(&self, url: &'a str) -> Result<u64, ShortnerError> {
+        decode_base(url, BASE62_ALPHABET)
+    }
+
+    async fn encode(&self, id: u64) -> Result<String, ShortnerError> {
+        Ok(encode_base(id, BASE62_ALPHABET))
+    }
+}
+
+/// Link ids in base 58 with the Bitcoin alphabet, which leaves out the
+/// look-alike `0`, `O`, `I` and `l`. Codes reveal link ids.
+pub struct Base58;
+
+#[async_trait]
+impl Shortner for Base58 {
+    async fn decode<'a>(&self, url: &'a str) -> Result<u64, ShortnerError> {
+        decode_base(url, BASE58_ALPHABET)
+    }
+
+    async fn encode(&self, id: u64) -> Result<String, ShortnerError> {
+        Ok(encode_base(id, BASE58_ALPHABET))
+    }
+}
+
+/// Shorteners replacing each other, e.g. after a salt leak. Codes are made
+/// by the current one and decoded by the first one accepting them, current
+/// first; the generation of a code is the position of that shortener, `0`
+/// being current.
+///
+/// Newer shorteners must reject codes of older ones, as hashids with
+/// different salts do, otherwise old codes lead to other links. Configured
+/// rotations are checked with [`validate_rotation`].
+pub struct Rotation {
+    shorteners: Vec<Box<dyn Shortner + Send + Sync>>,
+}
+
+impl Rotation {
+    /// `retired` are ordered from the newest to the oldest.
+    pub fn new(
+        current: Box<dyn Shortner + Send + Sync>,
+        retired: Vec<Box<dyn Shortner + Send + Sync>>,
+    ) -> Self {
+        let mut shorteners = vec![current];
+        shorteners.extend(retired);
+        Self { shorteners }
+    }
+}
+
+/// Reject rotations in which a newer shortener may accept codes of an older
+/// one. Base62 and Base58 read almost any code as an id, random codes share
+/// one mapping and hashids only reject codes made with other parameters, so
+/// only hashids with distinct parameters may be rotated.
+pub fn validate_rotation(
+    current: &settings::Shortener,
+    retired: &[settings::Shortener],
+) -> Result<(), ShortnerError> {
+    let mut seen: Vec<&settings::HashIdsShortener> = Vec::new();
+    for shortener in std::iter::once(current).chain(retired) {
+        let settings::Shortener::HashIds(config) = shortener else {
+            return Err(ShortnerError::Rotation(
+                "only hashids shorteners can be rotated",
+            ));
+        };
+        if seen.contains(&config) {
+            return Err(ShortnerError::Rotation(
+                "rotated hashids shorteners must differ in salt, alphabet, length or separators",
+            ));
+        }
+        seen.push(config);
+    }
+    Ok(())
+}
+
+#[async_trait]
+impl Shortner for Rotation {
+    async fn decode<'a>(&self, url: &'a str) -> Result<u64, ShortnerError> {
+        Ok(self.decode_generation(url).await?.0)
+    }
+
+    async fn encode(&self, id: u64) -> Result<String, ShortnerError> {
+        self.shorteners[0].encode(id).await
+    }
+
+    async fn assign(&self, id: u64) -> Result<String, ShortnerError> {
+        self.shorteners[0].assign(id).await
+    }
+
+    /// Storage failures are returned at once, otherwise the error of the
+    /// current shortener is returned when none accepts the code.
+    async fn decode_generation<'a>(&self, url: &'a str) -> Result<(u64, usize), ShortnerError> {
+        let mut rejection = None;
+        for (generation, shortner) in self.shorteners.iter().enumerate() {
+            match shortner.decode(url).await {
+                Ok(id) => return Ok((id, generation)),
+                Err(ShortnerError::Backend(error)) if !matches!(error, BackendError::NotFound) => {
+                    return Err(error.into())
+                }
+                Err(error) => {
+                    rejection.get_or_insert(error);
+                }
+            }
+        }
+        Err(rejection.unwrap_or(ShortnerError::Malformed))
+    }
+}
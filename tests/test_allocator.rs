@@ -0,0 +1,105 @@
+use anyhow::Result;
+use chrono::{Duration, Utc};
+use shortland::backend::{
+    allocator::{AllocatedBackend, AllocatorError, Block, IdAllocator, Snowflake},
+    memory::InMemoryBackend,
+    Backend, BackendError,
+};
+
+#[tokio::test]
+async fn test_block_allocator() -> Result<()> {
+    let backend = InMemoryBackend::new();
+    let block = Block::new(10)?;
+    assert_eq!(block.allocate(&backend).await?, 1);
+    assert_eq!(block.allocate(&backend).await?, 2);
+    // The rest of the block is reserved in the backend
+    assert_eq!(backend.store("http://example.com").await?, 11);
+    let ids = block.allocate_many(&backend, 15).await?;
+    assert_eq!(ids[..8], [3, 4, 5, 6, 7, 8, 9, 10]);
+    assert_eq!(ids[8..], [12, 13, 14, 15, 16, 17, 18]);
+    assert_eq!(block.allocate(&backend).await?, 19);
+    Ok(())
+}
+
+#[test]
+fn test_block_size_validation() {
+    assert!(matches!(
+        Block::new(0),
+        Err(AllocatorError::InvalidBlockSize)
+    ));
+}
+
+#[tokio::test]
+async fn test_snowflake_allocator() -> Result<()> {
+    let backend = InMemoryBackend::new();
+    let snowflake = Snowflake::new(513)?;
+    let ids = snowflake.allocate_many(&backend, 10000).await?;
+    assert!(ids.windows(2).all(|pair| pair[0] < pair[1]));
+    assert!(ids.iter().all(|id| (id >> 12) & 1023 == 513));
+    let other = Snowflake::new(514)?.allocate(&backend).await?;
+    assert!(!ids.contains(&other));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_snowflake_continues_after_stored_ids() -> Result<()> {
+    let snowflake_id = |ahead: i64| {
+        let millis =
+            (Utc::now() + Duration::milliseconds(ahead)).timestamp_millis() - 1_704_067_200_000;
+        (millis as u64) << 22 | 513 << 12
+    };
+    // Ids of a run whose clock was ahead
+    let backend = InMemoryBackend::new();
+    let stored = snowflake_id(200);
+    backend.store_with_id(stored, "http://example.com").await?;
+    assert!(Snowflake::new(513)?.allocate(&backend).await? > stored);
+
+    let backend = InMemoryBackend::new();
+    backend
+        .store_with_id(snowflake_id(3_600_000), "http://example.com")
+        .await?;
+    assert!(matches!(
+        Snowflake::new(513)?.allocate(&backend).await,
+        Err(BackendError::Internal(_))
+    ));
+    Ok(())
+}
+
+#[test]
+fn test_snowflake_node_validation() {
+    assert!(Snowflake::new(1023).is_ok());
+    assert!(matches!(
+        Snowflake::new(1024),
+        Err(AllocatorError::InvalidNode(1024))
+    ));
+}
+
+#[tokio::test]
+async fn test_allocated_backend() -> Result<()> {
+    let backend = AllocatedBackend::new(InMemoryBackend::new(), Snowflake::new(7)?);
+    let id = backend.store("http://example.com").await?;
+    assert_eq!((id >> 12) & 1023, 7);
+    assert_eq!(backend.retrive(id).await?, "http://example.com");
+
+    let urls = vec![
+        "http://example.com/1".to_owned(),
+        "http://example.org/2".to_owned(),
+    ];
+    let ids = backend
+        .store_many(&urls)
+        .await?
+        .into_iter()
+        .collect::<Result<Vec<_>, _>>()?;
+    assert!(ids.iter().all(|stored| *stored > id));
+    assert_eq!(backend.retrive(ids[0]).await?, urls[0]);
+    assert_eq!(backend.retrive(ids[1]).await?, urls[1]);
+    assert_eq!(
+        backend.list(None, 10).await?,
+        [
+            (id, "http://example.com".to_owned()),
+            (ids[0], urls[0].clone()),
+            (ids[1], urls[1].clone())
+        ]
+    );
+    Ok(())
+}
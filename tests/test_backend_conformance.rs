@@ -0,0 +1,134 @@
+use uuid::Uuid;
+
+fn temporary_path(extension: &str) -> String {
+    std::env::temp_dir()
+        .join(format!("shortland-{}.{}", Uuid::new_v4(), extension))
+        .to_string_lossy()
+        .into_owned()
+}
+
+mod memory {
+    use shortland::backend::memory::InMemoryBackend;
+
+    shortland::backend_conformance!(async { InMemoryBackend::new() });
+}
+
+mod persistent_memory {
+    use std::time::Duration;
+
+    use shortland::backend::memory::InMemoryBackend;
+
+    use super::temporary_path;
+
+    shortland::backend_conformance!(async {
+        InMemoryBackend::with_persistence(
+            temporary_path("journal"),
+            temporary_path("snapshot"),
+            Duration::ZERO,
+        )
+        .await
+        .unwrap()
+    });
+}
+
+mod sqlite {
+    use shortland::backend::sqlite::SqliteBackend;
+
+    use super::temporary_path;
+
+    shortland::backend_conformance!(async {
+        SqliteBackend::new(&temporary_path("db")).await.unwrap()
+    });
+}
+
+mod redb {
+    use shortland::backend::redb::RedbBackend;
+
+    use super::temporary_path;
+
+    shortland::backend_conformance!(async {
+        RedbBackend::new(&temporary_path("redb")).await.unwrap()
+    });
+}
+
+mod postgres {
+    use shortland::backend::postgres::PostgresBackend;
+
+    shortland::backend_conformance!(
+        #[ignore = "requires local PostgreSQL"]
+        async {
+            let connection = std::env::var("SL_TEST_POSTGRES")
+                .unwrap_or_else(|_| "postgres://postgres@localhost:5432/shortland_test".to_owned());
+            PostgresBackend::new(connection.as_str(), 2).await.unwrap()
+        }
+    );
+}
+
+mod redis {
+    use shortland::backend::redis::RedisBackend;
+
+    shortland::backend_conformance!(
+        #[ignore = "requires local Redis"]
+        async {
+            RedisBackend::new("redis://localhost:6379/2").await.unwrap()
+        }
+    );
+}
+
+mod prefixed_redis {
+    use shortland::backend::redis::RedisBackend;
+
+    shortland::backend_conformance!(
+        #[ignore = "requires local Redis"]
+        async {
+            RedisBackend::new("redis://localhost:6379/2")
+                .await
+                .unwrap()
+                .with_prefix("shortland:")
+        }
+    );
+}
+
+mod redis_cluster {
+    use shortland::backend::redis::RedisBackend;
+
+    shortland::backend_conformance!(
+        #[ignore = "requires local Redis Cluster"]
+        async {
+            let nodes = std::env::var("SL_TEST_REDIS_CLUSTER")
+                .unwrap_or_else(|_| "redis://127.0.0.1:7000".to_owned());
+            RedisBackend::cluster(nodes.split(',').collect())
+                .await
+                .unwrap()
+                .with_prefix("shortland:")
+        }
+    );
+}
+
+mod sharded_redis {
+    use shortland::backend::sharded::ShardedRedisBackend;
+
+    shortland::backend_conformance!(
+        #[ignore = "requires local Redis"]
+        async {
+            ShardedRedisBackend::new(vec![
+                "redis://localhost:6379/3",
+                "redis://localhost:6379/4",
+                "redis://localhost:6379/5",
+            ])
+            .await
+            .unwrap()
+        }
+    );
+}
+
+mod counter_allocated_memory {
+    use shortland::backend::{
+        allocator::{AllocatedBackend, Counter},
+        memory::InMemoryBackend,
+    };
+
+    shortland::backend_conformance!(async {
+        AllocatedBackend::new(InMemoryBackend::new(), Counter)
+    });
+}
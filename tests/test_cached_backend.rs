@@ -0,0 +1,226 @@
+use std::{
+    num::NonZeroUsize,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use shortland::backend::{
+    cached::CachedBackend, memory::InMemoryBackend, sqlite::SqliteBackend, Backend, BackendError,
+    Clicks, Link, Metadata, Revision,
+};
+use tokio::sync::Notify;
+use uuid::Uuid;
+
+async fn test_backend(ttl: Duration) -> Result<CachedBackend<SqliteBackend>> {
+    let path = std::env::temp_dir().join(format!("shortland-{}.db", Uuid::new_v4()));
+    let backend = SqliteBackend::new(path.to_string_lossy().as_ref()).await?;
+    Ok(CachedBackend::new(
+        backend,
+        NonZeroUsize::new(16).unwrap(),
+        ttl,
+    ))
+}
+
+#[tokio::test]
+async fn test_cache_records_clicks() -> Result<()> {
+    let backend = test_backend(Duration::from_secs(60)).await?;
+    let id = backend.store("http://example.com").await?;
+    for _ in 0..3 {
+        assert_eq!(backend.retrive(id).await?, "http://example.com");
+    }
+    for _ in 0..50 {
+        if backend.stat(id, None).await? == 3 {
+            return Ok(());
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    panic!("Clicks of cached retrives are not recorded");
+}
+
+#[tokio::test]
+async fn test_cache_invalidation() -> Result<()> {
+    let backend = test_backend(Duration::from_secs(60)).await?;
+    let id = backend.store("http://example.com").await?;
+    backend.retrive(id).await?;
+    backend.update(id, "http://example.org").await?;
+    assert_eq!(backend.retrive(id).await?, "http://example.org");
+    backend.delete(id).await?;
+    assert!(matches!(
+        backend.retrive(id).await,
+        Err(BackendError::Deleted)
+    ));
+    Ok(())
+}
+
+/// Pauses the first armed lookup after it has read the wrapped backend.
+struct StalledBackend {
+    backend: InMemoryBackend,
+    armed: AtomicBool,
+    read: Arc<Notify>,
+    resume: Arc<Notify>,
+}
+
+#[async_trait]
+impl Backend for StalledBackend {
+    async fn store<'a>(&self, url: &'a str) -> Result<u64, BackendError> {
+        self.backend.store(url).await
+    }
+
+    async fn allocate_ids(&self, count: u64) -> Result<Vec<u64>, BackendError> {
+        self.backend.allocate_ids(count).await
+    }
+
+    async fn reserve_until(&self, id: u64) -> Result<(), BackendError> {
+        self.backend.reserve_until(id).await
+    }
+
+    async fn store_with_id<'a>(&self, id: u64, url: &'a str) -> Result<(), BackendError> {
+        self.backend.store_with_id(id, url).await
+    }
+
+    async fn retrive_versioned(&self, id: u64) -> Result<(String, u64), BackendError> {
+        let link = self.backend.retrive_versioned(id).await;
+        if self.armed.swap(false, Ordering::SeqCst) {
+            self.read.notify_one();
+            self.resume.notified().await;
+        }
+        link
+    }
+
+    async fn stat(&self, id: u64, since: Option<DateTime<Utc>>) -> Result<u64, BackendError> {
+        self.backend.stat(id, since).await
+    }
+
+    async fn update_versioned<'a>(
+        &self,
+        id: u64,
+        url: &'a str,
+        expected: Option<u64>,
+    ) -> Result<u64, BackendError> {
+        self.backend.update_versioned(id, url, expected).await
+    }
+
+    async fn delete(&self, id: u64) -> Result<(), BackendError> {
+        self.backend.delete(id).await
+    }
+
+    async fn undelete(&self, id: u64) -> Result<(), BackendError> {
+        self.backend.undelete(id).await
+    }
+
+    async fn purge(&self, before: DateTime<Utc>) -> Result<u64, BackendError> {
+        self.backend.purge(before).await
+    }
+
+    async fn info(&self, id: u64) -> Result<Link, BackendError> {
+        self.backend.info(id).await
+    }
+
+    async fn set_metadata<'a>(&self, id: u64, metadata: &'a Metadata) -> Result<(), BackendError> {
+        self.backend.set_metadata(id, metadata).await
+    }
+
+    async fn history(&self, id: u64) -> Result<Vec<Revision>, BackendError> {
+        self.backend.history(id).await
+    }
+
+    async fn store_alias<'a>(&self, alias: &'a str, id: u64) -> Result<(), BackendError> {
+        self.backend.store_alias(alias, id).await
+    }
+
+    async fn store_with_alias<'a>(
+        &self,
+        id: u64,
+        url: &'a str,
+        alias: &'a str,
+    ) -> Result<(), BackendError> {
+        self.backend.store_with_alias(id, url, alias).await
+    }
+
+    async fn resolve_alias<'a>(&self, alias: &'a str) -> Result<u64, BackendError> {
+        self.backend.resolve_alias(alias).await
+    }
+
+    async fn aliases(&self, id: u64) -> Result<Vec<String>, BackendError> {
+        self.backend.aliases(id).await
+    }
+
+    async fn store_code<'a>(&self, id: u64, code: &'a str) -> Result<String, BackendError> {
+        self.backend.store_code(id, code).await
+    }
+
+    async fn resolve_code<'a>(&self, code: &'a str) -> Result<u64, BackendError> {
+        self.backend.resolve_code(code).await
+    }
+
+    async fn code(&self, id: u64) -> Result<String, BackendError> {
+        self.backend.code(id).await
+    }
+
+    async fn find<'a>(&self, url: &'a str) -> Result<Vec<u64>, BackendError> {
+        self.backend.find(url).await
+    }
+
+    async fn list(
+        &self,
+        after: Option<u64>,
+        limit: usize,
+    ) -> Result<Vec<(u64, String)>, BackendError> {
+        self.backend.list(after, limit).await
+    }
+
+    async fn list_with_tombstones(
+        &self,
+        after: Option<u64>,
+        limit: usize,
+    ) -> Result<Vec<(u64, String)>, BackendError> {
+        self.backend.list_with_tombstones(after, limit).await
+    }
+
+    async fn clicks(&self, id: u64) -> Result<Clicks, BackendError> {
+        self.backend.clicks(id).await
+    }
+
+    async fn restore<'a>(
+        &self,
+        id: u64,
+        link: &'a Link,
+        history: &'a [Revision],
+        clicks: &'a [(DateTime<Utc>, u64)],
+    ) -> Result<(), BackendError> {
+        self.backend.restore(id, link, history, clicks).await
+    }
+}
+
+#[tokio::test]
+async fn test_cache_miss_racing_update() -> Result<()> {
+    let read = Arc::new(Notify::new());
+    let resume = Arc::new(Notify::new());
+    let backend = Arc::new(CachedBackend::new(
+        StalledBackend {
+            backend: InMemoryBackend::new(),
+            armed: AtomicBool::new(true),
+            read: read.clone(),
+            resume: resume.clone(),
+        },
+        NonZeroUsize::new(16).unwrap(),
+        Duration::from_secs(60),
+    ));
+    let id = backend.store("http://example.com").await?;
+
+    let stalled = backend.clone();
+    let lookup = tokio::spawn(async move { stalled.retrive(id).await });
+    read.notified().await;
+    backend.update(id, "http://example.org").await?;
+    resume.notify_one();
+    assert_eq!(lookup.await??, "http://example.com");
+
+    assert_eq!(backend.retrive(id).await?, "http://example.org");
+    Ok(())
+}
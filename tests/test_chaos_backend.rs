@@ -0,0 +1,50 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use shortland::backend::{
+    chaos::{ChaosBackend, ChaosError, Fault, Operation},
+    memory::InMemoryBackend,
+    Backend, BackendError,
+};
+
+#[tokio::test(start_paused = true)]
+async fn test_injected_timeout() -> Result<()> {
+    let backend = ChaosBackend::new(InMemoryBackend::new())
+        .with_timeout(Duration::from_secs(5))
+        .with_fault(
+            Operation::Retrive,
+            Fault {
+                timeout_rate: 1.0,
+                ..Fault::default()
+            },
+        );
+    let id = backend.store("http://example.com").await?;
+    let started = tokio::time::Instant::now();
+    match backend.retrive(id).await {
+        Err(BackendError::Internal(error)) => assert!(matches!(
+            error.downcast_ref::<ChaosError>(),
+            Some(ChaosError::Timeout(Operation::Retrive))
+        )),
+        other => panic!("Expected injected timeout, got {:?}", other),
+    }
+    assert!(started.elapsed() >= Duration::from_secs(5));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_injected_failure() -> Result<()> {
+    let backend = ChaosBackend::new(InMemoryBackend::new()).with_fault(
+        Operation::Update,
+        Fault {
+            failure_rate: 1.0,
+            ..Fault::default()
+        },
+    );
+    let id = backend.store("http://example.com").await?;
+    assert!(matches!(
+        backend.update(id, "http://example.org").await,
+        Err(BackendError::Internal(_))
+    ));
+    assert_eq!(backend.retrive(id).await?, "http://example.com");
+    Ok(())
+}
@@ -3,11 +3,16 @@ use std::borrow::BorrowMut;
 use anyhow::Result;
 use axum::{
     body::Body,
-    http::{Method, Request, StatusCode},
+    http::{
+        header::{CONTENT_TYPE, ETAG, IF_MATCH},
+        Method, Request, StatusCode,
+    },
 };
 use shortland::{
+    handlers::{BatchEntry, ShortenHistory, ShortenInfo, ShortenPage},
     service::application,
-    settings::{Backend, Config},
+    settings::{Backend, Config, Deduplication, HashIdsShortener, RandomShortener, Shortener},
+    shortener::{HashIds, Shortner},
 };
 use tower::ServiceExt;
 
@@ -50,3 +55,574 @@ async fn test_invalid_uri_create_shorten() -> Result<()> {
     assert_eq!(response.status(), StatusCode::BAD_REQUEST);
     Ok(())
 }
+
+#[tokio::test]
+async fn test_backend_failure_create_shorten() -> Result<()> {
+    let mut config = test_config();
+    config.chaos.enabled = true;
+    config.chaos.store.failure_rate = 1.0;
+    let app = application(&config).await?;
+    let response = app
+        .oneshot(
+            Request::builder()
+                .uri("/urls")
+                .method(Method::POST)
+                .body(Body::from("http://example.com"))?,
+        )
+        .await?;
+    assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_backend_failure_list_shortens() -> Result<()> {
+    let mut config = test_config();
+    config.chaos.enabled = true;
+    config.chaos.list.failure_rate = 1.0;
+    let app = application(&config).await?;
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .uri("/urls")
+                .method(Method::POST)
+                .body(Body::from("http://example.com"))?,
+        )
+        .await?;
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let response = app
+        .oneshot(Request::builder().uri("/urls").body(Body::empty())?)
+        .await?;
+    assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_list_shortens() -> Result<()> {
+    let config = test_config();
+    let app = application(&config).await?;
+    for url in [
+        "http://example.com/1",
+        "http://example.com/2",
+        "http://example.com/3",
+    ] {
+        app.clone()
+            .oneshot(
+                Request::builder()
+                    .uri("/urls")
+                    .method(Method::POST)
+                    .body(Body::from(url))?,
+            )
+            .await?;
+    }
+    let list = |uri: String| {
+        let app = app.clone();
+        async move {
+            let response = app
+                .oneshot(Request::builder().uri(uri).body(Body::empty())?)
+                .await?;
+            assert_eq!(response.status(), StatusCode::OK);
+            let body = hyper::body::to_bytes(response.into_body()).await?;
+            Ok::<ShortenPage, anyhow::Error>(serde_json::from_slice(&body)?)
+        }
+    };
+    let first = list("/urls?limit=2".to_owned()).await?;
+    assert_eq!(first.urls.len(), 2);
+    assert_eq!(first.urls[0].url, "http://example.com/1");
+    let cursor = first.next.expect("cursor of the second page");
+    let second = list(format!("/urls?limit=2&cursor={}", cursor)).await?;
+    assert_eq!(second.urls.len(), 1);
+    assert_eq!(second.urls[0].url, "http://example.com/3");
+    assert!(second.next.is_none());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_create_shorten_batch() -> Result<()> {
+    let config = test_config();
+    let app = application(&config).await?;
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .uri("/urls/batch")
+                .method(Method::POST)
+                .body(Body::from(
+                    "http://example.com/1\n\\\n\nhttp://example.com/2\n",
+                ))?,
+        )
+        .await?;
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = hyper::body::to_bytes(response.into_body()).await?;
+    let entries: Vec<BatchEntry> = serde_json::from_slice(&body)?;
+    assert_eq!(entries.len(), 3);
+    assert!(entries[0].shorten.is_some());
+    assert!(entries[1].shorten.is_none() && entries[1].error.is_some());
+    assert!(entries[2].shorten.is_some());
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .uri("/urls/batch")
+                .method(Method::POST)
+                .header("Content-Type", "application/json")
+                .body(Body::from(r#"["http://example.com/3"]"#))?,
+        )
+        .await?;
+    let body = hyper::body::to_bytes(response.into_body()).await?;
+    let entries: Vec<BatchEntry> = serde_json::from_slice(&body)?;
+    assert_eq!(entries[0].url, "http://example.com/3");
+    assert!(entries[0].shorten.is_some());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_update_shorten_if_match() -> Result<()> {
+    let config = test_config();
+    let app = application(&config).await?;
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .uri("/urls")
+                .method(Method::POST)
+                .body(Body::from("http://example.com"))?,
+        )
+        .await?;
+    let shorten = String::from_utf8(hyper::body::to_bytes(response.into_body()).await?.to_vec())?;
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .uri(format!("/urls/{}", shorten))
+                .body(Body::empty())?,
+        )
+        .await?;
+    assert_eq!(response.headers()[ETAG], "\"1\"");
+
+    let update = |version: &'static str| {
+        app.clone().oneshot(
+            Request::builder()
+                .uri(format!("/urls/{}", shorten))
+                .method(Method::PUT)
+                .header(IF_MATCH, version)
+                .body(Body::from("http://example.org"))
+                .unwrap(),
+        )
+    };
+    let response = update("\"1\"").await?;
+    assert_eq!(response.status(), StatusCode::CREATED);
+    assert_eq!(response.headers()[ETAG], "\"2\"");
+    let response = update("\"1\"").await?;
+    assert_eq!(response.status(), StatusCode::PRECONDITION_FAILED);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_shorten_info() -> Result<()> {
+    let config = test_config();
+    let app = application(&config).await?;
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .uri("/urls")
+                .method(Method::POST)
+                .body(Body::from("http://example.com"))?,
+        )
+        .await?;
+    let shorten = String::from_utf8(hyper::body::to_bytes(response.into_body()).await?.to_vec())?;
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .uri(format!("/urls/{}/info", shorten))
+                .method(Method::PUT)
+                .header(CONTENT_TYPE, "application/json")
+                .body(Body::from(
+                    r#"{"title": "Example", "owner": "marketing", "flags": ["pinned"]}"#,
+                ))?,
+        )
+        .await?;
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .uri(format!("/urls/{}/info", shorten))
+                .body(Body::empty())?,
+        )
+        .await?;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[ETAG], "\"1\"");
+    let info: ShortenInfo =
+        serde_json::from_slice(&hyper::body::to_bytes(response.into_body()).await?)?;
+    assert_eq!(info.shorten, shorten);
+    assert_eq!(info.link.url, "http://example.com/");
+    assert_eq!(info.link.metadata.title.as_deref(), Some("Example"));
+    assert_eq!(info.link.metadata.owner.as_deref(), Some("marketing"));
+    assert!(info.link.metadata.flags.contains("pinned"));
+    assert!(info.link.created_at.is_some());
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .uri(format!("/urls/{}/stats", shorten))
+                .body(Body::empty())?,
+        )
+        .await?;
+    assert_eq!(hyper::body::to_bytes(response.into_body()).await?, "0");
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_delete_and_restore_shorten() -> Result<()> {
+    let config = test_config();
+    let app = application(&config).await?;
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .uri("/urls")
+                .method(Method::POST)
+                .body(Body::from("http://example.com"))?,
+        )
+        .await?;
+    let shorten = String::from_utf8(hyper::body::to_bytes(response.into_body()).await?.to_vec())?;
+    let request = |method: Method, path: &str| {
+        app.clone().oneshot(
+            Request::builder()
+                .uri(format!("/urls/{}{}", shorten, path))
+                .method(method)
+                .body(Body::empty())
+                .unwrap(),
+        )
+    };
+    request(Method::DELETE, "").await?;
+
+    let response = request(Method::GET, "").await?;
+    assert_eq!(response.status(), StatusCode::GONE);
+    assert!(response.headers()[CONTENT_TYPE]
+        .to_str()?
+        .starts_with("text/html"));
+    let page = hyper::body::to_bytes(response.into_body()).await?;
+    assert!(String::from_utf8(page.to_vec())?.contains("deleted"));
+
+    let response = request(Method::POST, "/restore").await?;
+    assert_eq!(response.status(), StatusCode::OK);
+    let response = request(Method::GET, "").await?;
+    assert_eq!(response.status(), StatusCode::TEMPORARY_REDIRECT);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_shorten_history_and_rollback() -> Result<()> {
+    let config = test_config();
+    let app = application(&config).await?;
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .uri("/urls")
+                .method(Method::POST)
+                .body(Body::from("http://example.com"))?,
+        )
+        .await?;
+    let shorten = String::from_utf8(hyper::body::to_bytes(response.into_body()).await?.to_vec())?;
+    app.clone()
+        .oneshot(
+            Request::builder()
+                .uri(format!("/urls/{}", shorten))
+                .method(Method::PUT)
+                .body(Body::from("http://example.org"))?,
+        )
+        .await?;
+
+    let rollback = |version: &'static str| {
+        app.clone().oneshot(
+            Request::builder()
+                .uri(format!("/urls/{}/rollback/1", shorten))
+                .method(Method::POST)
+                .header(IF_MATCH, version)
+                .body(Body::empty())
+                .unwrap(),
+        )
+    };
+    let response = rollback("\"1\"").await?;
+    assert_eq!(response.status(), StatusCode::PRECONDITION_FAILED);
+    let response = rollback("\"2\"").await?;
+    assert_eq!(response.status(), StatusCode::CREATED);
+    assert_eq!(response.headers()[ETAG], "\"3\"");
+
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .uri(format!("/urls/{}/history", shorten))
+                .body(Body::empty())?,
+        )
+        .await?;
+    assert_eq!(response.status(), StatusCode::OK);
+    let history: ShortenHistory =
+        serde_json::from_slice(&hyper::body::to_bytes(response.into_body()).await?)?;
+    assert_eq!(history.shorten, shorten);
+    let destinations = history
+        .history
+        .iter()
+        .map(|revision| (revision.version, revision.url.as_str()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        destinations,
+        [
+            (1, "http://example.com/"),
+            (2, "http://example.org/"),
+            (3, "http://example.com/"),
+        ]
+    );
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .uri(format!("/urls/{}/rollback/9", shorten))
+                .method(Method::POST)
+                .body(Body::empty())?,
+        )
+        .await?;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_create_shorten_deduplication() -> Result<()> {
+    let mut config = test_config();
+    config.deduplication = Deduplication::Global;
+    let app = application(&config).await?;
+    let create = |uri: &'static str, url: &'static str| {
+        app.clone().oneshot(
+            Request::builder()
+                .uri(uri)
+                .method(Method::POST)
+                .body(Body::from(url))
+                .unwrap(),
+        )
+    };
+    let response = create("/urls", "http://example.com").await?;
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let shorten = hyper::body::to_bytes(response.into_body()).await?;
+    let response = create("/urls?owner=marketing", " http://example.com ").await?;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(hyper::body::to_bytes(response.into_body()).await?, shorten);
+    let response = create("/urls", "http://example.org").await?;
+    assert_eq!(response.status(), StatusCode::CREATED);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_create_shorten_deduplication_by_owner() -> Result<()> {
+    let mut config = test_config();
+    config.deduplication = Deduplication::Owner;
+    let app = application(&config).await?;
+    let create = |uri: &'static str| {
+        app.clone().oneshot(
+            Request::builder()
+                .uri(uri)
+                .method(Method::POST)
+                .body(Body::from("http://example.com"))
+                .unwrap(),
+        )
+    };
+    let response = create("/urls?owner=marketing").await?;
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let shorten = String::from_utf8(hyper::body::to_bytes(response.into_body()).await?.to_vec())?;
+    let response = create("/urls?owner=sales").await?;
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let response = create("/urls").await?;
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let response = create("/urls?owner=marketing").await?;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(hyper::body::to_bytes(response.into_body()).await?, shorten);
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .uri(format!("/urls/{}/info", shorten))
+                .body(Body::empty())?,
+        )
+        .await?;
+    let info: ShortenInfo =
+        serde_json::from_slice(&hyper::body::to_bytes(response.into_body()).await?)?;
+    assert_eq!(info.link.metadata.owner.as_deref(), Some("marketing"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_create_shorten_alias() -> Result<()> {
+    let mut config = test_config();
+    config.aliases.reserved = vec!["admin".to_owned()];
+    let app = application(&config).await?;
+    let request = |method: Method, uri: String, body: &'static str| {
+        app.clone().oneshot(
+            Request::builder()
+                .uri(uri)
+                .method(method)
+                .body(Body::from(body))
+                .unwrap(),
+        )
+    };
+    let response = request(
+        Method::POST,
+        "/urls?alias=spring-sale".to_owned(),
+        "http://example.com",
+    )
+    .await?;
+    assert_eq!(response.status(), StatusCode::CREATED);
+    assert_eq!(
+        hyper::body::to_bytes(response.into_body()).await?,
+        "spring-sale"
+    );
+
+    let response = request(Method::GET, "/urls/spring-sale".to_owned(), "").await?;
+    assert_eq!(response.status(), StatusCode::TEMPORARY_REDIRECT);
+    let response = request(Method::GET, "/urls/spring-sale/stats".to_owned(), "").await?;
+    assert_eq!(hyper::body::to_bytes(response.into_body()).await?, "1");
+    let response = request(Method::GET, "/urls/spring-sale/info".to_owned(), "").await?;
+    let info: ShortenInfo =
+        serde_json::from_slice(&hyper::body::to_bytes(response.into_body()).await?)?;
+    assert_eq!(info.aliases, ["spring-sale"]);
+    let response = request(Method::DELETE, "/urls/spring-sale".to_owned(), "").await?;
+    assert_eq!(response.status(), StatusCode::GONE);
+    let response = request(Method::GET, "/urls/spring-sale".to_owned(), "").await?;
+    assert_eq!(response.status(), StatusCode::GONE);
+
+    let response = request(Method::POST, "/urls".to_owned(), "http://example.org").await?;
+    let shorten = String::from_utf8(hyper::body::to_bytes(response.into_body()).await?.to_vec())?;
+    for (alias, status) in [
+        ("spring-sale", StatusCode::CONFLICT),
+        (shorten.as_str(), StatusCode::CONFLICT),
+        ("Batch", StatusCode::BAD_REQUEST),
+        ("ADMIN", StatusCode::BAD_REQUEST),
+        ("spring%20sale", StatusCode::BAD_REQUEST),
+    ] {
+        let response = request(
+            Method::POST,
+            format!("/urls?alias={}", alias),
+            "http://example.net",
+        )
+        .await?;
+        assert_eq!(response.status(), status, "alias {}", alias);
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_random_shortener() -> Result<()> {
+    let mut config = test_config();
+    config.shortener = Shortener::Random(RandomShortener {
+        length: 10,
+        ..Default::default()
+    });
+    let app = application(&config).await?;
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .uri("/urls")
+                .method(Method::POST)
+                .body(Body::from("http://example.com"))?,
+        )
+        .await?;
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let shorten = String::from_utf8(hyper::body::to_bytes(response.into_body()).await?.to_vec())?;
+    assert_eq!(shorten.len(), 10);
+
+    let expand = |shorten: &str| {
+        app.clone().oneshot(
+            Request::builder()
+                .uri(format!("/urls/{}", shorten))
+                .body(Body::empty())
+                .unwrap(),
+        )
+    };
+    let response = expand(&shorten).await?;
+    assert_eq!(response.status(), StatusCode::TEMPORARY_REDIRECT);
+    let response = expand("0123456789").await?;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    let response = expand("short").await?;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    // Listings show the code assigned on creation
+    let response = app
+        .oneshot(Request::builder().uri("/urls").body(Body::empty())?)
+        .await?;
+    let body = hyper::body::to_bytes(response.into_body()).await?;
+    assert!(String::from_utf8(body.to_vec())?.contains(&shorten));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_base58_shortener() -> Result<()> {
+    let mut config = test_config();
+    config.shortener = Shortener::Base58;
+    let app = application(&config).await?;
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .uri("/urls")
+                .method(Method::POST)
+                .body(Body::from("http://example.com"))?,
+        )
+        .await?;
+    assert_eq!(response.status(), StatusCode::CREATED);
+    assert_eq!(hyper::body::to_bytes(response.into_body()).await?, "2");
+    let response = app
+        .oneshot(Request::builder().uri("/urls/2").body(Body::empty())?)
+        .await?;
+    assert_eq!(response.status(), StatusCode::TEMPORARY_REDIRECT);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_retired_shortener() -> Result<()> {
+    let salted = |salt: &str| HashIdsShortener {
+        salt: Some(salt.to_owned()),
+        ..Default::default()
+    };
+    let mut config = test_config();
+    config.shortener = Shortener::HashIds(salted("current"));
+    config.retired_shorteners = vec![Shortener::HashIds(salted("leaked"))];
+    let app = application(&config).await?;
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .uri("/urls")
+                .method(Method::POST)
+                .body(Body::from("http://example.com"))?,
+        )
+        .await?;
+    let shorten = String::from_utf8(hyper::body::to_bytes(response.into_body()).await?.to_vec())?;
+    let current = HashIds::new(&salted("current"))?;
+    let id = current.decode(&shorten).await?;
+    let leaked = HashIds::new(&salted("leaked"))?.encode(id).await?;
+
+    let info = |shorten: String| {
+        app.clone().oneshot(
+            Request::builder()
+                .uri(format!("/urls/{}/info", shorten))
+                .body(Body::empty())
+                .unwrap(),
+        )
+    };
+    let response = info(leaked).await?;
+    assert_eq!(response.status(), StatusCode::OK);
+    let body: ShortenInfo =
+        serde_json::from_slice(&hyper::body::to_bytes(response.into_body()).await?)?;
+    assert_eq!(body.generation, Some(1));
+    let response = info(shorten).await?;
+    let body: ShortenInfo =
+        serde_json::from_slice(&hyper::body::to_bytes(response.into_body()).await?)?;
+    assert_eq!(body.generation, None);
+    Ok(())
+}
@@ -0,0 +1,147 @@
+use std::{sync::Arc, time::Duration};
+
+use anyhow::Result;
+use chrono::Utc;
+use shortland::backend::{memory::InMemoryBackend, Backend, BackendError};
+use uuid::Uuid;
+
+fn test_paths() -> (String, String) {
+    let prefix = std::env::temp_dir().join(format!("shortland-{}", Uuid::new_v4()));
+    let prefix = prefix.to_string_lossy();
+    (
+        format!("{}.journal", prefix),
+        format!("{}.snapshot", prefix),
+    )
+}
+
+#[tokio::test]
+async fn test_journal_replay() -> Result<()> {
+    let (journal, snapshot) = test_paths();
+    let interval = Duration::from_secs(3600);
+    let backend = InMemoryBackend::with_persistence(&journal, &snapshot, interval).await?;
+    let first = backend.store("http://example.com").await?;
+    let second = backend.store("http://example.org").await?;
+    backend.update(first, "http://example.net").await?;
+    backend.delete(second).await?;
+    drop(backend);
+
+    let backend = InMemoryBackend::with_persistence(&journal, &snapshot, interval).await?;
+    assert_eq!(
+        backend.retrive_versioned(first).await?,
+        ("http://example.net".to_owned(), 2)
+    );
+    assert!(matches!(
+        backend.retrive(second).await,
+        Err(BackendError::Deleted)
+    ));
+    let history = backend.history(first).await?;
+    assert_eq!(history.len(), 2);
+    assert_eq!(history[0].url, "http://example.com");
+    assert_eq!(history[1].url, "http://example.net");
+    let third = backend.store("http://example.com").await?;
+    assert!(third > second);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_snapshot_restore() -> Result<()> {
+    let (journal, snapshot) = test_paths();
+    let backend = InMemoryBackend::with_persistence(&journal, &snapshot, Duration::ZERO).await?;
+    let id = backend.store("http://example.com").await?;
+    backend.retrive(id).await?;
+    backend.snapshot().await?;
+    assert_eq!(std::fs::metadata(&journal)?.len(), 0);
+    drop(backend);
+
+    let backend =
+        InMemoryBackend::with_persistence(&journal, &snapshot, Duration::from_secs(3600)).await?;
+    assert_eq!(backend.retrive(id).await?, "http://example.com");
+    assert!(backend.stat(id, None).await? > 0);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_journal_replay_purge() -> Result<()> {
+    let (journal, snapshot) = test_paths();
+    let interval = Duration::from_secs(3600);
+    let backend = InMemoryBackend::with_persistence(&journal, &snapshot, interval).await?;
+    let restored = backend.store("http://example.com").await?;
+    let purged = backend.store("http://example.org").await?;
+    backend.delete(restored).await?;
+    backend.undelete(restored).await?;
+    backend.delete(purged).await?;
+    assert_eq!(backend.purge(Utc::now()).await?, 1);
+    drop(backend);
+
+    let backend = InMemoryBackend::with_persistence(&journal, &snapshot, interval).await?;
+    assert_eq!(backend.retrive(restored).await?, "http://example.com");
+    assert!(matches!(
+        backend.info(purged).await,
+        Err(BackendError::NotFound)
+    ));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_journal_replay_clicks() -> Result<()> {
+    let (journal, snapshot) = test_paths();
+    let interval = Duration::from_secs(3600);
+    let backend = InMemoryBackend::with_persistence(&journal, &snapshot, interval).await?;
+    let id = backend.store("http://example.com").await?;
+    backend.retrive(id).await?;
+    backend.click(id).await?;
+    drop(backend);
+
+    let backend = InMemoryBackend::with_persistence(&journal, &snapshot, interval).await?;
+    assert_eq!(backend.stat(id, None).await?, 2);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_journal_replay_alias() -> Result<()> {
+    let (journal, snapshot) = test_paths();
+    let interval = Duration::from_secs(3600);
+    let backend = InMemoryBackend::with_persistence(&journal, &snapshot, interval).await?;
+    let id = backend.allocate_ids(1).await?[0];
+    backend
+        .store_with_alias(id, "http://example.com", "spring-sale")
+        .await?;
+    drop(backend);
+
+    // The link and its alias are a single journal entry
+    assert_eq!(std::fs::read_to_string(&journal)?.lines().count(), 2);
+    let backend = InMemoryBackend::with_persistence(&journal, &snapshot, interval).await?;
+    assert_eq!(backend.resolve_alias("spring-sale").await?, id);
+    assert_eq!(backend.retrive(id).await?, "http://example.com");
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_journal_replay_concurrent_writes() -> Result<()> {
+    let (journal, snapshot) = test_paths();
+    let interval = Duration::from_secs(3600);
+    let backend = Arc::new(InMemoryBackend::with_persistence(&journal, &snapshot, interval).await?);
+    let writes = (0..32)
+        .map(|index| {
+            let backend = backend.clone();
+            tokio::spawn(async move {
+                let id = backend
+                    .store(&format!("http://example.com/{}", index))
+                    .await?;
+                backend.click(id).await?;
+                Ok::<_, BackendError>(id)
+            })
+        })
+        .collect::<Vec<_>>();
+    let mut ids = Vec::new();
+    for write in writes {
+        ids.push(write.await??);
+    }
+    drop(backend);
+
+    let backend = InMemoryBackend::with_persistence(&journal, &snapshot, interval).await?;
+    for id in ids {
+        assert_eq!(backend.stat(id, None).await?, 1);
+    }
+    Ok(())
+}
@@ -0,0 +1,121 @@
+use anyhow::Result;
+use chrono::Utc;
+use shortland::{
+    backend::{memory::InMemoryBackend, sqlite::SqliteBackend, Backend, BackendError},
+    migration::{migrate, verify},
+};
+use uuid::Uuid;
+
+#[tokio::test]
+async fn test_migrate_preserves_ids_and_clicks() -> Result<()> {
+    let source = InMemoryBackend::new();
+    let first = source.store("http://example.com/1").await?;
+    let second = source.store("http://example.com/2").await?;
+    let third = source.store("http://example.com/3").await?;
+    source.delete(second).await?;
+    source.retrive(third).await?;
+    source.retrive(third).await?;
+
+    let path = std::env::temp_dir().join(format!("shortland-{}.db", Uuid::new_v4()));
+    let target = SqliteBackend::new(&path.to_string_lossy()).await?;
+    let report = migrate(&source, &target, 1, None).await?;
+    assert_eq!((report.links, report.clicks), (3, 2));
+
+    assert_eq!(target.retrive(first).await?, "http://example.com/1");
+    assert!(matches!(
+        target.retrive(second).await,
+        Err(BackendError::Deleted)
+    ));
+    target.undelete(second).await?;
+    assert_eq!(target.retrive(second).await?, "http://example.com/2");
+    assert_eq!(target.stat(third, None).await?, 2);
+    assert!(target.store("http://example.com/4").await? > third);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_migrate_skips_ids_of_purged_links() -> Result<()> {
+    let source = InMemoryBackend::new();
+    source.store("http://example.com/1").await?;
+    let purged = source.store("http://example.com/2").await?;
+    source.delete(purged).await?;
+    source.purge(Utc::now()).await?;
+
+    let target = InMemoryBackend::new();
+    migrate(&source, &target, 10, None).await?;
+    assert!(target.store("http://example.com/3").await? > purged);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_migrate_resumes_from_checkpoint() -> Result<()> {
+    let source = InMemoryBackend::new();
+    let first = source.store("http://example.com/1").await?;
+    let second = source.store("http://example.com/2").await?;
+    let checkpoint = std::env::temp_dir().join(format!("shortland-{}.checkpoint", Uuid::new_v4()));
+    let target = InMemoryBackend::new();
+    migrate(&source, &target, 10, Some(&checkpoint)).await?;
+
+    let third = source.store("http://example.com/3").await?;
+    let target = InMemoryBackend::new();
+    let report = migrate(&source, &target, 10, Some(&checkpoint)).await?;
+    assert_eq!(report.resumed_after, Some(second));
+    assert_eq!(report.links, 1);
+    assert_eq!(target.retrive(third).await?, "http://example.com/3");
+    assert!(target.retrive(first).await.is_err());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_verify_reports_differences() -> Result<()> {
+    let source = InMemoryBackend::new();
+    let first = source.store("http://example.com/1").await?;
+    let second = source.store("http://example.com/2").await?;
+    let target = InMemoryBackend::new();
+    migrate(&source, &target, 1, None).await?;
+    assert!(verify(&source, &target, 1).await?.is_consistent());
+
+    target.delete(first).await?;
+    target.update(second, "http://example.org/").await?;
+    let report = verify(&source, &target, 1).await?;
+    assert_eq!(report.checked, 2);
+    assert_eq!(report.mismatched, vec![first, second]);
+
+    target.purge(Utc::now()).await?;
+    let report = verify(&source, &target, 1).await?;
+    assert_eq!(report.missing, vec![first]);
+    assert_eq!(report.mismatched, vec![second]);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_migrate_copies_aliases() -> Result<()> {
+    let source = InMemoryBackend::new();
+    let id = source.store("http://example.com").await?;
+    source.store_alias("spring-sale", id).await?;
+    let target = InMemoryBackend::new();
+    let report = migrate(&source, &target, 10, None).await?;
+    assert_eq!(report.aliases, 1);
+    assert_eq!(target.resolve_alias("spring-sale").await?, id);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_migrate_copies_history() -> Result<()> {
+    let source = InMemoryBackend::new();
+    let id = source.store("http://example.com/1").await?;
+    source.update(id, "http://example.com/2").await?;
+    source.update(id, "http://example.com/3").await?;
+
+    let path = std::env::temp_dir().join(format!("shortland-{}.db", Uuid::new_v4()));
+    let target = SqliteBackend::new(&path.to_string_lossy()).await?;
+    migrate(&source, &target, 10, None).await?;
+    assert_eq!(target.history(id).await?, source.history(id).await?);
+    assert!(verify(&source, &target, 10).await?.is_consistent());
+
+    let link = target.info(id).await?;
+    target.restore(id, &link, &[], &[]).await?;
+    let report = verify(&source, &target, 10).await?;
+    assert_eq!(report.mismatched, vec![id]);
+    Ok(())
+}
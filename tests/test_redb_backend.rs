@@ -0,0 +1,16 @@
+use anyhow::Result;
+use shortland::backend::{redb::RedbBackend, Backend};
+use uuid::Uuid;
+
+#[tokio::test]
+async fn test_persistence() -> Result<()> {
+    let path = std::env::temp_dir().join(format!("shortland-{}.redb", Uuid::new_v4()));
+    let path = path.to_string_lossy();
+    let id = RedbBackend::new(path.as_ref())
+        .await?
+        .store("http://example.com")
+        .await?;
+    let backend = RedbBackend::new(path.as_ref()).await?;
+    assert_eq!(backend.retrive(id).await?, "http://example.com");
+    Ok(())
+}
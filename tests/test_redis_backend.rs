@@ -0,0 +1,190 @@
+use anyhow::Result;
+use shortland::backend::{
+    redis::RedisBackend, sharded::ShardedRedisBackend, Backend, BackendError,
+};
+
+async fn exercise(backend: &RedisBackend) -> Result<()> {
+    let id = backend.store("http://example.com").await?;
+    assert_eq!(backend.retrive(id).await?, "http://example.com");
+    assert_eq!(backend.stat(id, None).await?, 1);
+    backend.update(id, "http://example.org").await?;
+    assert_eq!(backend.retrive(id).await?, "http://example.org");
+    backend.delete(id).await?;
+    assert!(matches!(
+        backend.retrive(id).await,
+        Err(BackendError::Deleted)
+    ));
+    Ok(())
+}
+
+#[tokio::test]
+#[ignore = "requires local Redis Cluster"]
+async fn test_cluster() -> Result<()> {
+    let nodes = std::env::var("SL_TEST_REDIS_CLUSTER")
+        .unwrap_or_else(|_| "redis://127.0.0.1:7000".to_owned());
+    let backend = RedisBackend::cluster(nodes.split(',').collect()).await?;
+    exercise(&backend).await
+}
+
+#[tokio::test]
+#[ignore = "requires local Redis Cluster"]
+async fn test_cluster_batches() -> Result<()> {
+    let nodes = std::env::var("SL_TEST_REDIS_CLUSTER")
+        .unwrap_or_else(|_| "redis://127.0.0.1:7000".to_owned());
+    let backend = RedisBackend::cluster(nodes.split(',').collect()).await?;
+    let urls = (0..20)
+        .map(|index| format!("http://example.com/{}", index))
+        .collect::<Vec<_>>();
+    let ids = backend
+        .store_many(&urls)
+        .await?
+        .into_iter()
+        .collect::<Result<Vec<_>, _>>()?;
+    let found = backend.retrive_many(&ids).await?;
+    assert!(found
+        .iter()
+        .zip(&urls)
+        .all(|(found, url)| found.as_ref().ok() == Some(url)));
+    assert_eq!(backend.find(&urls[0]).await?, [ids[0]]);
+    assert_eq!(backend.list(Some(ids[0]), 3).await?.len(), 3);
+
+    let link = backend.info(ids[1]).await?;
+    backend
+        .store_alias(&format!("cluster-{}", ids[1]), ids[1])
+        .await?;
+    backend.restore(ids[1], &link, &[], &[]).await?;
+    assert_eq!(backend.retrive(ids[1]).await?, urls[1]);
+
+    assert!(backend.delete_many(&ids).await?.iter().all(Result::is_ok));
+    assert!(backend.purge(chrono::Utc::now()).await? >= ids.len() as u64);
+    assert!(matches!(
+        backend.retrive(ids[1]).await,
+        Err(BackendError::NotFound)
+    ));
+    assert!(backend
+        .resolve_alias(&format!("cluster-{}", ids[1]))
+        .await
+        .is_err());
+    Ok(())
+}
+
+#[tokio::test]
+#[ignore = "requires local Redis"]
+async fn test_keyspace_migration() -> Result<()> {
+    let legacy = RedisBackend::new("redis://localhost:6379/7").await?;
+    let id = legacy.store("http://example.com").await?;
+    legacy.retrive(id).await?;
+    // Keys of other applications sharing the database stay where they are
+    let mut con = redis::Client::open("redis://localhost:6379/7")?
+        .get_multiplexed_async_connection()
+        .await?;
+    redis::cmd("MSET")
+        .arg("0042")
+        .arg("foreign")
+        .arg("sessions")
+        .arg("foreign")
+        .query_async::<_, ()>(&mut con)
+        .await?;
+
+    let backend = RedisBackend::new("redis://localhost:6379/7")
+        .await?
+        .with_prefix("shortland:");
+    assert!(backend.migrate_keyspace(true).await? >= 2);
+    assert_eq!(legacy.retrive(id).await?, "http://example.com");
+    assert!(backend.migrate_keyspace(false).await? >= 2);
+    assert!(matches!(
+        legacy.retrive(id).await,
+        Err(BackendError::NotFound)
+    ));
+    assert_eq!(backend.retrive(id).await?, "http://example.com");
+    assert_eq!(backend.stat(id, None).await?, 2);
+    // Ids were indexed before the links were moved
+    assert_eq!(backend.list(Some(id - 1), 1).await?[0].0, id);
+    assert!(backend.store("http://example.org").await? > id);
+    let foreign: Vec<String> = redis::cmd("MGET")
+        .arg("0042")
+        .arg("sessions")
+        .query_async(&mut con)
+        .await?;
+    assert_eq!(foreign, ["foreign", "foreign"]);
+    Ok(())
+}
+
+#[tokio::test]
+#[ignore = "requires local Redis"]
+async fn test_keyspace_migration_without_prefix() -> Result<()> {
+    let backend = RedisBackend::new("redis://localhost:6379/8").await?;
+    let id = backend.store("http://example.com").await?;
+    // Stat keys were written without the hash tag before namespaces existed
+    let mut con = redis::Client::open("redis://localhost:6379/8")?
+        .get_multiplexed_async_connection()
+        .await?;
+    redis::cmd("INCRBY")
+        .arg(format!(
+            "stat:{}:{}",
+            id,
+            chrono::Utc::now().format("%Y%m%d")
+        ))
+        .arg(3)
+        .query_async::<_, ()>(&mut con)
+        .await?;
+
+    assert!(backend.migrate_keyspace(false).await? >= 1);
+    assert_eq!(backend.stat(id, None).await?, 3);
+    assert_eq!(backend.migrate_keyspace(false).await?, 0);
+    Ok(())
+}
+
+#[tokio::test]
+#[ignore = "requires local Redis"]
+async fn test_replica_lookup() -> Result<()> {
+    let backend = RedisBackend::new("redis://localhost:6379/2")
+        .await?
+        .with_replicas(vec!["redis://localhost:6379/2", "redis://localhost:1/2"]);
+    let id = backend.store("http://example.com").await?;
+    // The unreachable replica never connects, lookups skip it
+    for _ in 0..4 {
+        assert_eq!(backend.retrive(id).await?, "http://example.com");
+    }
+    for _ in 0..50 {
+        if backend.stat(id, None).await? == 4 {
+            return Ok(());
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    }
+    panic!("Clicks of replica lookups are not recorded");
+}
+
+#[tokio::test]
+#[ignore = "requires local Redis"]
+async fn test_shard_list_is_fixed() -> Result<()> {
+    let prefix = format!("shards-{}:", std::process::id());
+    let shards = vec!["redis://localhost:6379/3", "redis://localhost:6379/4"];
+    ShardedRedisBackend::new(shards.clone())
+        .await?
+        .with_prefix(&prefix)
+        .check_shards()
+        .await?;
+    let mut appended = shards.clone();
+    appended.push("redis://localhost:6379/5");
+    let backend = ShardedRedisBackend::new(appended)
+        .await?
+        .with_prefix(&prefix);
+    assert!(backend.check_shards().await.is_err());
+    let reordered = shards.iter().rev().copied().collect();
+    let backend = ShardedRedisBackend::new(reordered)
+        .await?
+        .with_prefix(&prefix);
+    assert!(backend.check_shards().await.is_err());
+    let replaced = vec![shards[0], "redis://localhost:6379/5"];
+    let backend = ShardedRedisBackend::new(replaced)
+        .await?
+        .with_prefix(&prefix);
+    assert!(backend.check_shards().await.is_err());
+    ShardedRedisBackend::new(shards)
+        .await?
+        .with_prefix(&prefix)
+        .check_shards()
+        .await?;
+    Ok(())
+}
@@ -0,0 +1,275 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use shortland::{
+    backend::{memory::InMemoryBackend, BackendError},
+    service::BoxedBackend,
+    settings::{self, HashIdsShortener as Shortener, RandomShortener},
+    shortener::{
+        validate_rotation, Base58, Base62, HashIds, RandomCodes, Rotation, Shortner, ShortnerError,
+    },
+};
+
+#[tokio::test]
+async fn test_hashids_round_trip() -> Result<()> {
+    let shortner = HashIds::new(&Shortener::default())?;
+    for id in [0, 1, 42, i64::MAX as u64] {
+        let code = shortner.encode(id).await?;
+        assert_eq!(shortner.decode(&code).await?, id);
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_hashids_salt() -> Result<()> {
+    let default = HashIds::new(&Shortener::default())?;
+    let salted = HashIds::new(&Shortener {
+        salt: Some("pepper".to_owned()),
+        ..Default::default()
+    })?;
+    let code = salted.encode(42).await?;
+    assert_ne!(code, default.encode(42).await?);
+    assert_eq!(salted.decode(&code).await?, 42);
+    assert!(default.decode(&code).await.map_or(true, |id| id != 42));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_hashids_alphabet_and_length() -> Result<()> {
+    let shortner = HashIds::new(&Shortener {
+        alphabet: Some("abcdefghijklmnop".to_owned()),
+        separators: Some("ab".to_owned()),
+        min_length: 12,
+        ..Default::default()
+    })?;
+    let code = shortner.encode(7).await?;
+    assert!(code.len() >= 12);
+    assert!(code.chars().all(|c| ('a'..='p').contains(&c)));
+    assert_eq!(shortner.decode(&code).await?, 7);
+    Ok(())
+}
+
+#[test]
+fn test_hashids_validation() {
+    let invalid = [
+        Shortener {
+            alphabet: Some("abcdef".to_owned()),
+            ..Default::default()
+        },
+        Shortener {
+            alphabet: Some("abcdefghijklmnop/".to_owned()),
+            ..Default::default()
+        },
+        Shortener {
+            separators: Some("!".to_owned()),
+            ..Default::default()
+        },
+    ];
+    for config in invalid {
+        assert!(matches!(
+            HashIds::new(&config),
+            Err(ShortnerError::Initialization(_))
+        ));
+    }
+}
+
+#[tokio::test]
+async fn test_random_codes() -> Result<()> {
+    let backend: Arc<BoxedBackend> = Arc::new(InMemoryBackend::new());
+    let shortner = RandomCodes::new(
+        backend.clone(),
+        &RandomShortener {
+            length: 6,
+            alphabet: Some("abcdefghijkmnpqrstuvwxyz23456789".to_owned()),
+        },
+    )?;
+    let first = backend.store("http://example.com").await?;
+    let second = backend.store("http://example.org").await?;
+    assert!(matches!(
+        shortner.encode(first).await,
+        Err(ShortnerError::Unassigned(_))
+    ));
+    let code = shortner.assign(first).await?;
+    assert_eq!(code.len(), 6);
+    assert!(code
+        .chars()
+        .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit()));
+    assert_eq!(shortner.encode(first).await?, code);
+    assert_eq!(shortner.assign(first).await?, code);
+    assert_ne!(shortner.assign(second).await?, code);
+    assert_eq!(shortner.decode(&code).await?, first);
+    assert_eq!(backend.resolve_code(&code).await?, first);
+
+    assert!(matches!(
+        shortner.decode("aaaaaa").await,
+        Err(ShortnerError::Backend(BackendError::NotFound))
+    ));
+    assert!(matches!(
+        shortner.decode("aaaaaaa").await,
+        Err(ShortnerError::Malformed)
+    ));
+    assert!(matches!(
+        shortner.decode("AAAAAA").await,
+        Err(ShortnerError::Malformed)
+    ));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_random_codes_collisions() -> Result<()> {
+    let backend: Arc<BoxedBackend> = Arc::new(InMemoryBackend::new());
+    let shortner = RandomCodes::new(
+        backend.clone(),
+        &RandomShortener {
+            length: 1,
+            alphabet: Some("abcdefghijklmnop".to_owned()),
+        },
+    )?;
+    let mut codes = Vec::new();
+    for _ in 0..16 {
+        let id = backend.store("http://example.com").await?;
+        match shortner.assign(id).await {
+            Ok(code) => codes.push(code),
+            Err(ShortnerError::Exhausted(_)) => {}
+            Err(error) => return Err(error.into()),
+        }
+    }
+    let total = codes.len();
+    codes.sort();
+    codes.dedup();
+    assert_eq!(codes.len(), total);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_random_codes_assign_missing() -> Result<()> {
+    let backend: Arc<BoxedBackend> = Arc::new(InMemoryBackend::new());
+    let shortner = RandomCodes::new(backend.clone(), &RandomShortener::default())?;
+    let assigned = backend.store("http://example.com").await?;
+    let code = shortner.assign(assigned).await?;
+    let missing = backend.store("http://example.org").await?;
+    let deleted = backend.store("http://example.net").await?;
+    backend.delete(deleted).await?;
+
+    assert_eq!(shortner.assign_missing().await?, 2);
+    assert_eq!(shortner.encode(assigned).await?, code);
+    assert_eq!(
+        shortner.decode(&shortner.encode(missing).await?).await?,
+        missing
+    );
+    assert!(shortner.encode(deleted).await.is_ok());
+    assert_eq!(shortner.assign_missing().await?, 0);
+    Ok(())
+}
+
+#[test]
+fn test_random_codes_validation() {
+    let backend: Arc<BoxedBackend> = Arc::new(InMemoryBackend::new());
+    let invalid = [
+        RandomShortener {
+            length: 0,
+            ..Default::default()
+        },
+        RandomShortener {
+            alphabet: Some("aabbccddeeffgghhiijjkkll".to_owned()),
+            ..Default::default()
+        },
+        RandomShortener {
+            alphabet: Some("abcdefghijklmnop/".to_owned()),
+            ..Default::default()
+        },
+    ];
+    for config in invalid {
+        assert!(RandomCodes::new(backend.clone(), &config).is_err());
+    }
+}
+
+#[tokio::test]
+async fn test_base62() -> Result<()> {
+    let shortner = Base62;
+    for (id, code) in [
+        (0, "0"),
+        (61, "z"),
+        (62, "10"),
+        (3843, "zz"),
+        (u64::MAX, "LygHa16AHYF"),
+    ] {
+        assert_eq!(shortner.encode(id).await?, code);
+        assert_eq!(shortner.decode(code).await?, id);
+    }
+    for code in ["", "01", "a-b", "LygHa16AHYG"] {
+        assert!(matches!(
+            shortner.decode(code).await,
+            Err(ShortnerError::Malformed)
+        ));
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_base58() -> Result<()> {
+    let shortner = Base58;
+    for (id, code) in [(0, "1"), (57, "z"), (58, "21"), (u64::MAX, "jpXCZedGfVQ")] {
+        assert_eq!(shortner.encode(id).await?, code);
+        assert_eq!(shortner.decode(code).await?, id);
+    }
+    for code in ["", "12", "0", "O", "Il"] {
+        assert!(matches!(
+            shortner.decode(code).await,
+            Err(ShortnerError::Malformed)
+        ));
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rotation() -> Result<()> {
+    let salted = |salt: &str| {
+        HashIds::new(&Shortener {
+            salt: Some(salt.to_owned()),
+            ..Default::default()
+        })
+    };
+    let rotation = Rotation::new(
+        Box::new(salted("current")?),
+        vec![Box::new(salted("leaked")?), Box::new(Base58)],
+    );
+    let code = rotation.encode(42).await?;
+    assert_eq!(code, salted("current")?.encode(42).await?);
+    assert_eq!(rotation.decode_generation(&code).await?, (42, 0));
+
+    let leaked = salted("leaked")?.encode(42).await?;
+    assert_eq!(rotation.decode_generation(&leaked).await?, (42, 1));
+    assert_eq!(rotation.decode(&leaked).await?, 42);
+    assert_eq!(rotation.decode_generation("K").await?, (18, 2));
+    assert!(matches!(
+        rotation.decode("0").await,
+        Err(ShortnerError::Decode(_))
+    ));
+    Ok(())
+}
+
+#[test]
+fn test_validate_rotation() {
+    let salted = |salt: &str| {
+        settings::Shortener::HashIds(Shortener {
+            salt: Some(salt.to_owned()),
+            ..Default::default()
+        })
+    };
+    assert!(validate_rotation(&salted("current"), &[salted("leaked")]).is_ok());
+    for retired in [
+        salted("current"),
+        settings::Shortener::Base62,
+        settings::Shortener::Random(RandomShortener::default()),
+    ] {
+        assert!(matches!(
+            validate_rotation(&salted("current"), &[salted("leaked"), retired]),
+            Err(ShortnerError::Rotation(_))
+        ));
+    }
+    assert!(matches!(
+        validate_rotation(&settings::Shortener::Base62, &[settings::Shortener::Base58]),
+        Err(ShortnerError::Rotation(_))
+    ));
+}